@@ -1,11 +1,12 @@
 import socket
-from typing import Any, Final, Optional, Tuple, Union
+from typing import Any, Callable, Dict, Final, List, Optional, Sequence, Tuple, Union
 
 RESPONSE_VALUE: int  # 1 - VALUE (VA)
 RESPONSE_SUCCESS: int  # 2 - SUCCESS (OK or HD)
 RESPONSE_NOT_STORED: int  # 3 - NOT_STORED (NS)
 RESPONSE_CONFLICT: int  # 4 - CONFLICT (EX)
 RESPONSE_MISS: int  # 5 - MISS (EN or NF)
+RESPONSE_DEBUG: int  # 6 - DEBUG (ME)
 RESPONSE_NOOP: int  # 100 - NOOP (MN)
 
 # Set modes
@@ -25,6 +26,72 @@ MA_MODE_INC: int  # 43 ('+')
 # - "decrement"
 MA_MODE_DEC: int  # 45 ('-')
 
+# Value codecs (low byte of client_flag, see meta_set_object)
+CODEC_MASK: int  # 0xff
+# str, UTF-8 encoded
+CODEC_STR: int  # 0
+# pickle.dumps(), the fallback for other types
+CODEC_PICKLE: int  # 1
+# int, as decimal text
+CODEC_INT: int  # 2
+# bytes, stored as-is
+CODEC_BINARY: int  # 16
+# json.dumps()
+CODEC_JSON: int  # 32
+
+# Compression algorithms (see set_compression), also the client_flag bit
+# marking values compressed with each
+COMPRESSION_ZSTD: int  # 0x100
+COMPRESSION_LZ4: int  # 0x200
+COMPRESSION_MASK: int  # 0x300
+
+# Encryption algorithms (see set_encryption), also the client_flag bit
+# marking values encrypted with each. The key id is stored in
+# ENCRYPTION_KEY_ID_MASK.
+ENCRYPTION_AES_GCM: int  # 0x400
+ENCRYPTION_CHACHA20_POLY1305: int  # 0x800
+ENCRYPTION_MASK: int  # 0xc00
+ENCRYPTION_KEY_ID_MASK: int  # 0xff0000
+ENCRYPTION_KEY_ID_SHIFT: int  # 16
+
+# Checksum algorithms (see set_checksum), also the client_flag bit marking
+# values stored with a checksum trailer
+CHECKSUM_CRC32C: int  # 0x1000
+CHECKSUM_XXH3: int  # 0x2000
+CHECKSUM_MASK: int  # 0x3000
+
+# client_flag bit marking a chunk manifest (see set_chunking)
+CHUNKED: int  # 0x4000
+DEFAULT_CHUNK_SIZE: int  # 512 KiB
+
+# Default and longest time a value is kept in a NearCache, in seconds
+DEFAULT_NEAR_CACHE_TTL: int  # 10
+
+# Default seconds between reloads of a versioned namespace's generation
+DEFAULT_NAMESPACE_REFRESH_INTERVAL: int  # 5
+
+# Hash algorithms for long keys (see KeyPolicy)
+KEY_HASH_BLAKE2B: int  # 1
+KEY_HASH_MD5: int  # 2
+KEY_HASH_SHA1: int  # 3
+KEY_HASH_XXH3: int  # 4
+
+# Default number of hashed keys a KeyRecorder remembers
+DEFAULT_KEY_RECORDER_SIZE: int  # 10000
+
+# Default seconds between polls of a ClusterDiscovery
+DEFAULT_DISCOVERY_POLL_INTERVAL: int  # 60
+
+# Lease statuses (see Lease)
+# Fresh value, or stale while another client recomputes it
+LEASE_HIT: int  # 1
+# Serve the value, this client won the recompute
+LEASE_HIT_STALE_MUST_RECOMPUTE: int  # 2
+# No value, this client won the compute
+LEASE_MISS_MUST_COMPUTE: int  # 3
+# No value, another client is computing it
+LEASE_MISS_WAIT_FOR_OTHERS: int  # 4
+
 # Server versions
 SERVER_VERSION_AWS_1_6_6: int  # 1
 SERVER_VERSION_STABLE: int  # 2
@@ -44,7 +111,11 @@ class RequestFlags:
     * return_fetched: Set to True if the server should return the fetched flag
     * return_key: Set to True if the server should return the key in the response
     * no_update_lru: Set to True if the server should not update the LRU on this access
-    * mark_stale: Set to True if the server should mark the value as stale
+    * mark_stale: Set to True if the server should mark the value as stale.
+        On a set with cas_token, a CAS older than the item's marks the stored
+        value as stale instead of failing (invalidate-if-CAS-older)
+    * remove_value: On delete, remove the value but keep the item (and its
+        metadata) in the cache
     * cache_ttl: The TTL to set on the key
     * recache_ttl: The TTL to use for recache policy
     * vivify_on_miss_ttl: The TTL to use when vivifying a value on a miss
@@ -52,8 +123,12 @@ class RequestFlags:
     * ma_initial_value: For arithmetic operations, the initial value to use (if the key does not exist)
     * ma_delta_value: For arithmetic operations, the delta value to use
     * cas_token: The CAS token to use when storing the value in the cache
-    * opaque: The opaque flag (will be echoed back in the response)
-    * mode: The mode to use when storing the value in the cache. See SET_MODE_* and MA_MODE_* constants
+    * explicit_cas: Use this CAS value for the stored item instead of letting
+        the server generate one (useful to keep CAS values in sync across servers)
+    * opaque: The opaque flag (will be echoed back in the response). Must be
+        printable ASCII without whitespace, at most 32 bytes
+    * mode: The mode to use when storing the value in the cache. One of the
+        SET_MODE_* and MA_MODE_* constants
     """
 
     no_reply: Final[bool]
@@ -67,6 +142,7 @@ class RequestFlags:
     return_key: Final[bool]
     no_update_lru: Final[bool]
     mark_stale: Final[bool]
+    remove_value: Final[bool]
     cache_ttl: Final[Optional[int]]
     recache_ttl: Final[Optional[int]]
     vivify_on_miss_ttl: Final[Optional[int]]
@@ -74,6 +150,7 @@ class RequestFlags:
     ma_initial_value: Final[Optional[int]]
     ma_delta_value: Final[Optional[int]]
     cas_token: Final[Optional[int]]
+    explicit_cas: Final[Optional[int]]
     opaque: Final[Optional[bytes]]
     mode: Final[Optional[int]]
 
@@ -91,6 +168,7 @@ class RequestFlags:
         return_key: bool = False,
         no_update_lru: bool = False,
         mark_stale: bool = False,
+        remove_value: bool = False,
         cache_ttl: Optional[int] = None,
         recache_ttl: Optional[int] = None,
         vivify_on_miss_ttl: Optional[int] = None,
@@ -98,6 +176,7 @@ class RequestFlags:
         ma_initial_value: Optional[int] = None,
         ma_delta_value: Optional[int] = None,
         cas_token: Optional[int] = None,
+        explicit_cas: Optional[int] = None,
         opaque: Optional[bytes] = None,
         mode: Optional[int] = None,
     ) -> None: ...
@@ -115,6 +194,7 @@ class RequestFlags:
         return_key: Optional[bool] = None,
         no_update_lru: Optional[bool] = None,
         mark_stale: Optional[bool] = None,
+        remove_value: Optional[bool] = None,
         cache_ttl: Optional[int] = None,
         recache_ttl: Optional[int] = None,
         vivify_on_miss_ttl: Optional[int] = None,
@@ -122,6 +202,7 @@ class RequestFlags:
         ma_initial_value: Optional[int] = None,
         ma_delta_value: Optional[int] = None,
         cas_token: Optional[int] = None,
+        explicit_cas: Optional[int] = None,
         opaque: Optional[bytes] = None,
         mode: Optional[int] = None,
     ) -> "RequestFlags": ...
@@ -203,45 +284,78 @@ def parse_header(
     """
     ...
 
+class KeyPolicy:
+    """
+    How keys are encoded for the wire, for build_cmd and
+    MemcacheSocket.set_key_policy
+
+    Keys of at least hash_threshold bytes (251 to hash only keys over
+    memcached's 250-byte limit), or binary keys too long once
+    base64-encoded, are hashed with algorithm (KEY_HASH_*) to digest_size
+    bytes (by default 18 for blake2b, 16 for md5, 20 for sha1, 8 for xxh3;
+    md5 and sha1 digests are truncated, xxh3 takes 8 or 16). The digest is
+    hex-encoded with hex_digest and prefixed with tag. Digests match
+    Python's hashlib (and xxhash) digest() and hexdigest(). With strict,
+    such keys raise ValueError instead. Keys still containing bytes <= ' '
+    or > '~' are sent base64-encoded with the b flag. The default hashes
+    keys of 187 bytes or more to an 18-byte blake2b digest.
+    """
+
+    def __init__(
+        self,
+        hash_threshold: int = 187,
+        algorithm: int = KEY_HASH_BLAKE2B,
+        digest_size: Optional[int] = None,
+        hex_digest: bool = False,
+        strict: bool = False,
+        tag: Optional[Union[str, bytes]] = None,
+    ) -> None: ...
+
 def build_cmd(
     cmd: bytes,
     key: Union[str, bytes],
     size: Optional[int] = None,
     request_flags: Optional[RequestFlags] = None,
     legacy_size_format: bool = False,
+    key_policy: Optional[KeyPolicy] = None,
 ) -> bytes:
     """
     Build a memcache meta-protocol command
 
-    :param cmd: The command to send
+    :param cmd: The command to send (letters, digits or '_' only)
     :param key: The key to use
     :param size: The size of the value (for set commands)
     :param request_flags: The flags to use
     :param legacy_size_format: Whether to use legacy size syntax from 1.6.6
+    :param key_policy: How to hash and encode the key, the default if None
     """
     ...
 
 def build_meta_get(
     key: Union[str, bytes],
     request_flags: Optional[RequestFlags] = None,
+    key_policy: Optional[KeyPolicy] = None,
 ) -> bytes:
     """
     Build a memcache meta-get command
 
     :param key: The key to use
     :param request_flags: The flags to use
+    :param key_policy: How to hash and encode the key, the default if None
     """
     ...
 
 def build_meta_delete(
     key: Union[str, bytes],
     request_flags: Optional[RequestFlags] = None,
+    key_policy: Optional[KeyPolicy] = None,
 ) -> bytes:
     """
     Build a memcache meta-delete command
 
     :param key: The key to use
     :param request_flags: The flags to use
+    :param key_policy: How to hash and encode the key, the default if None
     """
     ...
 
@@ -250,6 +364,7 @@ def build_meta_set(
     size: int,
     request_flags: Optional[RequestFlags] = None,
     legacy_size_format: bool = False,
+    key_policy: Optional[KeyPolicy] = None,
 ) -> bytes:
     """
     Build a memcache meta-set command
@@ -258,21 +373,186 @@ def build_meta_set(
     :param size: The size of the value
     :param request_flags: The flags to use
     :param legacy_size_format: Whether to use legacy size syntax from 1.6.6
+    :param key_policy: How to hash and encode the key, the default if None
     """
     ...
 
 def build_meta_arithmetic(
     key: Union[str, bytes],
     request_flags: Optional[RequestFlags] = None,
+    key_policy: Optional[KeyPolicy] = None,
 ) -> bytes:
     """
     Build a memcache meta-arithmetic command
 
     :param key: The key to use
     :param request_flags: The flags to use
+    :param key_policy: How to hash and encode the key, the default if None
+    """
+    ...
+
+def build_meta_touch(
+    key: Union[str, bytes],
+    ttl: int,
+    request_flags: Optional[RequestFlags] = None,
+    key_policy: Optional[KeyPolicy] = None,
+) -> bytes:
+    """
+    Build a memcache meta-get command that only updates the TTL
+
+    :param key: The key to use
+    :param ttl: The new TTL
+    :param request_flags: The flags to use (return_value is ignored)
+    :param key_policy: How to hash and encode the key, the default if None
+    """
+    ...
+
+def build_meta_debug(
+    key: Union[str, bytes],
+    key_policy: Optional[KeyPolicy] = None,
+) -> bytes:
+    """
+    Build a memcache meta-debug command
+
+    :param key: The key to use
+    :param key_policy: How to hash and encode the key, the default if None
+    """
+    ...
+
+def encode_key(
+    key: Union[str, bytes],
+    key_policy: Optional[KeyPolicy] = None,
+) -> Tuple[bytes, bool]:
+    """
+    The key as sent on the wire, hashed and base64-encoded as needed, and
+    whether it is sent base64-encoded (with the b flag)
+
+    :param key: The key to encode
+    :param key_policy: How to hash and encode the key, the default if None
+    """
+    ...
+
+def decode_wire_key(wire_key: Union[str, bytes], is_binary: bool) -> bytes:
+    """
+    Undo the base64 encoding of a wire key sent with the b flag. Hashed keys
+    decode to their digest; see KeyRecorder to map them back to keys.
+
+    :param wire_key: The key as sent on the wire
+    :param is_binary: Whether it was sent with the b flag
+    """
+    ...
+
+def routing_key(key: Union[str, bytes]) -> bytes:
+    """
+    The part of key to select its server with: the substring between the
+    first "{" and the next "}" if not empty (a Redis-style hash tag), so keys
+    sharing a tag land on the same server; otherwise the whole key. The full
+    key is still what is sent.
     """
     ...
 
+class KeyRecorder:
+    """
+    Remembers the original key (namespace included) of the hashed keys sent
+    through the sockets it is attached to with set_key_recorder, to map
+    hashes seen on the server back to keys. Keeps the most recent
+    max_entries. Thread-safe.
+    """
+
+    def __init__(self, max_entries: int = DEFAULT_KEY_RECORDER_SIZE) -> None: ...
+    def lookup(self, wire_key: Union[str, bytes]) -> Optional[bytes]:
+        """
+        The original key of wire_key, as sent on the wire (base64-encoded for
+        binary digests), or None if not recorded.
+        """
+        ...
+    def clear(self) -> None: ...
+    def __len__(self) -> int: ...
+
+class DebugInfo:
+    """
+    Item state returned by the meta-debug (me) command
+
+    * wire_key: the key as sent on the wire, base64-encoded for binary keys
+      (see decode_wire_key); for hashed keys, the digest of the original key
+    * ttl: time in seconds until the item expires (exp), -1 if it never expires
+    * last_access: time in seconds since last access (la)
+    * cas_token: Compare-And-Swap token (cas)
+    * fetched: whether the item was fetched since being set (fetch)
+    * slab_class: the slab class the item is stored in (cls)
+    * size: total item size in bytes, including overhead (size)
+
+    Any field not returned by the server is None.
+    """
+
+    wire_key: bytes
+    ttl: Optional[int]
+    last_access: Optional[int]
+    cas_token: Optional[int]
+    fetched: Optional[bool]
+    slab_class: Optional[int]
+    size: Optional[int]
+
+    def __init__(
+        self,
+        wire_key: bytes,
+        *,
+        ttl: Optional[int] = None,
+        last_access: Optional[int] = None,
+        cas_token: Optional[int] = None,
+        fetched: Optional[bool] = None,
+        slab_class: Optional[int] = None,
+        size: Optional[int] = None,
+    ) -> None: ...
+    def __repr__(self) -> str: ...
+    @staticmethod
+    def from_debug_header(header: bytes) -> Optional["DebugInfo"]:
+        """Parse a debug (ME) header."""
+        ...
+
+class ClusterNode:
+    """
+    A cache node listed by ElastiCache auto discovery
+
+    * host: DNS name of the node
+    * ip: private IP, None while the node is being provisioned
+    * port: memcached port
+    * address: "ip:port", or "host:port" without an IP
+    """
+
+    host: Final[str]
+    ip: Final[Optional[str]]
+    port: Final[int]
+    address: Final[str]
+
+    def __init__(self, host: str, ip: Optional[str], port: int) -> None: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __repr__(self) -> str: ...
+
+class ClusterConfig:
+    """
+    Cluster configuration returned by config get cluster
+
+    * version: bumped by ElastiCache on every node change
+    * nodes: the cache nodes
+    """
+
+    version: Final[int]
+    nodes: Final[List[ClusterNode]]
+
+    def __init__(self, version: int, nodes: List[ClusterNode]) -> None: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __repr__(self) -> str: ...
+
+class DecryptionError(ValueError):
+    """An encrypted value could not be decrypted."""
+
+class ChecksumError(ValueError):
+    """A value does not match its checksum."""
+
+class ReplicationError(ConnectionError):
+    """A replicated write was not acknowledged by enough replicas."""
+
 class Miss:
     def __init__(self) -> None: ...
     def __repr__(self) -> str: ...
@@ -306,6 +586,70 @@ class Value(Success):
     ) -> None: ...
     def __repr__(self) -> str: ...
 
+class Counter(Success):
+    """
+    Result of a meta arithmetic command with return_value: the counter
+    value after the operation.
+    """
+
+    value: Final[int]
+
+    def __init__(self, value: int, flags: ResponseFlags) -> None: ...
+    def __repr__(self) -> str: ...
+
+class Lease:
+    """
+    Result of MemcacheSocket.meta_get_lease()
+
+    * key: The key the lease was requested for
+    * status: One of the LEASE_* constants
+    * value: The (possibly stale) value, None on a miss
+    * flags: Flags returned by the server, None on a plain miss
+    * must_compute: This client won the lease and must write the value with
+        fill_lease()
+    * has_value: A value is available to serve
+    * cas_token: The CAS token used by fill_lease()
+    """
+
+    key: Final[bytes]
+    status: Final[int]
+    value: Final[Optional[bytes]]
+    flags: Final[Optional[ResponseFlags]]
+    must_compute: Final[bool]
+    has_value: Final[bool]
+    cas_token: Final[Optional[int]]
+
+    def __init__(
+        self,
+        key: bytes,
+        status: int,
+        value: Optional[bytes] = None,
+        flags: Optional[ResponseFlags] = None,
+    ) -> None: ...
+    def __repr__(self) -> str: ...
+
+StatValue = Union[int, float, str]
+
+class ServerCapabilities:
+    """
+    Protocol features supported by the connected server. Requests using an
+    unsupported feature raise ValueError before anything is sent.
+
+    * legacy_size_format: ms takes the size as an S<size> flag (1.6.6 and older)
+    * binary_keys: base64-encoded binary (and hashed) keys, the b flag
+    * explicit_cas: explicit CAS on write, the E flag
+    * remove_value: remove the value but keep the item on delete, the x flag
+    * vivify_on_miss: vivify on miss, the N flag
+    """
+
+    legacy_size_format: bool
+    binary_keys: bool
+    explicit_cas: bool
+    remove_value: bool
+    vivify_on_miss: bool
+
+    def __repr__(self) -> str: ...
+
 class MemcacheSocket:
     """
     A high-performance memcache socket that handles the meta-protocol
@@ -322,10 +666,19 @@ class MemcacheSocket:
     ) -> None: ...
     def __str__(self) -> str: ...
     def get_version(self) -> int: ...
+    def get_capabilities(self) -> ServerCapabilities: ...
+    def detect_version(self) -> int:
+        """
+        Ask the server for its version and select the matching capability
+        profile. Returns the closest SERVER_VERSION_* constant.
+        """
+        ...
     def set_socket(self, conn: socket.socket) -> None: ...
     def close(self) -> None: ...
     def sendall(self, data: bytes, with_noop: bool) -> None: ...
-    def get_response(self) -> Union[Value, Success, Miss, NotStored, Conflict]: ...
+    def get_response(
+        self,
+    ) -> Union[Value, Success, Miss, NotStored, Conflict, DebugInfo]: ...
     # send_meta_* methods (for pipelining — send only, read later with get_response())
     # Mutations automatically inject NOOP when no_reply is set in request_flags.
     def send_meta_get(
@@ -355,7 +708,18 @@ class MemcacheSocket:
         self,
         key: Union[str, bytes],
         request_flags: Optional[RequestFlags] = None,
-    ) -> Union[Value, Success, Miss, NotStored, Conflict]: ...
+        near_cache: bool = False,
+    ) -> Union[Value, Success, Miss, NotStored, Conflict]:
+        """
+        With near_cache, serve the value from the NearCache attached with
+        set_near_cache(), or fetch it (always with its value and TTL) and
+        cache it. Ignored if no NearCache is attached. Misses the NearCache
+        caches (see negative_ttl) are served from it either way. Reads with
+        vivify_on_miss_ttl or recache_ttl always go to the server. With a
+        SingleFlight attached, concurrent identical calls share one request and get the
+        same response object.
+        """
+        ...
     def meta_set(
         self,
         key: Union[str, bytes],
@@ -371,4 +735,568 @@ class MemcacheSocket:
         self,
         key: Union[str, bytes],
         request_flags: Optional[RequestFlags] = None,
-    ) -> Union[Value, Success, Miss, NotStored, Conflict]: ...
+    ) -> Union[Counter, Success, Miss, NotStored, Conflict]:
+        """With return_value, the new value is returned as a Counter."""
+        ...
+    def meta_incr(
+        self,
+        key: Union[str, bytes],
+        delta: int = 1,
+        initial_value: Optional[int] = None,
+        initial_ttl: int = 0,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Counter, Success, Miss]:
+        """
+        Increment a counter by delta. With initial_value, a missing key is
+        created with that value and initial_ttl; otherwise it returns Miss.
+        Returns Success when request_flags has no_reply.
+        """
+        ...
+    def meta_decr(
+        self,
+        key: Union[str, bytes],
+        delta: int = 1,
+        initial_value: Optional[int] = None,
+        initial_ttl: int = 0,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Counter, Success, Miss]:
+        """Decrement a counter by delta (clamped at 0). See meta_incr."""
+        ...
+    def meta_touch(
+        self,
+        key: Union[str, bytes],
+        ttl: int,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> bool:
+        """Update the TTL of a key. Returns True if the key exists."""
+        ...
+    def meta_get_and_touch(
+        self,
+        key: Union[str, bytes],
+        ttl: int,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Value, Miss]:
+        """Fetch a value and update its TTL in one round trip."""
+        ...
+    def meta_get_lease(
+        self,
+        key: Union[str, bytes],
+        lease_ttl: int,
+        recache_ttl: Optional[int] = None,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Lease:
+        """
+        Fetch a value with thundering-herd protection. A miss vivifies a
+        placeholder for lease_ttl seconds; a miss, a TTL below recache_ttl or
+        a stale value hands the (re)compute to a single client.
+        """
+        ...
+    def fill_lease(
+        self,
+        lease: Lease,
+        value: bytes,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Success, NotStored, Conflict]:
+        """
+        Write the value for a won lease, guarded by its CAS. Set the TTL with
+        request_flags.cache_ttl. Raises ValueError if the lease was not won.
+        """
+        ...
+    def cas_update(
+        self,
+        key: Union[str, bytes],
+        func: Callable[[Optional[bytes]], bytes],
+        max_retries: int = 3,
+        request_flags: Optional[RequestFlags] = None,
+        backoff: float = 0.0,
+    ) -> Union[Success, NotStored, Conflict, Miss]:
+        """
+        Read-modify-write a key with optimistic locking. func receives the
+        current value (None on a miss) and returns the new one, which is
+        written guarded by the CAS (or in add mode on a miss). On Conflict,
+        NotStored or Miss (deleted meanwhile) the cycle is retried up to
+        max_retries times, sleeping backoff * 2**attempt seconds (at most 1)
+        in between. request_flags apply to the write, except their mode on a
+        CAS write; no_reply is not supported.
+        """
+        ...
+    def invalidate(
+        self,
+        key: Union[str, bytes],
+        ttl: Optional[int] = None,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Success, Miss]:
+        """Mark a key as stale (md I), optionally setting its remaining TTL."""
+        ...
+    def meta_debug(
+        self,
+        key: Union[str, bytes],
+    ) -> Union[DebugInfo, Miss]: ...
+
+    # Compression
+    def set_compression(
+        self,
+        algorithm: Optional[int],
+        threshold: int = 1024,
+        level: Optional[int] = None,
+        dictionary: Optional[bytes] = None,
+    ) -> None:
+        """
+        Compress values of at least threshold bytes with algorithm
+        (COMPRESSION_ZSTD or COMPRESSION_LZ4) in meta_set/send_meta_set,
+        setting the algorithm's bit in client_flag. Values are only stored
+        compressed if that makes them smaller. Reads request the client_flag
+        and decompress marked values before building the Value. level and
+        dictionary apply to zstd only. Appends and prepends raise ValueError
+        while enabled. Pass None to disable.
+        """
+        ...
+    def set_encryption(
+        self,
+        keys: Optional[Dict[int, bytes]],
+        key_id: Optional[int] = None,
+        algorithm: int = ...,  # ENCRYPTION_AES_GCM
+        miss_on_error: bool = False,
+    ) -> None:
+        """
+        Encrypt values in meta_set/send_meta_set with algorithm
+        (ENCRYPTION_AES_GCM, the default, or ENCRYPTION_CHACHA20_POLY1305) and
+        decrypt them on meta_get and the other reads that know the key. keys
+        maps key ids (0-255, stored in client_flag) to 32-byte keys; writes
+        use key_id, by default the highest id. The item key and client_flag
+        are authenticated. A value that fails to decrypt, or isn't encrypted,
+        raises DecryptionError, or is returned as a Miss with miss_on_error.
+        That includes counters written by meta_incr/meta_decr, which must be
+        read with meta_incr(key, delta=0) instead of meta_get. Appends and prepends
+        raise ValueError while enabled. Pass None to disable.
+        """
+        ...
+    def set_checksum(
+        self,
+        algorithm: Optional[int],
+        miss_on_error: bool = False,
+    ) -> None:
+        """
+        Append a checksum trailer (CHECKSUM_CRC32C, 4 bytes, or CHECKSUM_XXH3,
+        8 bytes) to values in meta_set/send_meta_set, after compression and
+        encryption, setting the algorithm's bit in client_flag. Reads verify
+        and strip it. A mismatch is counted in get_checksum_failures() and
+        raises ChecksumError, or is returned as a Miss with miss_on_error.
+        Appends and prepends raise ValueError while enabled. Pass None to
+        disable.
+        """
+        ...
+    def get_checksum_failures(self) -> int:
+        """Number of values that failed checksum verification."""
+        ...
+
+    def set_chunking(self, chunk_size: Optional[int] = DEFAULT_CHUNK_SIZE) -> None:
+        """
+        Store values larger than chunk_size bytes (after compression and
+        encryption) in meta_set as chunk items plus a manifest at the key,
+        marked with the CHUNKED client_flag bit. Reads fetch the chunks in
+        one round trip; missing or corrupted chunks read as a Miss. Chunks of
+        a value replaced by meta_set or deleted by meta_delete are deleted,
+        except with no_reply or mark_stale, as are those of a value that
+        wasn't stored. Chunked values can't be appended or prepended, written
+        with send_meta_set or read with get_response. Pass None to disable.
+        """
+        ...
+    def set_key_policy(self, key_policy: Optional[KeyPolicy]) -> None:
+        """
+        Encode keys with key_policy (hash threshold and algorithm, strict
+        mode, tag), or the default if None.
+        """
+        ...
+    def set_key_recorder(self, recorder: Optional[KeyRecorder]) -> None:
+        """
+        Attach a KeyRecorder (None detaches it) to record the original key of
+        every hashed key sent through this socket.
+        """
+        ...
+    def set_namespace(
+        self,
+        namespace: Optional[Union[str, bytes]],
+        versioned: bool = False,
+        refresh_interval: int = DEFAULT_NAMESPACE_REFRESH_INTERVAL,
+    ) -> None:
+        """
+        Prepend namespace to every key, before it is hashed or base64-encoded
+        (None removes it). With versioned, the generation kept in the
+        "{namespace}generation" counter is folded in as
+        "{namespace}{generation}:{key}"; it is loaded now (created at the
+        current Unix time if missing) and reloaded by blocking commands every
+        refresh_interval seconds. Pipelined send_* commands never reload it.
+        """
+        ...
+    def bump_namespace(self) -> int:
+        """
+        Increment the versioned namespace's generation, so all its keys read
+        as misses, and return the new generation. Other clients see it when
+        they next reload it. Raises ValueError without a versioned namespace.
+        """
+        ...
+    def get_namespace_generation(self) -> Optional[int]:
+        """Generation of the versioned namespace in use, None without one."""
+        ...
+    def set_single_flight(self, single_flight: Optional[SingleFlight]) -> None:
+        """
+        Attach a SingleFlight (None detaches it), usually shared between the
+        sockets of a pool, to coalesce concurrent identical meta_get calls.
+        """
+        ...
+    def set_near_cache(self, cache: Optional[NearCache]) -> None:
+        """
+        Attach a NearCache (None detaches it). Share one between the sockets
+        of a pool: meta_set, meta_delete and meta_arithmetic of a key through
+        any of them, including the send_* variants, drop it from the cache.
+        """
+        ...
+
+    # Value codecs
+    def register_codec(
+        self,
+        code: int,
+        encode: Callable[[Any], bytes],
+        decode: Callable[[bytes], Any],
+        types: Optional[Union[type, Tuple[type, ...]]] = None,
+    ) -> None:
+        """
+        Register a codec for client_flag codec code (up to 255, not a built-in
+        CODEC_*). meta_set_object() uses it by default for instances of types.
+        """
+        ...
+    def meta_set_object(
+        self,
+        key: Union[str, bytes],
+        obj: Any,
+        request_flags: Optional[RequestFlags] = None,
+        codec: Optional[int] = None,
+    ) -> Union[Success, NotStored, Conflict, Miss]:
+        """
+        Encode obj and store it with the codec code in the low byte of
+        client_flag (other request_flags.client_flag bits are kept). Uses
+        codec, or picks one from the type: registered types, bytes, str, int,
+        then pickle.
+        """
+        ...
+    def meta_get_object(
+        self,
+        key: Union[str, bytes],
+        request_flags: Optional[RequestFlags] = None,
+        near_cache: bool = False,
+    ) -> Union[Value, Miss]:
+        """
+        Fetch a value and decode it with the codec in its client_flag.
+        Value.value holds the decoded object. Raises ValueError for an
+        unregistered codec code.
+        """
+        ...
+
+    # Batched (pipelined in quiet mode, a single round trip)
+    def meta_touch_many(
+        self,
+        keys: Sequence[Union[str, bytes]],
+        ttl: int,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> List[bool]:
+        """Touch many keys. Returns one bool per key, True if it exists."""
+        ...
+    def meta_get_and_touch_many(
+        self,
+        keys: Sequence[Union[str, bytes]],
+        ttl: int,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> List[Union[Value, Miss]]:
+        """Fetch and touch many keys. Returns one Value or Miss per key."""
+        ...
+
+    # Admin commands
+    def version(self) -> str:
+        """Return the server version string (e.g. "1.6.21")."""
+        ...
+    def stats(
+        self,
+        group: Optional[str] = None,
+    ) -> Dict[Union[str, int], Union[StatValue, Dict[str, StatValue]]]:
+        """
+        Return server statistics. Values are converted to int or float when
+        possible.
+
+        :param group: None for general stats, or one of "slabs", "items",
+            "settings", "conns". Per-id stats (e.g. "1:chunk_size",
+            "items:1:number") are nested under their integer id.
+        """
+        ...
+    def flush_all(
+        self,
+        delay: Optional[int] = None,
+        no_reply: bool = False,
+    ) -> Union[Success, Miss, NotStored, Conflict]:
+        """Invalidate all items, and clear the attached NearCache, if any."""
+        ...
+    def verbosity(
+        self,
+        level: int,
+        no_reply: bool = False,
+    ) -> Union[Success, Miss, NotStored, Conflict]: ...
+    def config_get_cluster(self) -> "ClusterConfig":
+        """
+        Return the cluster configuration of an ElastiCache configuration
+        endpoint (config get cluster). Raises ConnectionError on other
+        servers, which answer ERROR.
+        """
+        ...
+    def quit(self) -> None:
+        """Ask the server to close the connection, then close the socket."""
+        ...
+
+class NearCache:
+    """
+    In-process LRU cache of hot values in front of one or more
+    MemcacheSocket, read with meta_get(near_cache=True)
+
+    Holds up to max_bytes of keys and values, each for at most max_ttl
+    seconds, or until the item expires on the server if sooner. Stale items
+    and lease responses are not cached. Thread-safe.
+
+    With negative_ttl, misses of all keys, or of keys starting with one of
+    negative_prefixes, are cached for negative_ttl seconds and served by
+    every meta_get of the key, near_cache or not.
+    """
+
+    def __init__(
+        self,
+        max_bytes: int,
+        max_ttl: int = 10,
+        negative_ttl: Optional[int] = None,
+        negative_prefixes: Optional[list[Union[str, bytes]]] = None,
+    ) -> None: ...
+    def invalidate(self, key: Union[str, bytes]) -> None:
+        """Drop a key from the cache."""
+        ...
+    def clear(self) -> None:
+        """Drop all entries, keeping the stats."""
+        ...
+    def get_stats(self) -> dict[str, int]:
+        """
+        Counters: hits, negative_hits (cached misses served), misses, and the
+        current entries and bytes.
+        """
+        ...
+    def __len__(self) -> int: ...
+
+class SingleFlight:
+    """
+    Coalesces concurrent meta_get calls with the same key and request flags
+    on the sockets it is attached to: the first sends the request, the rest
+    wait for it and get the same response object, or exception. Thread-safe.
+    """
+
+    def __init__(self) -> None: ...
+    def get_stats(self) -> dict[str, int]:
+        """Counters: requests sent and coalesced calls that waited for one."""
+        ...
+    def __len__(self) -> int:
+        """Number of requests in flight."""
+        ...
+
+class MemcacheLock:
+    """
+    Short-lived distributed lock stored in memcache
+
+    Acquiring adds the key (set in add mode) with a random owner token and a
+    TTL, so the lock expires if its owner dies. Release and extend are guarded
+    by the CAS returned on acquire, so they never touch a lock that expired
+    and was taken by someone else.
+
+    * ttl: Seconds until the lock expires unless extended
+    * locked: True while this instance holds the lock
+    * token: Owner token stored by the last acquire
+    """
+
+    ttl: Final[int]
+    locked: Final[bool]
+    token: Final[Optional[bytes]]
+
+    def __init__(
+        self,
+        socket: MemcacheSocket,
+        key: Union[str, bytes],
+        ttl: int = 30,
+        poll_interval: float = 0.05,
+    ) -> None: ...
+    def acquire(self, blocking: bool = True, timeout: Optional[float] = None) -> bool:
+        """
+        Take the lock. When blocking, poll every poll_interval seconds (with
+        jitter; it must be positive) until acquired or timeout seconds have passed (forever if None).
+        Raises RuntimeError if this instance already holds the lock.
+        """
+        ...
+    def release(self) -> bool:
+        """
+        Release the lock. Returns False if it was no longer ours. If the
+        delete raises, the lock is still held and release() can be retried.
+        """
+        ...
+    def extend(self, ttl: Optional[int] = None) -> bool:
+        """Reset the TTL of the held lock. Returns False if it was lost."""
+        ...
+    def __enter__(self) -> "MemcacheLock": ...
+    def __exit__(self, *args: Any) -> bool: ...
+    def __repr__(self) -> str: ...
+
+class ReplicaSet:
+    """
+    Keeps copies of keys on several sockets, the first being the primary.
+    Writes are pipelined to all of them (sent one after the other when
+    chunks are involved) and succeed once write_quorum (default: a
+    majority) acknowledge; reads try the replicas in order until
+    one hits. With quiet_replicas, replicas other than the primary are
+    written in quiet mode and only the primary acknowledges. With
+    read_repair, a hit is copied to the replicas that missed it. Not
+    thread-safe, like the sockets.
+    """
+
+    def __init__(
+        self,
+        sockets: List[MemcacheSocket],
+        write_quorum: Optional[int] = None,
+        quiet_replicas: bool = False,
+        read_repair: bool = False,
+    ) -> None: ...
+    def meta_set(
+        self,
+        key: Union[str, bytes],
+        value: bytes,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Success, NotStored, Conflict, Miss]:
+        """
+        Set on every replica. Returns the first Success if write_quorum
+        replicas stored it. Otherwise raises ReplicationError if a replica
+        failed, or returns the first other response (e.g. NotStored).
+        no_reply is not supported.
+        """
+        ...
+    def meta_delete(
+        self,
+        key: Union[str, bytes],
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Success, NotStored, Conflict, Miss]:
+        """Delete on every replica, as meta_set. Miss counts as deleted."""
+        ...
+    def meta_get(
+        self,
+        key: Union[str, bytes],
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Value, Success, NotStored, Conflict, Miss]:
+        """
+        Get from the first replica that hits, falling back on miss or error.
+        Raises the last error only if every replica failed.
+        """
+        ...
+    def get_stats(self) -> Dict[str, int]:
+        """write_failures, read_fallbacks and read_repairs counters."""
+        ...
+    def __len__(self) -> int: ...
+
+class MigrationClient:
+    """
+    Wraps the sockets to an old and a new cluster while migrating. Writes go
+    to both, the primary first; reads come from the primary, optionally
+    shadowed by a read from the secondary to compare the values. old is the
+    primary until flip(). Secondary and shadow failures are logged and
+    counted, never raised; a shadow read failing with a connection error or
+    timeout closes the secondary, which must then be reconnected with
+    set_socket(). Not thread-safe, like the sockets.
+
+    * flipped: True once new is the primary
+    * primary / secondary: the sockets in each role
+    """
+
+    flipped: Final[bool]
+    primary: Final[MemcacheSocket]
+    secondary: Final[MemcacheSocket]
+
+    def __init__(
+        self, old: MemcacheSocket, new: MemcacheSocket, shadow_reads: bool = False
+    ) -> None: ...
+    def flip(self) -> bool:
+        """Swap the primary and the secondary. Returns the new flipped."""
+        ...
+    def set_shadow_reads(self, enabled: bool) -> None: ...
+    def meta_set(
+        self,
+        key: Union[str, bytes],
+        value: bytes,
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Success, NotStored, Conflict, Miss]:
+        """Set on both, returning the primary's response."""
+        ...
+    def meta_delete(
+        self,
+        key: Union[str, bytes],
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Success, NotStored, Conflict, Miss]:
+        """Delete from both, returning the primary's response."""
+        ...
+    def meta_get(
+        self,
+        key: Union[str, bytes],
+        request_flags: Optional[RequestFlags] = None,
+    ) -> Union[Value, Success, NotStored, Conflict, Miss]:
+        """
+        Get from the primary. With shadow reads, the secondary's response to
+        the same get is read on the next call, compared with it and dropped.
+        """
+        ...
+    def flush_shadow_read(self) -> None:
+        """
+        Read the response to the last shadow get, if pending, e.g. before
+        using the secondary directly or reading the stats.
+        """
+        ...
+    def get_stats(self) -> Dict[str, int]:
+        """
+        reads, shadow_reads (compared), shadow_misses, mismatches,
+        shadow_errors and secondary_write_errors counters. The last shadow
+        read only counts once flushed.
+        """
+        ...
+
+class ClusterDiscovery:
+    """
+    Polls an ElastiCache configuration endpoint and calls on_change with the
+    new ClusterConfig whenever its version changes, e.g. to rebuild the
+    server ring. Poll on demand with poll(), or every poll_interval seconds
+    (which must be positive) on a background thread between start() and
+    stop(). The thread doesn't keep the ClusterDiscovery alive, and stops
+    once it is collected.
+
+    * config: the last configuration passed to on_change, if any
+    """
+
+    config: Final[Optional[ClusterConfig]]
+
+    def __init__(
+        self,
+        socket: MemcacheSocket,
+        on_change: Callable[[ClusterConfig], Any],
+        poll_interval: float = DEFAULT_DISCOVERY_POLL_INTERVAL,
+    ) -> None: ...
+    def poll(self) -> bool:
+        """
+        Fetch the configuration and call on_change if its version changed.
+        Returns whether it did. If on_change raises, the exception propagates
+        and the configuration is offered again on the next poll.
+        """
+        ...
+    def start(self) -> None:
+        """
+        Poll now and every poll_interval on a background thread. Errors are
+        logged. Raises RuntimeError if already started.
+        """
+        ...
+    def stop(self) -> None:
+        """Stop the background thread, waiting for a poll in progress."""
+        ...
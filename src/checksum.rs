@@ -0,0 +1,86 @@
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::constants::*;
+
+create_exception!(
+    meta_memcache_socket,
+    ChecksumError,
+    PyValueError,
+    "A value does not match its checksum."
+);
+
+/// Value checksum settings. A checksum of the stored bytes is appended as a
+/// trailer, marked with the algorithm's CHECKSUM_* bit in client_flag, and
+/// verified and stripped on read.
+pub struct Checksum {
+    algorithm: u32,
+    miss_on_error: bool,
+}
+
+fn trailer_len(algorithm: u32) -> Option<usize> {
+    match algorithm {
+        CHECKSUM_CRC32C => Some(4),
+        CHECKSUM_XXH3 => Some(8),
+        _ => None,
+    }
+}
+
+fn compute(algorithm: u32, data: &[u8]) -> Vec<u8> {
+    match algorithm {
+        CHECKSUM_CRC32C => crc32c::crc32c(data).to_le_bytes().to_vec(),
+        _ => xxh3_64(data).to_le_bytes().to_vec(),
+    }
+}
+
+impl Checksum {
+    pub fn new(algorithm: u32, miss_on_error: bool) -> Result<Self, String> {
+        if trailer_len(algorithm).is_none() {
+            return Err(format!("Unknown checksum algorithm {algorithm}"));
+        }
+        Ok(Checksum {
+            algorithm,
+            miss_on_error,
+        })
+    }
+
+    /// client_flag bit set on values stored with these settings.
+    pub fn flag(&self) -> u32 {
+        self.algorithm
+    }
+
+    /// Whether a value that fails verification is returned as a Miss
+    /// instead of raising ChecksumError.
+    pub fn miss_on_error(&self) -> bool {
+        self.miss_on_error
+    }
+
+    /// Bytes append() adds to a value.
+    pub fn trailer_len(&self) -> usize {
+        trailer_len(self.algorithm).unwrap_or(0)
+    }
+
+    /// Append the checksum trailer to `value`.
+    pub fn append(&self, value: &mut Vec<u8>) {
+        let trailer = compute(self.algorithm, value);
+        value.extend_from_slice(&trailer);
+    }
+
+    /// Verify the trailer of a value stored with `client_flag`, using the
+    /// algorithm it records, and return the value without it.
+    pub fn verify<'a>(&self, client_flag: u32, data: &'a [u8]) -> Result<&'a [u8], String> {
+        let algorithm = client_flag & CHECKSUM_MASK;
+        let len = trailer_len(algorithm)
+            .ok_or_else(|| format!("Unknown checksum bits in client_flag {client_flag}"))?;
+        let split = data
+            .len()
+            .checked_sub(len)
+            .ok_or("Value is shorter than its checksum")?;
+        let (value, trailer) = data.split_at(split);
+        if compute(algorithm, value) != trailer {
+            return Err("Checksum mismatch".into());
+        }
+        Ok(value)
+    }
+}
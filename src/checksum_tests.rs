@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod tests {
+    use crate::checksum::Checksum;
+    use crate::constants::*;
+
+    #[test]
+    fn test_crc32c_trailer() {
+        let checksum = Checksum::new(CHECKSUM_CRC32C, false).unwrap();
+        let mut value = b"123456789".to_vec();
+        checksum.append(&mut value);
+        // CRC-32C check value
+        assert_eq!(&value[9..], &0xe3069283u32.to_le_bytes());
+        assert_eq!(
+            checksum.verify(CHECKSUM_CRC32C, &value).unwrap(),
+            b"123456789"
+        );
+    }
+
+    #[test]
+    fn test_xxh3_trailer() {
+        let checksum = Checksum::new(CHECKSUM_XXH3, false).unwrap();
+        let mut value = b"value".to_vec();
+        checksum.append(&mut value);
+        assert_eq!(value.len(), 5 + 8);
+        assert_eq!(checksum.verify(CHECKSUM_XXH3, &value).unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_verify_uses_stored_algorithm() {
+        let crc = Checksum::new(CHECKSUM_CRC32C, false).unwrap();
+        let xxh3 = Checksum::new(CHECKSUM_XXH3, false).unwrap();
+        let mut value = b"value".to_vec();
+        crc.append(&mut value);
+        assert_eq!(xxh3.verify(CHECKSUM_CRC32C | 7, &value).unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_empty_value() {
+        let checksum = Checksum::new(CHECKSUM_XXH3, false).unwrap();
+        let mut value = Vec::new();
+        checksum.append(&mut value);
+        assert_eq!(checksum.verify(CHECKSUM_XXH3, &value).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_mismatch_rejected() {
+        let checksum = Checksum::new(CHECKSUM_CRC32C, false).unwrap();
+        let mut value = b"value".to_vec();
+        checksum.append(&mut value);
+        value[0] ^= 1;
+        assert!(checksum.verify(CHECKSUM_CRC32C, &value).is_err());
+        assert!(checksum.verify(CHECKSUM_CRC32C, b"abc").is_err());
+        assert!(checksum.verify(CHECKSUM_MASK, &value).is_err());
+    }
+
+    #[test]
+    fn test_unknown_algorithm() {
+        assert!(Checksum::new(CHECKSUM_MASK, false).is_err());
+        assert!(Checksum::new(0, true).is_err());
+    }
+}
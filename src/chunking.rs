@@ -0,0 +1,129 @@
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Smallest chunk size accepted, to keep the number of chunk items sane.
+pub const MIN_CHUNK_SIZE: usize = 1024;
+
+/// Most chunks a single value can be split into.
+pub const MAX_CHUNKS: u64 = 4096;
+
+/// Longest TTL memcached takes as relative; longer ones are unix times.
+const MAX_RELATIVE_TTL: u32 = 30 * 24 * 3600;
+
+/// How much longer chunks live than their manifest.
+const CHUNK_TTL_MARGIN: u32 = 60;
+
+const MANIFEST_MAGIC: &[u8; 4] = b"MMC1";
+pub const MANIFEST_LEN: usize = 32;
+
+/// Describes a value stored in chunks. It is stored at the value's key,
+/// marked with the CHUNKED client_flag bit, and the chunks under keys
+/// derived from the key and a random `version`, so a rewrite never mixes
+/// chunks with those of a concurrent or previous write.
+///
+/// Layout (32 bytes, little-endian):
+/// magic "MMC1" | version u64 | size u64 | chunk_size u32 | xxh3 u64
+#[derive(Debug, PartialEq)]
+pub struct Manifest {
+    pub version: u64,
+    pub size: u64,
+    pub chunk_size: u32,
+    pub hash: u64,
+}
+
+/// A random chunk version.
+pub fn random_version() -> Result<u64, String> {
+    let mut buf = [0u8; 8];
+    getrandom::getrandom(&mut buf).map_err(|e| format!("Error generating chunk version: {e}"))?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// TTL of the chunks of a value stored with `ttl` (0 for none): a little
+/// longer, as they are written before the manifest and must not expire
+/// before it.
+pub fn chunk_ttl(ttl: u32) -> u32 {
+    match ttl {
+        0 => 0,
+        ttl if ttl <= MAX_RELATIVE_TTL => (ttl + CHUNK_TTL_MARGIN).min(MAX_RELATIVE_TTL),
+        ttl => ttl.saturating_add(CHUNK_TTL_MARGIN),
+    }
+}
+
+/// Check a chunk size for set_chunking().
+pub fn validate_chunk_size(chunk_size: usize) -> Result<(), String> {
+    if chunk_size < MIN_CHUNK_SIZE || u32::try_from(chunk_size).is_err() {
+        return Err(format!(
+            "chunk_size must be between {MIN_CHUNK_SIZE} and {} bytes",
+            u32::MAX
+        ));
+    }
+    Ok(())
+}
+
+impl Manifest {
+    /// Manifest for storing `value` in chunks of `chunk_size` bytes.
+    pub fn new(value: &[u8], chunk_size: usize, version: u64) -> Result<Self, String> {
+        // Values are returned with a u32 size
+        if u32::try_from(value.len()).is_err() {
+            return Err(format!(
+                "Value of {} bytes is too large, at most {} bytes can be stored",
+                value.len(),
+                u32::MAX
+            ));
+        }
+        let manifest = Manifest {
+            version,
+            size: value.len() as u64,
+            chunk_size: u32::try_from(chunk_size).map_err(|_| "chunk_size is too large")?,
+            hash: xxh3_64(value),
+        };
+        if manifest.chunk_count() > MAX_CHUNKS {
+            return Err(format!(
+                "Value of {} bytes needs more than {MAX_CHUNKS} chunks of {chunk_size} bytes",
+                value.len()
+            ));
+        }
+        Ok(manifest)
+    }
+
+    /// Number of chunks the value is split into.
+    pub fn chunk_count(&self) -> u64 {
+        self.size.div_ceil(self.chunk_size as u64)
+    }
+
+    /// Key of chunk `index` of the value stored at `key`.
+    pub fn chunk_key(&self, key: &[u8], index: u64) -> Vec<u8> {
+        let mut chunk_key = key.to_vec();
+        chunk_key.extend_from_slice(format!(":{:016x}:{index}", self.version).as_bytes());
+        chunk_key
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(MANIFEST_LEN);
+        buf.extend_from_slice(MANIFEST_MAGIC);
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(&self.size.to_le_bytes());
+        buf.extend_from_slice(&self.chunk_size.to_le_bytes());
+        buf.extend_from_slice(&self.hash.to_le_bytes());
+        buf
+    }
+
+    /// Parse a stored manifest. None if malformed.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != MANIFEST_LEN || !data.starts_with(MANIFEST_MAGIC) {
+            return None;
+        }
+        let u64_at = |pos: usize| u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
+        let manifest = Manifest {
+            version: u64_at(4),
+            size: u64_at(12),
+            chunk_size: u32::from_le_bytes(data[20..24].try_into().unwrap()),
+            hash: u64_at(24),
+        };
+        (manifest.chunk_size > 0 && manifest.chunk_count() <= MAX_CHUNKS).then_some(manifest)
+    }
+
+    /// Whether `value` reassembled from the chunks is the stored value.
+    pub fn verify(&self, value: &[u8]) -> bool {
+        value.len() as u64 == self.size && xxh3_64(value) == self.hash
+    }
+}
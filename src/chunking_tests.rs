@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use crate::chunking::{
+        MANIFEST_LEN, MAX_CHUNKS, Manifest, chunk_ttl, random_version, validate_chunk_size,
+    };
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let value = vec![7u8; 2500];
+        let manifest = Manifest::new(&value, 1024, 0x1234).unwrap();
+        assert_eq!(manifest.chunk_count(), 3);
+        let encoded = manifest.encode();
+        assert_eq!(encoded.len(), MANIFEST_LEN);
+        assert_eq!(Manifest::decode(&encoded), Some(manifest));
+    }
+
+    #[test]
+    fn test_chunk_count_exact_multiple() {
+        let manifest = Manifest::new(&[0u8; 2048], 1024, 1).unwrap();
+        assert_eq!(manifest.chunk_count(), 2);
+    }
+
+    #[test]
+    fn test_too_many_chunks() {
+        let value = vec![0u8; (MAX_CHUNKS as usize + 1) * 16];
+        assert!(Manifest::new(&value, 16, 1).is_err());
+        assert!(Manifest::new(&value, 32, 1).is_ok());
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed() {
+        let manifest = Manifest::new(&[1u8; 4096], 1024, 1).unwrap();
+        let encoded = manifest.encode();
+        assert_eq!(Manifest::decode(&encoded[..MANIFEST_LEN - 1]), None);
+        assert_eq!(Manifest::decode(b"not a manifest at all, 32 bytes!"), None);
+
+        let mut zero_chunk_size = encoded.clone();
+        zero_chunk_size[20..24].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(Manifest::decode(&zero_chunk_size), None);
+
+        let mut huge = encoded;
+        huge[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(Manifest::decode(&huge), None);
+    }
+
+    #[test]
+    fn test_chunk_key() {
+        let manifest = Manifest::new(&[0u8; 10], 1024, 0xabc).unwrap();
+        assert_eq!(manifest.chunk_key(b"foo", 2), b"foo:0000000000000abc:2");
+    }
+
+    #[test]
+    fn test_verify() {
+        let value = b"chunked value".repeat(200);
+        let manifest = Manifest::new(&value, 1024, 1).unwrap();
+        assert!(manifest.verify(&value));
+        assert!(!manifest.verify(&value[1..]));
+        let mut corrupted = value.clone();
+        corrupted[100] ^= 1;
+        assert!(!manifest.verify(&corrupted));
+    }
+
+    #[test]
+    fn test_validate_chunk_size() {
+        assert!(validate_chunk_size(1024).is_ok());
+        assert!(validate_chunk_size(1023).is_err());
+        assert!(validate_chunk_size(u32::MAX as usize + 1).is_err());
+    }
+
+    #[test]
+    fn test_chunk_ttl() {
+        assert_eq!(chunk_ttl(0), 0);
+        assert_eq!(chunk_ttl(60), 120);
+        assert_eq!(chunk_ttl(30 * 24 * 3600 - 1), 30 * 24 * 3600);
+        assert_eq!(chunk_ttl(1_800_000_000), 1_800_000_060);
+        assert_eq!(chunk_ttl(u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_random_version() {
+        assert_ne!(random_version().unwrap(), random_version().unwrap());
+    }
+}
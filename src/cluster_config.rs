@@ -0,0 +1,134 @@
+use atoi::FromRadix10Checked;
+use pyo3::prelude::*;
+
+#[inline]
+fn parse_number<T: FromRadix10Checked>(value: &[u8]) -> Option<T> {
+    match T::from_radix_10_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
+/// A cache node listed by ElastiCache auto discovery.
+#[pyclass(frozen, eq, skip_from_py_object)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterNode {
+    #[pyo3(get)]
+    pub host: String,
+    /// Private IP, None while the node is still being provisioned.
+    #[pyo3(get)]
+    pub ip: Option<String>,
+    #[pyo3(get)]
+    pub port: u16,
+}
+
+#[pymethods]
+impl ClusterNode {
+    #[new]
+    #[pyo3(signature = (host, ip, port))]
+    fn new(host: String, ip: Option<String>, port: u16) -> Self {
+        ClusterNode { host, ip, port }
+    }
+
+    /// `host:port`, or `ip:port` when the IP is known.
+    #[getter]
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.ip.as_deref().unwrap_or(&self.host), self.port)
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "ClusterNode(host={:?}, ip={:?}, port={})",
+            self.host, self.ip, self.port
+        )
+    }
+}
+
+/// Cluster configuration returned by `config get cluster`: a version,
+/// bumped on every membership change, and the cache nodes.
+#[pyclass(frozen, eq, skip_from_py_object)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterConfig {
+    #[pyo3(get)]
+    pub version: u64,
+    #[pyo3(get)]
+    pub nodes: Vec<ClusterNode>,
+}
+
+#[pymethods]
+impl ClusterConfig {
+    #[new]
+    #[pyo3(signature = (version, nodes))]
+    fn new(version: u64, nodes: Vec<PyRef<'_, ClusterNode>>) -> Self {
+        ClusterConfig {
+            version,
+            nodes: nodes.iter().map(|node| (**node).clone()).collect(),
+        }
+    }
+
+    pub fn __repr__(&self) -> String {
+        let nodes: Vec<String> = self.nodes.iter().map(ClusterNode::__repr__).collect();
+        format!(
+            "ClusterConfig(version={}, nodes=[{}])",
+            self.version,
+            nodes.join(", ")
+        )
+    }
+}
+
+impl ClusterConfig {
+    /// Parse the data block of a `CONFIG cluster` response:
+    /// `<version>\n<host>|<ip>|<port> <host>|<ip>|<port> ...\n`.
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut lines = data
+            .split(|&c| c == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+        let version = lines.next().unwrap_or_default();
+        let version = parse_number(version).ok_or_else(|| {
+            format!(
+                "Invalid cluster config version: {:?}",
+                String::from_utf8_lossy(version)
+            )
+        })?;
+        let nodes = lines
+            .next()
+            .unwrap_or_default()
+            .split(|&c| c == b' ')
+            .filter(|node| !node.is_empty())
+            .map(parse_node)
+            .collect::<Result<_, _>>()?;
+        Ok(ClusterConfig { version, nodes })
+    }
+}
+
+fn parse_node(node: &[u8]) -> Result<ClusterNode, String> {
+    let invalid = || format!("Invalid cluster node: {:?}", String::from_utf8_lossy(node));
+    let mut parts = node.split(|&c| c == b'|');
+    let (Some(host), Some(ip), Some(port), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let port = parse_number(port).ok_or_else(invalid)?;
+    let text = |value: &[u8]| String::from_utf8_lossy(value).into_owned();
+    Ok(ClusterNode {
+        host: text(host),
+        ip: (!ip.is_empty()).then(|| text(ip)),
+        port,
+    })
+}
+
+/// Size of the data block announced by a `CONFIG cluster <flags> <size>`
+/// header. None if the line is not one.
+pub fn parse_config_header(line: &[u8]) -> Option<usize> {
+    let rest = line.strip_prefix(b"CONFIG cluster ")?;
+    let mut parts = rest.split(|&c| c == b' ');
+    let (Some(flags), Some(size), None) = (parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    parse_number::<u32>(flags)?;
+    parse_number(size)
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster_config::{ClusterConfig, ClusterNode, parse_config_header};
+
+    fn node(host: &str, ip: Option<&str>, port: u16) -> ClusterNode {
+        ClusterNode {
+            host: host.to_string(),
+            ip: ip.map(str::to_string),
+            port,
+        }
+    }
+
+    #[test]
+    fn test_parse_config_header() {
+        assert_eq!(parse_config_header(b"CONFIG cluster 0 147"), Some(147));
+        assert_eq!(parse_config_header(b"CONFIG cluster 0"), None);
+        assert_eq!(parse_config_header(b"CONFIG cluster 0 x"), None);
+        assert_eq!(parse_config_header(b"CONFIG cluster 0 1 2"), None);
+        assert_eq!(parse_config_header(b"ERROR"), None);
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let data = b"12\nnode1.cache.amazonaws.com|10.82.235.120|11211 node2.cache.amazonaws.com|10.80.249.27|11212\n";
+        let config = ClusterConfig::parse(data).unwrap();
+        assert_eq!(config.version, 12);
+        assert_eq!(
+            config.nodes,
+            vec![
+                node("node1.cache.amazonaws.com", Some("10.82.235.120"), 11211),
+                node("node2.cache.amazonaws.com", Some("10.80.249.27"), 11212),
+            ]
+        );
+        assert_eq!(config.nodes[0].address(), "10.82.235.120:11211");
+    }
+
+    #[test]
+    fn test_parse_config_without_ip() {
+        let config = ClusterConfig::parse(b"3\r\nnode1||11211\r\n").unwrap();
+        assert_eq!(config.nodes, vec![node("node1", None, 11211)]);
+        assert_eq!(config.nodes[0].address(), "node1:11211");
+    }
+
+    #[test]
+    fn test_parse_config_empty() {
+        let config = ClusterConfig::parse(b"1\n\n").unwrap();
+        assert_eq!(config.version, 1);
+        assert!(config.nodes.is_empty());
+    }
+
+    #[test]
+    fn test_parse_config_invalid() {
+        assert!(ClusterConfig::parse(b"").is_err());
+        assert!(ClusterConfig::parse(b"v1\nnode1||11211\n").is_err());
+        assert!(ClusterConfig::parse(b"1\nnode1|11211\n").is_err());
+        assert!(ClusterConfig::parse(b"1\nnode1||port\n").is_err());
+        assert!(ClusterConfig::parse(b"1\n|10.0.0.1|11211\n").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use atoi::FromRadix10SignedChecked;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::sync::PyOnceLock;
+use pyo3::types::{PyBool, PyBytes, PyInt, PyString};
+
+use crate::constants::*;
+
+/// Codec code stored in a client_flag.
+pub fn codec_of(client_flag: Option<u32>) -> u32 {
+    client_flag.unwrap_or(0) & CODEC_MASK
+}
+
+/// Replace the codec code in `client_flag`, keeping the other bits.
+pub fn with_codec(client_flag: Option<u32>, code: u32) -> u32 {
+    (client_flag.unwrap_or(0) & !CODEC_MASK) | (code & CODEC_MASK)
+}
+
+pub fn is_builtin_codec(code: u32) -> bool {
+    matches!(
+        code,
+        CODEC_STR | CODEC_PICKLE | CODEC_INT | CODEC_BINARY | CODEC_JSON
+    )
+}
+
+/// Parse an INT value without going through Python when it fits an i64.
+pub fn parse_int(value: &[u8]) -> Option<i64> {
+    match i64::from_radix_10_signed_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
+static JSON_DUMPS: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+static JSON_LOADS: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+static PICKLE_DUMPS: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+static PICKLE_LOADS: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+
+/// A codec implemented in Python.
+struct PyCodec {
+    encode: Py<PyAny>,
+    decode: Py<PyAny>,
+    /// Types (a type or tuple of types) encoded with this codec by default.
+    types: Option<Py<PyAny>>,
+}
+
+/// Converts Python objects to stored bytes and back, keyed on the codec
+/// code in client_flag. bytes, str and int are handled in Rust; JSON and
+/// pickle call the stdlib modules; other codes are looked up in the
+/// registry of Python codecs.
+#[derive(Default)]
+pub struct CodecRegistry {
+    custom: BTreeMap<u32, PyCodec>,
+}
+
+impl CodecRegistry {
+    pub fn register(
+        &mut self,
+        code: u32,
+        encode: Py<PyAny>,
+        decode: Py<PyAny>,
+        types: Option<Py<PyAny>>,
+    ) -> PyResult<()> {
+        if code > CODEC_MASK {
+            return Err(PyValueError::new_err(format!(
+                "Codec code must be at most {CODEC_MASK}"
+            )));
+        }
+        if is_builtin_codec(code) {
+            return Err(PyValueError::new_err(format!(
+                "Codec code {code} is reserved for a built-in codec"
+            )));
+        }
+        self.custom.insert(
+            code,
+            PyCodec {
+                encode,
+                decode,
+                types,
+            },
+        );
+        Ok(())
+    }
+
+    /// Pick the codec for `obj`: registered types first (lowest code wins),
+    /// then bytes, str and int, falling back to pickle.
+    fn codec_for(&self, py: Python<'_>, obj: &Bound<'_, PyAny>) -> PyResult<u32> {
+        for (code, codec) in &self.custom {
+            if let Some(types) = &codec.types
+                && obj.is_instance(types.bind(py))?
+            {
+                return Ok(*code);
+            }
+        }
+        Ok(if obj.is_exact_instance_of::<PyBytes>() {
+            CODEC_BINARY
+        } else if obj.is_exact_instance_of::<PyString>() {
+            CODEC_STR
+        } else if obj.is_instance_of::<PyInt>() && !obj.is_instance_of::<PyBool>() {
+            CODEC_INT
+        } else {
+            CODEC_PICKLE
+        })
+    }
+
+    /// Encode `obj` with `code`, or the default codec for its type.
+    /// Returns the bytes to store and the codec code used.
+    pub fn encode<'py>(
+        &self,
+        py: Python<'py>,
+        obj: &Bound<'py, PyAny>,
+        code: Option<u32>,
+    ) -> PyResult<(Bound<'py, PyBytes>, u32)> {
+        let code = match code {
+            Some(code) => code,
+            None => self.codec_for(py, obj)?,
+        };
+        let encoded = match code {
+            CODEC_BINARY => obj.cast::<PyBytes>()?.clone(),
+            CODEC_STR => PyBytes::new(py, obj.cast::<PyString>()?.to_str()?.as_bytes()),
+            CODEC_INT => {
+                let obj = obj.cast::<PyInt>()?;
+                match obj.extract::<i64>() {
+                    Ok(v) => PyBytes::new(py, itoa::Buffer::new().format(v).as_bytes()),
+                    Err(_) => PyBytes::new(py, obj.str()?.to_str()?.as_bytes()),
+                }
+            }
+            CODEC_JSON => {
+                let dumps = JSON_DUMPS.import(py, "json", "dumps")?;
+                let text = dumps.call1((obj,))?;
+                PyBytes::new(py, text.cast::<PyString>()?.to_str()?.as_bytes())
+            }
+            CODEC_PICKLE => {
+                let dumps = PICKLE_DUMPS.import(py, "pickle", "dumps")?;
+                dumps.call1((obj,))?.cast_into::<PyBytes>()?
+            }
+            code => {
+                let codec = self.custom.get(&code).ok_or_else(|| unknown_codec(code))?;
+                codec
+                    .encode
+                    .bind(py)
+                    .call1((obj,))?
+                    .cast_into::<PyBytes>()?
+            }
+        };
+        Ok((encoded, code))
+    }
+
+    /// Decode stored bytes according to the codec code in `client_flag`.
+    pub fn decode<'py>(
+        &self,
+        py: Python<'py>,
+        value: &[u8],
+        client_flag: Option<u32>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        match codec_of(client_flag) {
+            CODEC_BINARY => Ok(PyBytes::new(py, value).into_any()),
+            CODEC_STR => {
+                let text = std::str::from_utf8(value)
+                    .map_err(|_| PyValueError::new_err("STR value is not valid UTF-8"))?;
+                Ok(PyString::new(py, text).into_any())
+            }
+            CODEC_INT => match parse_int(value) {
+                Some(v) => Ok(v.into_pyobject(py)?.into_any()),
+                None => py.get_type::<PyInt>().call1((PyBytes::new(py, value),)),
+            },
+            CODEC_JSON => {
+                let loads = JSON_LOADS.import(py, "json", "loads")?;
+                loads.call1((PyBytes::new(py, value),))
+            }
+            CODEC_PICKLE => {
+                let loads = PICKLE_LOADS.import(py, "pickle", "loads")?;
+                loads.call1((PyBytes::new(py, value),))
+            }
+            code => {
+                let codec = self.custom.get(&code).ok_or_else(|| unknown_codec(code))?;
+                codec.decode.bind(py).call1((PyBytes::new(py, value),))
+            }
+        }
+    }
+}
+
+fn unknown_codec(code: u32) -> PyErr {
+    PyValueError::new_err(format!("Unknown codec code {code}"))
+}
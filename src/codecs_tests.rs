@@ -0,0 +1,39 @@
+#[cfg(test)]
+mod tests {
+    use crate::codecs::{codec_of, is_builtin_codec, parse_int, with_codec};
+    use crate::constants::*;
+
+    #[test]
+    fn test_codec_of() {
+        assert_eq!(codec_of(None), CODEC_STR);
+        assert_eq!(codec_of(Some(CODEC_JSON)), CODEC_JSON);
+        assert_eq!(codec_of(Some(0x1234_0000 | CODEC_INT)), CODEC_INT);
+    }
+
+    #[test]
+    fn test_with_codec_keeps_other_bits() {
+        assert_eq!(with_codec(None, CODEC_PICKLE), CODEC_PICKLE);
+        assert_eq!(
+            with_codec(Some(0x100 | CODEC_INT), CODEC_JSON),
+            0x100 | CODEC_JSON
+        );
+    }
+
+    #[test]
+    fn test_builtin_codecs() {
+        for code in [CODEC_STR, CODEC_PICKLE, CODEC_INT, CODEC_BINARY, CODEC_JSON] {
+            assert!(is_builtin_codec(code));
+        }
+        assert!(!is_builtin_codec(100));
+    }
+
+    #[test]
+    fn test_parse_int() {
+        assert_eq!(parse_int(b"0"), Some(0));
+        assert_eq!(parse_int(b"-42"), Some(-42));
+        assert_eq!(parse_int(b"9223372036854775807"), Some(i64::MAX));
+        assert_eq!(parse_int(b"9223372036854775808"), None);
+        assert_eq!(parse_int(b""), None);
+        assert_eq!(parse_int(b"1.5"), None);
+    }
+}
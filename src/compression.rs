@@ -0,0 +1,145 @@
+use std::io;
+
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use crate::constants::*;
+
+/// Largest value decompress() will produce, so a corrupt or hostile size
+/// header can't make us allocate without bound.
+pub const MAX_DECOMPRESSED_SIZE: usize = 128 * 1024 * 1024;
+
+/// Size of the little-endian length prefix of LZ4 blocks.
+const LZ4_SIZE_LEN: usize = 4;
+
+enum Algorithm {
+    Zstd {
+        level: i32,
+        dictionary: Option<(EncoderDictionary<'static>, DecoderDictionary<'static>)>,
+    },
+    Lz4,
+}
+
+/// Value compression settings. Values of at least `threshold` bytes are
+/// compressed and marked with the algorithm's COMPRESSION_* client_flag
+/// bit, unless compression doesn't make them smaller.
+pub struct Compression {
+    algorithm: Algorithm,
+    threshold: usize,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Compression {
+    pub fn new(
+        algorithm: u32,
+        threshold: usize,
+        level: Option<i32>,
+        dictionary: Option<&[u8]>,
+    ) -> Result<Self, String> {
+        let algorithm = match algorithm {
+            COMPRESSION_ZSTD => {
+                let level = level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+                if !zstd::compression_level_range().contains(&level) {
+                    return Err(format!("Invalid zstd compression level {level}"));
+                }
+                let dictionary = dictionary.map(|dictionary| {
+                    (
+                        EncoderDictionary::copy(dictionary, level),
+                        DecoderDictionary::copy(dictionary),
+                    )
+                });
+                Algorithm::Zstd { level, dictionary }
+            }
+            COMPRESSION_LZ4 => {
+                if level.is_some() || dictionary.is_some() {
+                    return Err(
+                        "level and dictionary are only supported with COMPRESSION_ZSTD".into(),
+                    );
+                }
+                Algorithm::Lz4
+            }
+            other => {
+                return Err(format!("Unknown compression algorithm {other}"));
+            }
+        };
+        Ok(Compression {
+            algorithm,
+            threshold,
+        })
+    }
+
+    /// client_flag bit set on values compressed with these settings.
+    pub fn flag(&self) -> u32 {
+        match self.algorithm {
+            Algorithm::Zstd { .. } => COMPRESSION_ZSTD,
+            Algorithm::Lz4 => COMPRESSION_LZ4,
+        }
+    }
+
+    pub fn should_compress(&self, value: &[u8]) -> bool {
+        value.len() >= self.threshold
+    }
+
+    /// Compressed value, or None if it would not be smaller.
+    pub fn compress(&self, value: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let compressed = match &self.algorithm {
+            Algorithm::Zstd {
+                dictionary: Some((dictionary, _)),
+                ..
+            } => Compressor::with_prepared_dictionary(dictionary)?.compress(value)?,
+            Algorithm::Zstd { level, .. } => Compressor::new(*level)?.compress(value)?,
+            Algorithm::Lz4 => lz4_flex::compress_prepend_size(value),
+        };
+        Ok((compressed.len() < value.len()).then_some(compressed))
+    }
+
+    /// Decompress a value according to the COMPRESSION_* bit in
+    /// `client_flag`. zstd values are read with the configured dictionary,
+    /// if any.
+    pub fn decompress(&self, client_flag: u32, data: &[u8]) -> io::Result<Vec<u8>> {
+        match client_flag & COMPRESSION_MASK {
+            COMPRESSION_ZSTD => {
+                let size = zstd::zstd_safe::get_frame_content_size(data)
+                    .ok()
+                    .flatten()
+                    .ok_or_else(|| invalid_data("Missing zstd frame content size".into()))?;
+                let size = check_size(size as usize)?;
+                let mut decompressor = match &self.algorithm {
+                    Algorithm::Zstd {
+                        dictionary: Some((_, dictionary)),
+                        ..
+                    } => Decompressor::with_prepared_dictionary(dictionary)?,
+                    _ => Decompressor::new()?,
+                };
+                decompressor.decompress(data, size)
+            }
+            COMPRESSION_LZ4 => {
+                let (prefix, block) = data
+                    .split_first_chunk::<LZ4_SIZE_LEN>()
+                    .ok_or_else(|| invalid_data("Truncated lz4 value".into()))?;
+                let size = check_size(u32::from_le_bytes(*prefix) as usize)?;
+                let value = lz4_flex::decompress(block, size)
+                    .map_err(|e| invalid_data(format!("Invalid lz4 value: {e}")))?;
+                if value.len() != size {
+                    return Err(invalid_data("lz4 value size mismatch".into()));
+                }
+                Ok(value)
+            }
+            _ => Err(invalid_data(format!(
+                "Unknown compression bits in client_flag {client_flag}"
+            ))),
+        }
+    }
+}
+
+fn check_size(size: usize) -> io::Result<usize> {
+    if size > MAX_DECOMPRESSED_SIZE {
+        return Err(invalid_data(format!(
+            "Decompressed size {size} exceeds {MAX_DECOMPRESSED_SIZE}"
+        )));
+    }
+    Ok(size)
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use crate::compression::{Compression, MAX_DECOMPRESSED_SIZE};
+    use crate::constants::*;
+
+    fn sample() -> Vec<u8> {
+        b"the quick brown fox jumps over the lazy dog. ".repeat(100)
+    }
+
+    #[test]
+    fn test_zstd_roundtrip() {
+        let compression = Compression::new(COMPRESSION_ZSTD, 16, None, None).unwrap();
+        let value = sample();
+        let compressed = compression.compress(&value).unwrap().unwrap();
+        assert!(compressed.len() < value.len());
+        assert_eq!(
+            compression
+                .decompress(COMPRESSION_ZSTD, &compressed)
+                .unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_zstd_dictionary_roundtrip() {
+        let dictionary = b"the quick brown fox jumps over the lazy dog".repeat(4);
+        let compression =
+            Compression::new(COMPRESSION_ZSTD, 16, Some(19), Some(&dictionary)).unwrap();
+        let value = sample();
+        let compressed = compression.compress(&value).unwrap().unwrap();
+        assert_eq!(
+            compression
+                .decompress(COMPRESSION_ZSTD, &compressed)
+                .unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_lz4_roundtrip() {
+        let compression = Compression::new(COMPRESSION_LZ4, 16, None, None).unwrap();
+        let value = sample();
+        let compressed = compression.compress(&value).unwrap().unwrap();
+        assert_eq!(
+            compression
+                .decompress(COMPRESSION_LZ4 | CODEC_BINARY, &compressed)
+                .unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_incompressible_value_is_kept() {
+        let compression = Compression::new(COMPRESSION_ZSTD, 0, None, None).unwrap();
+        assert_eq!(compression.compress(b"abc").unwrap(), None);
+    }
+
+    #[test]
+    fn test_threshold() {
+        let compression = Compression::new(COMPRESSION_LZ4, 4, None, None).unwrap();
+        assert!(!compression.should_compress(b"abc"));
+        assert!(compression.should_compress(b"abcd"));
+    }
+
+    #[test]
+    fn test_flag() {
+        let zstd = Compression::new(COMPRESSION_ZSTD, 0, None, None).unwrap();
+        let lz4 = Compression::new(COMPRESSION_LZ4, 0, None, None).unwrap();
+        assert_eq!(zstd.flag(), COMPRESSION_ZSTD);
+        assert_eq!(lz4.flag(), COMPRESSION_LZ4);
+    }
+
+    #[test]
+    fn test_corrupt_values_rejected() {
+        let compression = Compression::new(COMPRESSION_LZ4, 0, None, None).unwrap();
+        assert!(
+            compression
+                .decompress(COMPRESSION_ZSTD, b"garbage")
+                .is_err()
+        );
+        assert!(compression.decompress(COMPRESSION_LZ4, b"ab").is_err());
+        assert!(
+            compression
+                .decompress(COMPRESSION_LZ4, b"\x10\x00\x00\x00xx")
+                .is_err()
+        );
+        assert!(compression.decompress(COMPRESSION_MASK, b"").is_err());
+    }
+
+    #[test]
+    fn test_oversized_lz4_rejected() {
+        let compression = Compression::new(COMPRESSION_LZ4, 0, None, None).unwrap();
+        let mut data = ((MAX_DECOMPRESSED_SIZE + 1) as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(b"\x00");
+        assert!(compression.decompress(COMPRESSION_LZ4, &data).is_err());
+    }
+}
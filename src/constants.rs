@@ -3,6 +3,7 @@ pub const RESPONSE_SUCCESS: u8 = 2; // SUCCESS (OK or HD)
 pub const RESPONSE_NOT_STORED: u8 = 3; // NOT_STORED (NS)
 pub const RESPONSE_CONFLICT: u8 = 4; // CONFLICT (EX)
 pub const RESPONSE_MISS: u8 = 5; // MISS (EN or NF)
+pub const RESPONSE_DEBUG: u8 = 6; // DEBUG (ME)
 pub const RESPONSE_NOOP: u8 = 100; // NOOP (MN)
 
 // Set modes:
@@ -19,7 +20,79 @@ pub const SET_MODE_SET: u8 = 83; // 'S'
 pub const MA_MODE_INC: u8 = 43; // '+'
 pub const MA_MODE_DEC: u8 = 45; // '-'
 
+// Lease statuses (see Lease):
+// HIT: fresh value, or stale while another client recomputes it.
+// HIT_STALE_MUST_RECOMPUTE: serve the value, this client won the recompute.
+// MISS_MUST_COMPUTE: no value, this client won the compute.
+// MISS_WAIT_FOR_OTHERS: no value, another client is computing it.
+pub const LEASE_HIT: u8 = 1;
+pub const LEASE_HIT_STALE_MUST_RECOMPUTE: u8 = 2;
+pub const LEASE_MISS_MUST_COMPUTE: u8 = 3;
+pub const LEASE_MISS_WAIT_FOR_OTHERS: u8 = 4;
+
+// Value codecs, stored in the low byte of client_flag (CODEC_MASK).
+// STR, PICKLE, INT and BINARY match the flags used by meta-memcache-py's
+// MixedSerializer (STR, PICKLE and INT also match python-memcached). Other
+// codes can be registered.
+pub const CODEC_MASK: u32 = 0xff;
+pub const CODEC_STR: u32 = 0;
+pub const CODEC_PICKLE: u32 = 1;
+pub const CODEC_INT: u32 = 2;
+pub const CODEC_BINARY: u32 = 16;
+pub const CODEC_JSON: u32 = 32;
+
+// Compression algorithms. Each value is also the client_flag bit that marks
+// values compressed with it (COMPRESSION_MASK covers both).
+pub const COMPRESSION_MASK: u32 = 0x300;
+pub const COMPRESSION_ZSTD: u32 = 0x100;
+pub const COMPRESSION_LZ4: u32 = 0x200;
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+// Encryption algorithms. Like compression, each value is also the client_flag
+// bit marking values encrypted with it. The id of the key used is stored in
+// ENCRYPTION_KEY_ID_MASK.
+pub const ENCRYPTION_MASK: u32 = 0xc00;
+pub const ENCRYPTION_AES_GCM: u32 = 0x400;
+pub const ENCRYPTION_CHACHA20_POLY1305: u32 = 0x800;
+pub const ENCRYPTION_KEY_ID_MASK: u32 = 0xff_0000;
+pub const ENCRYPTION_KEY_ID_SHIFT: u32 = 16;
+
+// Checksum algorithms, also the client_flag bit marking values stored with a
+// checksum trailer: CRC32C appends 4 bytes, XXH3 the 8 bytes of its 64-bit
+// hash, both little-endian.
+pub const CHECKSUM_MASK: u32 = 0x3000;
+pub const CHECKSUM_CRC32C: u32 = 0x1000;
+pub const CHECKSUM_XXH3: u32 = 0x2000;
+
+// client_flag bit marking a chunk manifest: the value is split across
+// separate chunk items, see set_chunking(). DEFAULT_CHUNK_SIZE stays below
+// memcached's default 1 MiB item size limit.
+pub const CHUNKED: u32 = 0x4000;
+pub const DEFAULT_CHUNK_SIZE: usize = 512 * 1024;
+
+// Default and longest time a value is kept in a NearCache, in seconds.
+pub const DEFAULT_NEAR_CACHE_TTL: u32 = 10;
+
+// Default time a versioned namespace's generation is used before it is
+// re-read from the server, in seconds.
+pub const DEFAULT_NAMESPACE_REFRESH_INTERVAL: u32 = 5;
+
+// Hash algorithms for long keys, see KeyPolicy.
+pub const KEY_HASH_BLAKE2B: u32 = 1;
+pub const KEY_HASH_MD5: u32 = 2;
+pub const KEY_HASH_SHA1: u32 = 3;
+pub const KEY_HASH_XXH3: u32 = 4;
+
+// Default number of hashed keys a KeyRecorder remembers.
+pub const DEFAULT_KEY_RECORDER_SIZE: usize = 10_000;
+
+// Default seconds between polls of a ClusterDiscovery.
+pub const DEFAULT_DISCOVERY_POLL_INTERVAL: u32 = 60;
+
 pub const NOOP_CMD: &[u8] = b"mn\r\n";
+pub const VERSION_CMD: &[u8] = b"version\r\n";
+pub const QUIT_CMD: &[u8] = b"quit\r\n";
+pub const CONFIG_GET_CLUSTER_CMD: &[u8] = b"config get cluster\r\n";
 pub const ENDL: &[u8] = b"\r\n";
 pub const ENDL_LEN: usize = 2;
 
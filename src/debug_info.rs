@@ -0,0 +1,155 @@
+use atoi::{FromRadix10Checked, FromRadix10SignedChecked};
+use memchr::memchr;
+use pyo3::prelude::*;
+
+#[inline]
+fn parse_u32(value: &[u8]) -> Option<u32> {
+    match u32::from_radix_10_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
+#[inline]
+fn parse_u64(value: &[u8]) -> Option<u64> {
+    match u64::from_radix_10_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
+#[inline]
+fn parse_i32(value: &[u8]) -> Option<i32> {
+    match i32::from_radix_10_signed_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
+/// Item state returned by the meta debug (`me`) command.
+#[pyclass(frozen, eq, skip_from_py_object)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugInfo {
+    /// The key as sent on the wire: base64-encoded for binary keys, and
+    /// the digest of hashed keys, which decode_wire_key can't reverse.
+    #[pyo3(get)]
+    pub wire_key: Vec<u8>,
+    #[pyo3(get)]
+    pub ttl: Option<i32>,
+    #[pyo3(get)]
+    pub last_access: Option<u32>,
+    #[pyo3(get)]
+    pub cas_token: Option<u64>,
+    #[pyo3(get)]
+    pub fetched: Option<bool>,
+    #[pyo3(get)]
+    pub slab_class: Option<u32>,
+    #[pyo3(get)]
+    pub size: Option<u32>,
+}
+
+#[pymethods]
+impl DebugInfo {
+    #[new]
+    #[pyo3(
+        signature = (
+            wire_key,
+            /,
+            *,
+            ttl=None,
+            last_access=None,
+            cas_token=None,
+            fetched=None,
+            slab_class=None,
+            size=None,
+        ),
+        text_signature = "(wire_key, /, *,
+            ttl=None,
+            last_access=None,
+            cas_token=None,
+            fetched=None,
+            slab_class=None,
+            size=None)"
+    )]
+    fn new(
+        wire_key: Vec<u8>,
+        ttl: Option<i32>,
+        last_access: Option<u32>,
+        cas_token: Option<u64>,
+        fetched: Option<bool>,
+        slab_class: Option<u32>,
+        size: Option<u32>,
+    ) -> Self {
+        DebugInfo {
+            wire_key,
+            ttl,
+            last_access,
+            cas_token,
+            fetched,
+            slab_class,
+            size,
+        }
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "DebugInfo(wire_key={:?}, ttl={:?}, last_access={:?}, cas_token={:?}, fetched={:?}, slab_class={:?}, size={:?})",
+            String::from_utf8_lossy(&self.wire_key),
+            self.ttl,
+            self.last_access,
+            self.cas_token,
+            self.fetched,
+            self.slab_class,
+            self.size,
+        )
+    }
+
+    /// Parse a debug (ME) header: `ME <wire key> exp=.. la=.. cas=.. fetch=.. cls=.. size=..`
+    /// Unknown or malformed pairs are skipped. Returns None if the key is missing.
+    #[staticmethod]
+    pub fn from_debug_header(header: &[u8]) -> Option<Self> {
+        let key_start: usize = 3;
+        if header.len() <= key_start {
+            return None;
+        }
+        let key_end = match memchr(b' ', &header[key_start..]) {
+            Some(pos) => key_start + pos,
+            None => header.len(),
+        };
+        if key_end == key_start {
+            return None;
+        }
+
+        let mut info = DebugInfo {
+            wire_key: header[key_start..key_end].to_vec(),
+            ttl: None,
+            last_access: None,
+            cas_token: None,
+            fetched: None,
+            slab_class: None,
+            size: None,
+        };
+        for pair in header[key_end..].split(|&c| c == b' ') {
+            let Some(eq) = memchr(b'=', pair) else {
+                continue;
+            };
+            let value = &pair[eq + 1..];
+            match &pair[..eq] {
+                b"exp" => info.ttl = parse_i32(value),
+                b"la" => info.last_access = parse_u32(value),
+                b"cas" => info.cas_token = parse_u64(value),
+                b"fetch" => {
+                    info.fetched = match value {
+                        b"yes" => Some(true),
+                        b"no" => Some(false),
+                        _ => None,
+                    }
+                }
+                b"cls" => info.slab_class = parse_u32(value),
+                b"size" => info.size = parse_u32(value),
+                _ => {}
+            }
+        }
+        Some(info)
+    }
+}
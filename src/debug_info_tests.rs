@@ -0,0 +1,57 @@
+#[cfg(test)]
+mod tests {
+    use crate::debug_info::DebugInfo;
+
+    #[test]
+    fn test_parse_all_fields() {
+        let info = DebugInfo::from_debug_header(b"ME foo exp=-1 la=3 cas=2 fetch=no cls=1 size=63")
+            .unwrap();
+        assert_eq!(info.wire_key, b"foo".to_vec());
+        assert_eq!(info.ttl, Some(-1));
+        assert_eq!(info.last_access, Some(3));
+        assert_eq!(info.cas_token, Some(2));
+        assert_eq!(info.fetched, Some(false));
+        assert_eq!(info.slab_class, Some(1));
+        assert_eq!(info.size, Some(63));
+    }
+
+    #[test]
+    fn test_parse_fetched_yes() {
+        let info = DebugInfo::from_debug_header(b"ME foo fetch=yes").unwrap();
+        assert_eq!(info.fetched, Some(true));
+    }
+
+    #[test]
+    fn test_parse_large_cas() {
+        let info = DebugInfo::from_debug_header(b"ME foo cas=18446744073709551615").unwrap();
+        assert_eq!(info.cas_token, Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_parse_key_only() {
+        let info = DebugInfo::from_debug_header(b"ME foo").unwrap();
+        assert_eq!(info.wire_key, b"foo".to_vec());
+        assert!(info.ttl.is_none());
+        assert!(info.last_access.is_none());
+        assert!(info.cas_token.is_none());
+        assert!(info.fetched.is_none());
+        assert!(info.slab_class.is_none());
+        assert!(info.size.is_none());
+    }
+
+    #[test]
+    fn test_parse_unknown_and_malformed_skipped() {
+        let info =
+            DebugInfo::from_debug_header(b"ME foo new=1 la=x garbage exp=5 size=12a").unwrap();
+        assert_eq!(info.ttl, Some(5));
+        assert!(info.last_access.is_none());
+        assert!(info.size.is_none());
+    }
+
+    #[test]
+    fn test_parse_missing_key() {
+        assert!(DebugInfo::from_debug_header(b"ME").is_none());
+        assert!(DebugInfo::from_debug_header(b"ME ").is_none());
+        assert!(DebugInfo::from_debug_header(b"ME  exp=1").is_none());
+    }
+}
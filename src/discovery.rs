@@ -0,0 +1,145 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use log::warn;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyWeakrefReference;
+
+use crate::cluster_config::ClusterConfig;
+use crate::constants::DEFAULT_DISCOVERY_POLL_INTERVAL;
+use crate::durations::positive_seconds;
+use crate::memcache_socket::MemcacheSocket;
+use crate::sync::{lock, unpoisoned};
+
+/// Tells the polling thread to stop. The thread only holds this and a weak
+/// reference to its ClusterDiscovery, so dropping the latter stops it too.
+struct StopSignal {
+    stopped: Mutex<bool>,
+    wakeup: Condvar,
+}
+
+impl StopSignal {
+    fn stop(&self) {
+        *lock(&self.stopped) = true;
+        self.wakeup.notify_all();
+    }
+
+    /// Wait `interval` for the next poll. Returns true if stopped meanwhile.
+    fn wait(&self, interval: Duration) -> bool {
+        let stopped = lock(&self.stopped);
+        let (stopped, _) = unpoisoned(self.wakeup.wait_timeout_while(
+            stopped,
+            interval,
+            |stopped| !*stopped,
+        ));
+        *stopped
+    }
+}
+
+/// Polls an ElastiCache configuration endpoint with `config get cluster`
+/// and calls `on_change` with the new ClusterConfig whenever its version
+/// changes, e.g. to rebuild the server ring. Polling runs either on demand
+/// with poll(), or every `poll_interval` on a background thread between
+/// start() and stop(), or until the ClusterDiscovery is collected.
+#[pyclass(frozen, weakref)]
+pub struct ClusterDiscovery {
+    socket: Py<MemcacheSocket>,
+    on_change: Py<PyAny>,
+    poll_interval: Duration,
+    config: Mutex<Option<ClusterConfig>>,
+    signal: Arc<StopSignal>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Drop for ClusterDiscovery {
+    fn drop(&mut self) {
+        // Not joined: a poll in progress would need the GIL held here
+        self.signal.stop();
+    }
+}
+
+#[pymethods]
+impl ClusterDiscovery {
+    #[new]
+    #[pyo3(signature = (socket, on_change, poll_interval=DEFAULT_DISCOVERY_POLL_INTERVAL as f64))]
+    fn new(socket: Py<MemcacheSocket>, on_change: Py<PyAny>, poll_interval: f64) -> PyResult<Self> {
+        Ok(ClusterDiscovery {
+            socket,
+            on_change,
+            poll_interval: positive_seconds("poll_interval", poll_interval)
+                .map_err(PyValueError::new_err)?,
+            config: Mutex::new(None),
+            signal: Arc::new(StopSignal {
+                stopped: Mutex::new(true),
+                wakeup: Condvar::new(),
+            }),
+            thread: Mutex::new(None),
+        })
+    }
+
+    /// The last configuration passed to on_change, if any.
+    #[getter]
+    fn config(&self) -> Option<ClusterConfig> {
+        lock(&self.config).clone()
+    }
+
+    /// Fetch the configuration and call on_change if its version differs
+    /// from the last one seen. Returns whether it did. If on_change raises,
+    /// the configuration is offered again on the next poll.
+    fn poll(&self, py: Python<'_>) -> PyResult<bool> {
+        let config = self.socket.bind(py).borrow_mut().config_get_cluster(py)?;
+        let changed = lock(&self.config)
+            .as_ref()
+            .is_none_or(|current| current.version != config.version);
+        if !changed {
+            return Ok(false);
+        }
+        self.on_change.call1(py, (config.clone(),))?;
+        *lock(&self.config) = Some(config);
+        Ok(true)
+    }
+
+    /// Poll now and then every poll_interval on a background thread until
+    /// stop(), or until this object is collected. Errors are logged and
+    /// polling goes on.
+    fn start(slf: &Bound<'_, Self>) -> PyResult<()> {
+        let this = slf.get();
+        let mut thread = lock(&this.thread);
+        if thread.is_some() {
+            return Err(PyRuntimeError::new_err("Cluster discovery already started"));
+        }
+        *lock(&this.signal.stopped) = false;
+        let discovery = PyWeakrefReference::new(slf.as_any())?.unbind();
+        let signal = this.signal.clone();
+        let poll_interval = this.poll_interval;
+        *thread = Some(std::thread::spawn(move || {
+            loop {
+                let alive = Python::attach(|py| {
+                    let Ok(Some(discovery)) = discovery.bind(py).upgrade_as::<Self>() else {
+                        return false;
+                    };
+                    if let Err(e) = discovery.get().poll(py) {
+                        warn!("Cluster discovery failed: {e}");
+                    }
+                    true
+                });
+                if !alive || signal.wait(poll_interval) {
+                    break;
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    /// Stop the background thread, waiting for a poll in progress.
+    fn stop(&self, py: Python<'_>) {
+        self.signal.stop();
+        let thread = lock(&self.thread).take();
+        if let Some(thread) = thread {
+            // The thread needs the GIL to finish a poll in progress
+            let _ = py.detach(|| thread.join());
+        }
+    }
+}
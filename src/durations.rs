@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+/// Convert a number of seconds from Python into a Duration.
+pub fn seconds(name: &str, value: f64) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(value).map_err(|_| format!("{name} must be a non-negative number"))
+}
+
+/// Convert a number of seconds from Python into a non-zero Duration, for
+/// intervals a loop sleeps between iterations.
+pub fn positive_seconds(name: &str, value: f64) -> Result<Duration, String> {
+    seconds(name, value)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("{name} must be a positive number"))
+}
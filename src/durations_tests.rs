@@ -0,0 +1,32 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::durations::{positive_seconds, seconds};
+
+    #[test]
+    fn test_seconds() {
+        assert_eq!(seconds("timeout", 1.5), Ok(Duration::from_millis(1500)));
+        assert_eq!(seconds("timeout", 0.0), Ok(Duration::ZERO));
+        for value in [-1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                seconds("timeout", value),
+                Err("timeout must be a non-negative number".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_positive_seconds() {
+        assert_eq!(
+            positive_seconds("poll_interval", 0.05),
+            Ok(Duration::from_millis(50))
+        );
+        for value in [0.0, -0.0, 1e-12, -1.0, f64::NAN] {
+            assert_eq!(
+                positive_seconds("poll_interval", value),
+                Err("poll_interval must be a positive number".to_string())
+            );
+        }
+    }
+}
@@ -1,9 +1,14 @@
 use base64::{Engine as _, engine::general_purpose};
 use blake2::Blake2bVar;
-use blake2::digest::{Update, VariableOutput};
+use blake2::digest::{Digest, Update, VariableOutput};
+use md5::Md5;
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::{PyBytes, PyString};
+use sha1::Sha1;
+use xxhash_rust::xxh3::{xxh3_64, xxh3_128};
+
+use crate::constants::*;
 
 /// Max raw key size before hashing. Binary keys get base64-encoded (4/3 expansion),
 /// so the threshold is 250 * 3 / 4 ≈ 187.
@@ -12,6 +17,9 @@ const MAX_KEY_SIZE: usize = 187;
 /// Blake2b digest size in bytes. Matches Python's hashlib.blake2b(digest_size=18).
 const BLAKE2B_DIGEST_SIZE: usize = 18;
 
+/// memcached's key length limit.
+const MAX_KEY_LEN: usize = 250;
+
 /// A wire-ready encoded key plus metadata.
 pub struct EncodedKey {
     /// The key bytes ready to be written to the wire (base64-encoded if binary).
@@ -21,41 +29,267 @@ pub struct EncodedKey {
     pub is_binary: bool,
 }
 
-/// Encode a raw key for the memcache wire protocol:
-/// 1. If the key is >= MAX_KEY_SIZE bytes, hash it with blake2b (18-byte digest).
-/// 2. If the (possibly hashed) key contains binary bytes, base64-encode it.
-/// The resulting wire key is always < 250 bytes (memcache max key length).
-/// Returns None if the key is empty.
-pub fn encode_key(data: &[u8]) -> Option<EncodedKey> {
-    if data.is_empty() {
-        return None;
-    }
-
-    // Hash long keys to a compact digest
-    let mut digest_buf = [0u8; BLAKE2B_DIGEST_SIZE];
-    let key: &[u8] = if data.len() >= MAX_KEY_SIZE {
-        let mut hasher = Blake2bVar::new(BLAKE2B_DIGEST_SIZE).ok()?;
-        hasher.update(data);
-        hasher.finalize_variable(&mut digest_buf).ok()?;
-        &digest_buf
-    } else {
-        data
-    };
-
-    let is_binary = key.iter().any(|&c| c <= b' ' || c > b'~');
-
-    // Key is guaranteed < MAX_KEY_SIZE (187) bytes at this point (either
-    // original short key or 18-byte blake2b digest). Base64 expands by 4/3:
-    //   - Worst case: 186 bytes -> ceil(186/3)*4 = 248 < MAX_KEY_LEN (250)
-    //   - Hash case:   18 bytes -> ceil(18/3)*4  =  24 < MAX_KEY_LEN (250)
-    // So the wire key is always under MAX_KEY_LEN.
-    let value = if is_binary {
-        general_purpose::STANDARD.encode(key).into_bytes()
-    } else {
-        key.to_vec()
-    };
+/// Reasons a key can't be encoded.
+#[derive(Debug, PartialEq)]
+pub enum KeyError {
+    Empty,
+    /// The key needs hashing but the policy is strict.
+    TooLong,
+}
+
+/// Hash function for long keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum KeyHash {
+    Blake2b,
+    Md5,
+    Sha1,
+    Xxh3,
+}
+
+impl KeyHash {
+    fn from_code(code: u32) -> Option<Self> {
+        match code {
+            KEY_HASH_BLAKE2B => Some(KeyHash::Blake2b),
+            KEY_HASH_MD5 => Some(KeyHash::Md5),
+            KEY_HASH_SHA1 => Some(KeyHash::Sha1),
+            KEY_HASH_XXH3 => Some(KeyHash::Xxh3),
+            _ => None,
+        }
+    }
+
+    fn default_digest_size(self) -> usize {
+        match self {
+            KeyHash::Blake2b => BLAKE2B_DIGEST_SIZE,
+            KeyHash::Md5 => 16,
+            KeyHash::Sha1 => 20,
+            KeyHash::Xxh3 => 8,
+        }
+    }
+
+    /// blake2b takes any size up to 64 bytes, as a parameter of the hash;
+    /// md5 and sha1 digests are truncated; xxh3 is the 64 or 128-bit
+    /// variant.
+    fn supports_digest_size(self, digest_size: usize) -> bool {
+        match self {
+            KeyHash::Blake2b => (1..=64).contains(&digest_size),
+            KeyHash::Md5 => (1..=16).contains(&digest_size),
+            KeyHash::Sha1 => (1..=20).contains(&digest_size),
+            KeyHash::Xxh3 => digest_size == 8 || digest_size == 16,
+        }
+    }
+
+    /// Digest of `data`, as Python's hashlib (and xxhash) `digest()` gives
+    /// it, so keys match those of Python clients.
+    fn digest(self, data: &[u8], digest_size: usize) -> Vec<u8> {
+        let mut digest = match self {
+            KeyHash::Blake2b => {
+                let mut digest = vec![0u8; digest_size];
+                // The size was validated, so neither call can fail.
+                if let Ok(mut hasher) = Blake2bVar::new(digest_size) {
+                    hasher.update(data);
+                    let _ = hasher.finalize_variable(&mut digest);
+                }
+                digest
+            }
+            KeyHash::Md5 => Md5::digest(data).to_vec(),
+            KeyHash::Sha1 => Sha1::digest(data).to_vec(),
+            KeyHash::Xxh3 if digest_size == 16 => xxh3_128(data).to_be_bytes().to_vec(),
+            KeyHash::Xxh3 => xxh3_64(data).to_be_bytes().to_vec(),
+        };
+        digest.truncate(digest_size);
+        digest
+    }
+}
+
+fn is_binary(key: &[u8]) -> bool {
+    key.iter().any(|&c| c <= b' ' || c > b'~')
+}
+
+fn base64_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+fn hex(data: &[u8]) -> Vec<u8> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    data.iter()
+        .flat_map(|&b| [DIGITS[(b >> 4) as usize], DIGITS[(b & 0xf) as usize]])
+        .collect()
+}
+
+/// How keys are encoded for the wire protocol:
+/// 1. If the key is at least `hash_threshold` bytes, or would be too long
+///    once base64-encoded, it is hashed (or rejected if `strict`), and the
+///    digest, hex-encoded with `hex_digest`, is prefixed with `tag`.
+/// 2. If the (possibly hashed) key contains binary bytes, it is
+///    base64-encoded.
+///
+/// The resulting wire key is always at most 250 bytes (memcache max key
+/// length). The default hashes keys of 187 bytes or more to an 18-byte
+/// blake2b digest.
+#[pyclass(frozen, skip_from_py_object)]
+#[derive(Clone, Debug)]
+pub struct KeyPolicy {
+    hash_threshold: usize,
+    hash: KeyHash,
+    digest_size: usize,
+    hex_digest: bool,
+    strict: bool,
+    tag: Vec<u8>,
+}
+
+/// The policy used without one.
+pub static DEFAULT_KEY_POLICY: KeyPolicy = KeyPolicy {
+    hash_threshold: MAX_KEY_SIZE,
+    hash: KeyHash::Blake2b,
+    digest_size: BLAKE2B_DIGEST_SIZE,
+    hex_digest: false,
+    strict: false,
+    tag: Vec::new(),
+};
 
-    Some(EncodedKey { value, is_binary })
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        DEFAULT_KEY_POLICY.clone()
+    }
+}
+
+impl KeyPolicy {
+    pub fn new(
+        hash_threshold: usize,
+        algorithm: u32,
+        digest_size: Option<usize>,
+        hex_digest: bool,
+        strict: bool,
+        tag: &[u8],
+    ) -> Result<Self, String> {
+        if !(1..=MAX_KEY_LEN + 1).contains(&hash_threshold) {
+            return Err(format!(
+                "hash_threshold must be between 1 and {}",
+                MAX_KEY_LEN + 1
+            ));
+        }
+        let hash = KeyHash::from_code(algorithm)
+            .ok_or_else(|| format!("Unknown key hash algorithm: {algorithm}"))?;
+        let digest_size = digest_size.unwrap_or(hash.default_digest_size());
+        if !hash.supports_digest_size(digest_size) {
+            return Err(format!(
+                "Unsupported digest_size {digest_size} for this algorithm"
+            ));
+        }
+        let len = tag.len()
+            + if hex_digest {
+                digest_size * 2
+            } else {
+                digest_size
+            };
+        let wire_len = if hex_digest && !is_binary(tag) {
+            len
+        } else {
+            base64_len(len)
+        };
+        if wire_len > MAX_KEY_LEN {
+            return Err(format!(
+                "Hashed keys would exceed {MAX_KEY_LEN} bytes, use a shorter tag or digest"
+            ));
+        }
+        Ok(KeyPolicy {
+            hash_threshold,
+            hash,
+            digest_size,
+            hex_digest,
+            strict,
+            tag: tag.to_vec(),
+        })
+    }
+
+    /// Whether `data` must be hashed to fit.
+    pub fn needs_hashing(&self, data: &[u8]) -> bool {
+        data.len() >= self.hash_threshold
+            || (base64_len(data.len()) > MAX_KEY_LEN && is_binary(data))
+    }
+
+    /// `tag` followed by the digest of `data`.
+    pub fn hash_key(&self, data: &[u8]) -> Vec<u8> {
+        let digest = self.hash.digest(data, self.digest_size);
+        let digest = if self.hex_digest {
+            hex(&digest)
+        } else {
+            digest
+        };
+        [self.tag.as_slice(), &digest].concat()
+    }
+
+    /// Encode a raw key for the memcache wire protocol.
+    pub fn encode(&self, data: &[u8]) -> Result<EncodedKey, KeyError> {
+        if data.is_empty() {
+            return Err(KeyError::Empty);
+        }
+
+        // Hash long keys to a compact digest
+        let hashed;
+        let key: &[u8] = if self.needs_hashing(data) {
+            if self.strict {
+                return Err(KeyError::TooLong);
+            }
+            hashed = self.hash_key(data);
+            &hashed
+        } else {
+            data
+        };
+
+        let is_binary = is_binary(key);
+
+        // Unhashed keys are short enough to base64-encode (needs_hashing),
+        // and hashed ones were checked in new().
+        let value = if is_binary {
+            general_purpose::STANDARD.encode(key).into_bytes()
+        } else {
+            key.to_vec()
+        };
+
+        Ok(EncodedKey { value, is_binary })
+    }
+}
+
+#[pymethods]
+impl KeyPolicy {
+    /// Hash keys of at least `hash_threshold` bytes (251 to hash only keys
+    /// over memcached's 250-byte limit) with `algorithm` (KEY_HASH_*) to
+    /// `digest_size` bytes, by default the algorithm's own (18 for
+    /// blake2b, 8 for xxh3), hex-encoded with `hex_digest` and prefixed
+    /// with `tag`. With `strict`, such keys raise ValueError instead.
+    #[new]
+    #[pyo3(signature = (hash_threshold=MAX_KEY_SIZE, algorithm=KEY_HASH_BLAKE2B, digest_size=None, hex_digest=false, strict=false, tag=None))]
+    fn py_new(
+        hash_threshold: usize,
+        algorithm: u32,
+        digest_size: Option<usize>,
+        hex_digest: bool,
+        strict: bool,
+        tag: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let tag = tag.map(extract_key).transpose()?.unwrap_or_default();
+        KeyPolicy::new(
+            hash_threshold,
+            algorithm,
+            digest_size,
+            hex_digest,
+            strict,
+            tag,
+        )
+        .map_err(PyValueError::new_err)
+    }
+}
+
+/// Undo the base64 encoding of a wire key sent with the `b` flag. Hashed
+/// keys can't be reversed, see KeyRecorder.
+pub fn decode_wire_key(wire_key: &[u8], is_binary: bool) -> Result<Vec<u8>, String> {
+    if !is_binary {
+        return Ok(wire_key.to_vec());
+    }
+    general_purpose::STANDARD
+        .decode(wire_key)
+        .map_err(|e| format!("Invalid base64 wire key: {e}"))
 }
 
 /// Extract a key from a Python object. Accepts str (UTF-8) or bytes.
@@ -75,6 +309,10 @@ pub fn extract_key<'py>(ob: &'py Bound<'py, PyAny>) -> PyResult<&'py [u8]> {
 mod tests {
     use super::*;
 
+    fn encode_key(data: &[u8]) -> Result<EncodedKey, KeyError> {
+        KeyPolicy::default().encode(data)
+    }
+
     #[test]
     fn test_short_ascii_key_passthrough() {
         let ek = encode_key(b"users:profile:12345").unwrap();
@@ -84,7 +322,7 @@ mod tests {
 
     #[test]
     fn test_empty_key_returns_none() {
-        assert!(encode_key(b"").is_none());
+        assert!(encode_key(b"").is_err());
     }
 
     #[test]
@@ -131,4 +369,119 @@ mod tests {
         let ek = encode_key(&key).unwrap();
         assert!(ek.value.len() < 250);
     }
+
+    fn policy(
+        hash_threshold: usize,
+        algorithm: u32,
+        digest_size: Option<usize>,
+        hex_digest: bool,
+        tag: &[u8],
+    ) -> KeyPolicy {
+        KeyPolicy::new(
+            hash_threshold,
+            algorithm,
+            digest_size,
+            hex_digest,
+            false,
+            tag,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_policy_default_matches_encode_key() {
+        let key = vec![b'k'; 300];
+        let default = KeyPolicy::default().encode(&key).unwrap();
+        let explicit = policy(MAX_KEY_SIZE, KEY_HASH_BLAKE2B, None, false, b"")
+            .encode(&key)
+            .unwrap();
+        assert_eq!(default.value, explicit.value);
+    }
+
+    #[test]
+    fn test_policy_hex_digest() {
+        let md5 = policy(251, KEY_HASH_MD5, None, true, b"");
+        let ek = md5.encode(&[b'a'; 251]).unwrap();
+        assert!(!ek.is_binary);
+        assert_eq!(ek.value.len(), 32);
+        // A 250-byte ASCII key fits as-is
+        let ek = md5.encode(&[b'a'; 250]).unwrap();
+        assert_eq!(ek.value, vec![b'a'; 250]);
+    }
+
+    #[test]
+    fn test_policy_known_digests() {
+        assert_eq!(
+            policy(1, KEY_HASH_MD5, None, true, b"").hash_key(b"abc"),
+            b"900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            policy(1, KEY_HASH_SHA1, None, true, b"").hash_key(b"abc"),
+            b"a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            policy(1, KEY_HASH_XXH3, None, true, b"").hash_key(b""),
+            b"2d06800538d394c2"
+        );
+        assert_eq!(
+            policy(1, KEY_HASH_SHA1, Some(4), true, b"").hash_key(b"abc"),
+            b"a9993e36"
+        );
+    }
+
+    #[test]
+    fn test_policy_tag() {
+        let tagged = policy(10, KEY_HASH_MD5, None, true, b"h:");
+        let ek = tagged.encode(b"a long enough key").unwrap();
+        assert!(ek.value.starts_with(b"h:"));
+        assert_eq!(ek.value.len(), 34);
+        assert_eq!(tagged.encode(b"short").unwrap().value, b"short");
+    }
+
+    #[test]
+    fn test_policy_strict() {
+        let strict = KeyPolicy::new(10, KEY_HASH_BLAKE2B, None, false, true, b"").unwrap();
+        assert!(strict.encode(b"short").is_ok());
+        assert_eq!(
+            strict.encode(b"a long enough key").err(),
+            Some(KeyError::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_policy_hashes_binary_keys_too_long_for_base64() {
+        let key = vec![0u8; 200];
+        let ek = policy(251, KEY_HASH_BLAKE2B, None, false, b"")
+            .encode(&key)
+            .unwrap();
+        assert!(ek.is_binary);
+        assert_eq!(
+            general_purpose::STANDARD.decode(&ek.value).unwrap().len(),
+            BLAKE2B_DIGEST_SIZE
+        );
+    }
+
+    #[test]
+    fn test_decode_wire_key() {
+        let ek = encode_key(b"\x00binary").unwrap();
+        assert_eq!(
+            decode_wire_key(&ek.value, ek.is_binary).unwrap(),
+            b"\x00binary"
+        );
+        assert_eq!(decode_wire_key(b"plain", false).unwrap(), b"plain");
+        assert!(decode_wire_key(b"not base64!", true).is_err());
+    }
+
+    #[test]
+    fn test_policy_rejects_invalid() {
+        assert!(KeyPolicy::new(0, KEY_HASH_BLAKE2B, None, false, false, b"").is_err());
+        assert!(KeyPolicy::new(252, KEY_HASH_BLAKE2B, None, false, false, b"").is_err());
+        assert!(KeyPolicy::new(187, 99, None, false, false, b"").is_err());
+        assert!(KeyPolicy::new(187, KEY_HASH_MD5, Some(17), false, false, b"").is_err());
+        assert!(KeyPolicy::new(187, KEY_HASH_XXH3, Some(12), false, false, b"").is_err());
+        assert!(
+            KeyPolicy::new(187, KEY_HASH_BLAKE2B, Some(64), false, false, &[b't'; 140]).is_err()
+        );
+        assert!(KeyPolicy::new(187, KEY_HASH_BLAKE2B, Some(64), true, false, &[b't'; 122]).is_ok());
+    }
 }
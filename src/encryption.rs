@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::generic_array::typenum::Unsigned;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, Nonce, OsRng, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+
+use crate::constants::*;
+
+create_exception!(
+    meta_memcache_socket,
+    DecryptionError,
+    PyValueError,
+    "An encrypted value could not be decrypted."
+);
+
+/// Both ciphers use 256-bit keys.
+pub const KEY_LEN: usize = 32;
+
+/// Bytes encrypt() adds to a value: the nonce and the tag, 12 and 16 bytes
+/// with both algorithms.
+pub const SEAL_OVERHEAD: usize = 12 + 16;
+
+/// Value encryption settings. Values are encrypted with the key `key_id`
+/// and stored as nonce + ciphertext + tag, marked with the algorithm's
+/// ENCRYPTION_* bit and the key id in client_flag. The AAD is the stored
+/// client_flag plus the item key, so a value can't be moved to another key
+/// or have its flags changed undetected.
+pub struct Encryption {
+    keys: BTreeMap<u8, [u8; KEY_LEN]>,
+    key_id: u8,
+    algorithm: u32,
+    miss_on_error: bool,
+}
+
+fn aad(client_flag: u32, key: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(4 + key.len());
+    aad.extend_from_slice(&client_flag.to_be_bytes());
+    aad.extend_from_slice(key);
+    aad
+}
+
+fn cipher<C: KeyInit>(key: &[u8; KEY_LEN]) -> Result<C, String> {
+    C::new_from_slice(key).map_err(|_| "Invalid encryption key length".into())
+}
+
+fn seal<C: Aead + AeadCore + KeyInit>(
+    key: &[u8; KEY_LEN],
+    aad: &[u8],
+    value: &[u8],
+) -> Result<Vec<u8>, String> {
+    let nonce = C::generate_nonce(&mut OsRng);
+    let ciphertext = cipher::<C>(key)?
+        .encrypt(&nonce, Payload { msg: value, aad })
+        .map_err(|_| "Value too large to encrypt".to_string())?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+fn open<C: Aead + AeadCore + KeyInit>(
+    key: &[u8; KEY_LEN],
+    aad: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>, String> {
+    let nonce_len = C::NonceSize::USIZE;
+    if data.len() < nonce_len {
+        return Err("Encrypted value is truncated".into());
+    }
+    let (nonce, ciphertext) = data.split_at(nonce_len);
+    cipher::<C>(key)?
+        .decrypt(
+            Nonce::<C>::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| "Authentication failed".into())
+}
+
+impl Encryption {
+    /// `key_id` defaults to the highest id in `keys`, so rotating is a matter
+    /// of adding a key with a higher id.
+    pub fn new(
+        keys: Vec<(u32, Vec<u8>)>,
+        key_id: Option<u32>,
+        algorithm: u32,
+        miss_on_error: bool,
+    ) -> Result<Self, String> {
+        if algorithm != ENCRYPTION_AES_GCM && algorithm != ENCRYPTION_CHACHA20_POLY1305 {
+            return Err(format!("Unknown encryption algorithm {algorithm}"));
+        }
+        let max_id = ENCRYPTION_KEY_ID_MASK >> ENCRYPTION_KEY_ID_SHIFT;
+        let mut parsed = BTreeMap::new();
+        for (id, key) in keys {
+            let id = u8::try_from(id)
+                .map_err(|_| format!("Encryption key ids must be at most {max_id}"))?;
+            let key: [u8; KEY_LEN] = key
+                .try_into()
+                .map_err(|_| format!("Encryption keys must be {KEY_LEN} bytes"))?;
+            parsed.insert(id, key);
+        }
+        let key_id = match key_id {
+            Some(id) => u8::try_from(id)
+                .ok()
+                .filter(|id| parsed.contains_key(id))
+                .ok_or_else(|| format!("Unknown encryption key id {id}"))?,
+            None => *parsed
+                .keys()
+                .next_back()
+                .ok_or("At least one encryption key is required")?,
+        };
+        Ok(Encryption {
+            keys: parsed,
+            key_id,
+            algorithm,
+            miss_on_error,
+        })
+    }
+
+    /// client_flag bits set on values encrypted with these settings.
+    pub fn flag(&self) -> u32 {
+        self.algorithm | (self.key_id as u32) << ENCRYPTION_KEY_ID_SHIFT
+    }
+
+    /// Whether a value that fails to decrypt is returned as a Miss instead
+    /// of raising DecryptionError.
+    pub fn miss_on_error(&self) -> bool {
+        self.miss_on_error
+    }
+
+    /// Encrypt a value for `key`, to be stored with `client_flag`, which
+    /// must include flag().
+    pub fn encrypt(&self, client_flag: u32, key: &[u8], value: &[u8]) -> Result<Vec<u8>, String> {
+        let secret = &self.keys[&self.key_id];
+        let aad = aad(client_flag, key);
+        match self.algorithm {
+            ENCRYPTION_AES_GCM => seal::<Aes256Gcm>(secret, &aad, value),
+            _ => seal::<ChaCha20Poly1305>(secret, &aad, value),
+        }
+    }
+
+    /// Decrypt a value of `key` stored with `client_flag`, using the
+    /// algorithm and key id it records.
+    pub fn decrypt(&self, client_flag: u32, key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+        let key_id = (client_flag & ENCRYPTION_KEY_ID_MASK) >> ENCRYPTION_KEY_ID_SHIFT;
+        let secret = self
+            .keys
+            .get(&(key_id as u8))
+            .ok_or_else(|| format!("Unknown encryption key id {key_id}"))?;
+        let aad = aad(client_flag, key);
+        match client_flag & ENCRYPTION_MASK {
+            ENCRYPTION_AES_GCM => open::<Aes256Gcm>(secret, &aad, data),
+            ENCRYPTION_CHACHA20_POLY1305 => open::<ChaCha20Poly1305>(secret, &aad, data),
+            _ => Err(format!(
+                "Unknown encryption bits in client_flag {client_flag}"
+            )),
+        }
+    }
+}
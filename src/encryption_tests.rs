@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use crate::constants::*;
+    use crate::encryption::Encryption;
+
+    fn keys() -> Vec<(u32, Vec<u8>)> {
+        vec![(1, vec![1u8; 32]), (7, vec![7u8; 32])]
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for algorithm in [ENCRYPTION_AES_GCM, ENCRYPTION_CHACHA20_POLY1305] {
+            let encryption = Encryption::new(keys(), None, algorithm, false).unwrap();
+            let flag = encryption.flag() | CODEC_JSON;
+            let sealed = encryption.encrypt(flag, b"key", b"secret").unwrap();
+            assert_eq!(sealed.len(), 12 + 6 + 16);
+            assert_eq!(
+                encryption.decrypt(flag, b"key", &sealed).unwrap(),
+                b"secret"
+            );
+        }
+    }
+
+    #[test]
+    fn test_flag_defaults_to_highest_key_id() {
+        let encryption = Encryption::new(keys(), None, ENCRYPTION_AES_GCM, false).unwrap();
+        assert_eq!(
+            encryption.flag(),
+            ENCRYPTION_AES_GCM | 7 << ENCRYPTION_KEY_ID_SHIFT
+        );
+        let encryption =
+            Encryption::new(keys(), Some(1), ENCRYPTION_CHACHA20_POLY1305, false).unwrap();
+        assert_eq!(
+            encryption.flag(),
+            ENCRYPTION_CHACHA20_POLY1305 | 1 << ENCRYPTION_KEY_ID_SHIFT
+        );
+    }
+
+    #[test]
+    fn test_nonce_is_random() {
+        let encryption = Encryption::new(keys(), None, ENCRYPTION_AES_GCM, false).unwrap();
+        let flag = encryption.flag();
+        assert_ne!(
+            encryption.encrypt(flag, b"key", b"secret").unwrap(),
+            encryption.encrypt(flag, b"key", b"secret").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decrypts_with_rotated_out_key() {
+        let old = Encryption::new(keys(), Some(1), ENCRYPTION_AES_GCM, false).unwrap();
+        let flag = old.flag();
+        let sealed = old.encrypt(flag, b"key", b"secret").unwrap();
+        let new = Encryption::new(keys(), None, ENCRYPTION_AES_GCM, false).unwrap();
+        assert_eq!(new.decrypt(flag, b"key", &sealed).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_aad_binds_key_and_client_flag() {
+        let encryption = Encryption::new(keys(), None, ENCRYPTION_AES_GCM, false).unwrap();
+        let flag = encryption.flag();
+        let sealed = encryption.encrypt(flag, b"key", b"secret").unwrap();
+        assert!(encryption.decrypt(flag, b"other", &sealed).is_err());
+        assert!(
+            encryption
+                .decrypt(flag | CODEC_PICKLE, b"key", &sealed)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_tampered_or_unknown_rejected() {
+        let encryption = Encryption::new(keys(), None, ENCRYPTION_AES_GCM, false).unwrap();
+        let flag = encryption.flag();
+        let mut sealed = encryption.encrypt(flag, b"key", b"secret").unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(encryption.decrypt(flag, b"key", &sealed).is_err());
+        assert!(encryption.decrypt(flag, b"key", b"short").is_err());
+        let unknown_id = ENCRYPTION_AES_GCM | 3 << ENCRYPTION_KEY_ID_SHIFT;
+        assert!(encryption.decrypt(unknown_id, b"key", &sealed).is_err());
+        assert!(
+            encryption
+                .decrypt(ENCRYPTION_MASK, b"key", &sealed)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        assert!(Encryption::new(vec![], None, ENCRYPTION_AES_GCM, false).is_err());
+        assert!(Encryption::new(vec![(1, vec![0; 16])], None, ENCRYPTION_AES_GCM, false).is_err());
+        assert!(
+            Encryption::new(vec![(256, vec![0; 32])], None, ENCRYPTION_AES_GCM, false).is_err()
+        );
+        assert!(Encryption::new(keys(), Some(2), ENCRYPTION_AES_GCM, false).is_err());
+        assert!(Encryption::new(keys(), None, COMPRESSION_ZSTD, false).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::convert::Infallible;
+
+use atoi::{FromRadix10Checked, FromRadix10SignedChecked};
+use memchr::memchr;
+use pyo3::prelude::*;
+
+/// Stats groups accepted by `stats <group>`.
+pub const STATS_GROUPS: &[&str] = &["slabs", "items", "settings", "conns"];
+
+/// A stat value converted to the most specific Python type that fits.
+#[derive(Debug, PartialEq)]
+pub enum StatValue {
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Str(String),
+}
+
+impl<'py> IntoPyObject<'py> for StatValue {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = Infallible;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        Ok(match self {
+            StatValue::Int(v) => v.into_pyobject(py)?.into_any(),
+            StatValue::Uint(v) => v.into_pyobject(py)?.into_any(),
+            StatValue::Float(v) => v.into_pyobject(py)?.into_any(),
+            StatValue::Str(v) => v.into_pyobject(py)?.into_any(),
+        })
+    }
+}
+
+/// Build `stats [group]\r\n`. Returns None for an unknown group.
+pub fn build_stats_cmd(group: Option<&str>) -> Option<Vec<u8>> {
+    let mut buf: Vec<u8> = Vec::with_capacity(16);
+    buf.extend_from_slice(b"stats");
+    if let Some(group) = group {
+        if !STATS_GROUPS.contains(&group) {
+            return None;
+        }
+        buf.push(b' ');
+        buf.extend_from_slice(group.as_bytes());
+    }
+    buf.extend_from_slice(b"\r\n");
+    Some(buf)
+}
+
+/// Build `flush_all [delay] [noreply]\r\n`.
+pub fn build_flush_all_cmd(delay: Option<u32>, no_reply: bool) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::with_capacity(32);
+    buf.extend_from_slice(b"flush_all");
+    if let Some(delay) = delay {
+        let mut itoa_buf = itoa::Buffer::new();
+        buf.push(b' ');
+        buf.extend_from_slice(itoa_buf.format(delay).as_bytes());
+    }
+    if no_reply {
+        buf.extend_from_slice(b" noreply");
+    }
+    buf.extend_from_slice(b"\r\n");
+    buf
+}
+
+/// Build `verbosity <level> [noreply]\r\n`.
+pub fn build_verbosity_cmd(level: u32, no_reply: bool) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::with_capacity(32);
+    let mut itoa_buf = itoa::Buffer::new();
+    buf.extend_from_slice(b"verbosity ");
+    buf.extend_from_slice(itoa_buf.format(level).as_bytes());
+    if no_reply {
+        buf.extend_from_slice(b" noreply");
+    }
+    buf.extend_from_slice(b"\r\n");
+    buf
+}
+
+/// Split a `STAT <name> <value>` line (without ENDL) into name and value.
+/// Returns None if the line is not a STAT line.
+pub fn parse_stat_line(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = line.strip_prefix(b"STAT ")?;
+    match memchr(b' ', rest) {
+        Some(pos) => Some((&rest[..pos], &rest[pos + 1..])),
+        None => Some((rest, b"")),
+    }
+}
+
+/// Split a per-id stat name into the id and the field name:
+/// `1:chunk_size` (slabs), `items:1:number` (items) or `23:addr` (conns).
+/// Names without an id are returned unchanged.
+pub fn split_stat_name(name: &[u8]) -> (Option<u32>, &[u8]) {
+    let mut parts = name.splitn(3, |&c| c == b':');
+    let first = parts.next().unwrap_or_default();
+    if let Some(id) = parse_id(first)
+        && name.len() > first.len() + 1
+    {
+        // `<id>:<field>`, the field may itself contain ':'
+        return (Some(id), &name[first.len() + 1..]);
+    }
+    if let (Some(second), Some(field)) = (parts.next(), parts.next())
+        && let Some(id) = parse_id(second)
+    {
+        // `<prefix>:<id>:<field>`
+        return (Some(id), field);
+    }
+    (None, name)
+}
+
+#[inline]
+fn parse_id(value: &[u8]) -> Option<u32> {
+    match u32::from_radix_10_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
+/// Convert a stat value to an int, float or string.
+pub fn parse_stat_value(value: &[u8]) -> StatValue {
+    if let (Some(v), len) = u64::from_radix_10_checked(value)
+        && len == value.len()
+        && len > 0
+    {
+        return StatValue::Uint(v);
+    }
+    if let (Some(v), len) = i64::from_radix_10_signed_checked(value)
+        && len == value.len()
+        && len > 1
+    {
+        return StatValue::Int(v);
+    }
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    if memchr(b'.', digits).is_some()
+        && digits.iter().all(|&c| c.is_ascii_digit() || c == b'.')
+        && let Some(v) = std::str::from_utf8(value).ok().and_then(|v| v.parse().ok())
+    {
+        return StatValue::Float(v);
+    }
+    StatValue::Str(String::from_utf8_lossy(value).into_owned())
+}
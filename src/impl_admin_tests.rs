@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use crate::impl_admin::*;
+
+    #[test]
+    fn test_build_stats_cmd() {
+        assert_eq!(build_stats_cmd(None).unwrap(), b"stats\r\n");
+        assert_eq!(build_stats_cmd(Some("slabs")).unwrap(), b"stats slabs\r\n");
+        assert_eq!(build_stats_cmd(Some("items")).unwrap(), b"stats items\r\n");
+        assert_eq!(
+            build_stats_cmd(Some("settings")).unwrap(),
+            b"stats settings\r\n"
+        );
+        assert_eq!(build_stats_cmd(Some("conns")).unwrap(), b"stats conns\r\n");
+    }
+
+    #[test]
+    fn test_build_stats_cmd_unknown_group() {
+        assert!(build_stats_cmd(Some("detail on")).is_none());
+        assert!(build_stats_cmd(Some("items\r\nflush_all")).is_none());
+    }
+
+    #[test]
+    fn test_build_flush_all_cmd() {
+        assert_eq!(build_flush_all_cmd(None, false), b"flush_all\r\n");
+        assert_eq!(build_flush_all_cmd(Some(10), false), b"flush_all 10\r\n");
+        assert_eq!(
+            build_flush_all_cmd(Some(0), true),
+            b"flush_all 0 noreply\r\n"
+        );
+        assert_eq!(build_flush_all_cmd(None, true), b"flush_all noreply\r\n");
+    }
+
+    #[test]
+    fn test_build_verbosity_cmd() {
+        assert_eq!(build_verbosity_cmd(1, false), b"verbosity 1\r\n");
+        assert_eq!(build_verbosity_cmd(0, true), b"verbosity 0 noreply\r\n");
+    }
+
+    #[test]
+    fn test_parse_stat_line() {
+        assert_eq!(
+            parse_stat_line(b"STAT pid 1234"),
+            Some((&b"pid"[..], &b"1234"[..]))
+        );
+        assert_eq!(
+            parse_stat_line(b"STAT 23:addr tcp:127.0.0.1:11211"),
+            Some((&b"23:addr"[..], &b"tcp:127.0.0.1:11211"[..]))
+        );
+        assert_eq!(
+            parse_stat_line(b"STAT empty"),
+            Some((&b"empty"[..], &b""[..]))
+        );
+        assert!(parse_stat_line(b"END").is_none());
+        assert!(parse_stat_line(b"ERROR").is_none());
+    }
+
+    #[test]
+    fn test_split_stat_name() {
+        assert_eq!(split_stat_name(b"curr_items"), (None, &b"curr_items"[..]));
+        assert_eq!(
+            split_stat_name(b"1:chunk_size"),
+            (Some(1), &b"chunk_size"[..])
+        );
+        assert_eq!(
+            split_stat_name(b"items:12:number"),
+            (Some(12), &b"number"[..])
+        );
+        assert_eq!(split_stat_name(b"23:addr"), (Some(23), &b"addr"[..]));
+        assert_eq!(
+            split_stat_name(b"items:x:number"),
+            (None, &b"items:x:number"[..])
+        );
+        assert_eq!(split_stat_name(b"1:"), (None, &b"1:"[..]));
+    }
+
+    #[test]
+    fn test_parse_stat_value() {
+        assert_eq!(parse_stat_value(b"1234"), StatValue::Uint(1234));
+        assert_eq!(
+            parse_stat_value(b"18446744073709551615"),
+            StatValue::Uint(u64::MAX)
+        );
+        assert_eq!(parse_stat_value(b"-1"), StatValue::Int(-1));
+        assert_eq!(parse_stat_value(b"0.123456"), StatValue::Float(0.123456));
+        assert_eq!(
+            parse_stat_value(b"1.6.21"),
+            StatValue::Str("1.6.21".to_string())
+        );
+        assert_eq!(parse_stat_value(b"yes"), StatValue::Str("yes".to_string()));
+        assert_eq!(parse_stat_value(b"-"), StatValue::Str("-".to_string()));
+        assert_eq!(parse_stat_value(b""), StatValue::Str("".to_string()));
+    }
+}
@@ -1,9 +1,65 @@
+use std::ops::Range;
+
+use pyo3::PyErr;
+use pyo3::exceptions::PyValueError;
+
 use crate::RequestFlags;
-use crate::encode_key::encode_key;
+use crate::encode_key::{KeyError, KeyPolicy};
 
 pub struct BuiltCmd {
     pub buf: Vec<u8>,
     pub no_reply: bool,
+    /// The key was sent base64-encoded with the `b` flag.
+    pub binary_key: bool,
+    /// Where the encoded key is in `buf`.
+    pub wire_key: Range<usize>,
+}
+
+impl BuiltCmd {
+    /// The key as sent: hashed and/or base64-encoded if needed.
+    pub fn wire_key(&self) -> &[u8] {
+        &self.buf[self.wire_key.clone()]
+    }
+}
+
+/// Reasons a command can't be built.
+#[derive(Debug, PartialEq)]
+pub enum BuildCmdError {
+    /// The key is empty.
+    EmptyKey,
+    /// The key would need hashing under a strict KeyPolicy.
+    KeyTooLong,
+    /// The command is not a well-formed token (e.g. contains spaces or `\r\n`).
+    InvalidCmd,
+}
+
+impl From<BuildCmdError> for PyErr {
+    fn from(err: BuildCmdError) -> PyErr {
+        match err {
+            BuildCmdError::EmptyKey => PyValueError::new_err("Key is empty"),
+            BuildCmdError::KeyTooLong => {
+                PyValueError::new_err("Key is too long and the key policy is strict")
+            }
+            BuildCmdError::InvalidCmd => PyValueError::new_err(
+                "Command must be a non-empty token of ASCII letters, digits or '_'",
+            ),
+        }
+    }
+}
+
+impl From<KeyError> for BuildCmdError {
+    fn from(err: KeyError) -> BuildCmdError {
+        match err {
+            KeyError::Empty => BuildCmdError::EmptyKey,
+            KeyError::TooLong => BuildCmdError::KeyTooLong,
+        }
+    }
+}
+
+/// A command is written verbatim to the wire, so it must be a single
+/// token that can't inject extra flags or commands into the stream.
+fn is_valid_cmd(cmd: &[u8]) -> bool {
+    !cmd.is_empty() && cmd.iter().all(|&c| c.is_ascii_alphanumeric() || c == b'_')
 }
 
 pub fn impl_build_cmd(
@@ -13,8 +69,12 @@ pub fn impl_build_cmd(
     request_flags: Option<&RequestFlags>,
     legacy_size_format: bool,
     allow_no_reply_flag: bool,
-) -> Option<BuiltCmd> {
-    let encoded_key = encode_key(key)?;
+    key_policy: &KeyPolicy,
+) -> Result<BuiltCmd, BuildCmdError> {
+    if !is_valid_cmd(cmd) {
+        return Err(BuildCmdError::InvalidCmd);
+    }
+    let encoded_key = key_policy.encode(key)?;
 
     // Build the command
     let mut buf: Vec<u8> = Vec::with_capacity(128);
@@ -24,6 +84,7 @@ pub fn impl_build_cmd(
     buf.push(b' ');
 
     // Add wire-ready key (already base64-encoded if binary)
+    let wire_key = buf.len()..buf.len() + encoded_key.value.len();
     buf.extend_from_slice(&encoded_key.value);
 
     // Add size
@@ -50,5 +111,10 @@ pub fn impl_build_cmd(
     };
     buf.push(b'\r');
     buf.push(b'\n');
-    Some(BuiltCmd { buf, no_reply })
+    Ok(BuiltCmd {
+        buf,
+        no_reply,
+        binary_key: encoded_key.is_binary,
+        wire_key,
+    })
 }
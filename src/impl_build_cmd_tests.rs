@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
+    use crate::encode_key::KeyPolicy;
     use crate::impl_build_cmd;
+    use crate::impl_build_cmd::BuildCmdError;
     use crate::request_flags::RequestFlags;
 
     #[test]
@@ -19,6 +21,7 @@ mod tests {
             true,                     // return_key
             true,                     // no_update_lru
             true,                     // mark_stale
+            false,                    // remove_value
             Some(111),                // cache_ttl
             Some(222),                // recache_ttl
             Some(333),                // vivify_on_miss_ttl
@@ -26,11 +29,22 @@ mod tests {
             Some(555),                // ma_initial_value
             Some(666),                // ma_delta_value,
             Some(777),                // cas_token
+            None,                     // explicit_cas
             Some(b"opaque".to_vec()), // opaque
             Some(b'A'),               // mode (APPEND)
-        );
+        )
+        .unwrap();
 
-        let built = impl_build_cmd(cmd, key, None, Some(&request_flags), false, true).unwrap();
+        let built = impl_build_cmd(
+            cmd,
+            key,
+            None,
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
         let string = String::from_utf8_lossy(&built.buf);
         println!("{:?}", string);
         assert!(built.no_reply);
@@ -56,6 +70,7 @@ mod tests {
             false, // return_key
             false, // no_update_lru
             false, // mark_stale
+            false, // remove_value
             None,  // cache_ttl
             None,  // recache_ttl
             None,  // vivify_on_miss_ttl
@@ -63,11 +78,22 @@ mod tests {
             None,  // ma_initial_value
             None,  // ma_delta_value,
             None,  // cas_token
+            None,  // explicit_cas
             None,  // opaque
             None,  // mode
-        );
+        )
+        .unwrap();
 
-        let built = impl_build_cmd(cmd, key, None, Some(&request_flags), false, true).unwrap();
+        let built = impl_build_cmd(
+            cmd,
+            key,
+            None,
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
         let string = String::from_utf8_lossy(&built.buf);
         println!("{:?}", string);
         assert!(!built.no_reply);
@@ -90,6 +116,7 @@ mod tests {
             false, // return_key
             false, // no_update_lru
             false, // mark_stale
+            false, // remove_value
             None,  // cache_ttl
             None,  // recache_ttl
             None,  // vivify_on_miss_ttl
@@ -97,11 +124,22 @@ mod tests {
             None,  // ma_initial_value
             None,  // ma_delta_value,
             None,  // cas_token
+            None,  // explicit_cas
             None,  // opaque
             None,  // mode
-        );
+        )
+        .unwrap();
 
-        let built = impl_build_cmd(cmd, key, None, Some(&request_flags), false, true).unwrap();
+        let built = impl_build_cmd(
+            cmd,
+            key,
+            None,
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
         let string = String::from_utf8_lossy(&built.buf);
         println!("{:?}", string);
         assert_eq!(built.buf, b"mg S2V5X3dpdGhfYmluYXJ5AA== b\r\n");
@@ -123,6 +161,7 @@ mod tests {
             false, // return_key
             false, // no_update_lru
             false, // mark_stale
+            false, // remove_value
             None,  // cache_ttl
             None,  // recache_ttl
             None,  // vivify_on_miss_ttl
@@ -130,11 +169,22 @@ mod tests {
             None,  // ma_initial_value
             None,  // ma_delta_value,
             None,  // cas_token
+            None,  // explicit_cas
             None,  // opaque
             None,  // mode
-        );
+        )
+        .unwrap();
 
-        let built = impl_build_cmd(cmd, key, None, Some(&request_flags), false, true).unwrap();
+        let built = impl_build_cmd(
+            cmd,
+            key,
+            None,
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
         let string = String::from_utf8_lossy(&built.buf);
         println!("{:?}", string);
         assert_eq!(built.buf, b"mg S2V5IHdpdGggc3BhY2Vz b\r\n");
@@ -142,14 +192,28 @@ mod tests {
 
     #[test]
     fn test_empty_key_rejected() {
-        assert!(impl_build_cmd(b"mg", b"", None, None, false, true).is_none());
+        assert_eq!(
+            impl_build_cmd(b"mg", b"", None, None, false, true, &KeyPolicy::default()).err(),
+            Some(BuildCmdError::EmptyKey)
+        );
+    }
+
+    #[test]
+    fn test_invalid_cmd_rejected() {
+        for cmd in [&b""[..], b"mg key\r\nmd", b"mg k", b"m\x00"] {
+            assert_eq!(
+                impl_build_cmd(cmd, b"key", None, None, false, true, &KeyPolicy::default()).err(),
+                Some(BuildCmdError::InvalidCmd)
+            );
+        }
     }
 
     #[test]
     fn test_short_key_passthrough() {
         // 186 bytes is OK (< 187 = MAX_KEY_SIZE), passed through as-is
         let key = &vec![b'X'; 186];
-        let built = impl_build_cmd(b"mg", key, None, None, false, true).unwrap();
+        let built =
+            impl_build_cmd(b"mg", key, None, None, false, true, &KeyPolicy::default()).unwrap();
         // Key should appear directly in the command (not hashed)
         assert!(built.buf.windows(186).any(|w| w == key.as_slice()));
     }
@@ -158,35 +222,51 @@ mod tests {
     fn test_long_key_gets_hashed() {
         // 250 bytes is >= MAX_KEY_SIZE, so it gets blake2b-hashed
         let key = &vec![b'X'; 250];
-        let built = impl_build_cmd(b"mg", key, None, None, false, true).unwrap();
+        let built =
+            impl_build_cmd(b"mg", key, None, None, false, true, &KeyPolicy::default()).unwrap();
         // The hashed key is binary -> base64-encoded, so the b flag should be present
         assert!(built.buf.windows(2).any(|w| w == b" b"));
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_binary_key_at_max_size() {
         // 186 binary bytes is < MAX_KEY_SIZE, base64-encoded on the wire
         let key = &vec![0x00u8; 186];
-        let built = impl_build_cmd(b"mg", key, None, None, false, true).unwrap();
+        let built =
+            impl_build_cmd(b"mg", key, None, None, false, true, &KeyPolicy::default()).unwrap();
         assert!(built.buf.windows(2).any(|w| w == b" b"));
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_long_binary_key_gets_hashed() {
         // 187 binary bytes is >= MAX_KEY_SIZE, gets hashed then base64-encoded
         let key = &vec![0x00u8; 187];
-        let built = impl_build_cmd(b"mg", key, None, None, false, true).unwrap();
+        let built =
+            impl_build_cmd(b"mg", key, None, None, false, true, &KeyPolicy::default()).unwrap();
         assert!(built.buf.windows(2).any(|w| w == b" b"));
     }
 
     #[test]
     fn test_very_large_key_gets_hashed() {
         let key = &vec![b'X'; 10_000];
-        let built = impl_build_cmd(b"mg", key, None, None, false, true).unwrap();
+        let built =
+            impl_build_cmd(b"mg", key, None, None, false, true, &KeyPolicy::default()).unwrap();
         // Should succeed (hashed to 18 bytes -> base64 24 chars)
         assert!(built.buf.len() < 100);
     }
 
+    #[test]
+    fn test_wire_key() {
+        let policy = KeyPolicy::default();
+        let built = impl_build_cmd(b"ms", b"key", Some(5), None, false, true, &policy).unwrap();
+        assert_eq!(built.wire_key(), b"key");
+        let built = impl_build_cmd(b"mg", &[b'X'; 250], None, None, false, true, &policy).unwrap();
+        assert_eq!(built.wire_key().len(), 24);
+        assert_eq!(&built.buf[3..27], built.wire_key());
+    }
+
     #[test]
     fn test_cmd_with_size() {
         let cmd = b"ms";
@@ -204,6 +284,7 @@ mod tests {
             false,     // return_key
             false,     // no_update_lru
             false,     // mark_stale
+            false,     // remove_value
             Some(111), // cache_ttl
             None,      // recache_ttl
             None,      // vivify_on_miss_ttl
@@ -211,12 +292,22 @@ mod tests {
             None,      // ma_initial_value
             None,      // ma_delta_value,
             None,      // cas_token
+            None,      // explicit_cas
             None,      // opaque
             None,      // mode
-        );
+        )
+        .unwrap();
 
-        let built =
-            impl_build_cmd(cmd, key, Some(size), Some(&request_flags), false, true).unwrap();
+        let built = impl_build_cmd(
+            cmd,
+            key,
+            Some(size),
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
         let string = String::from_utf8_lossy(&built.buf);
         println!("{:?}", string);
         assert_eq!(built.buf, b"ms key 123 T111\r\n");
@@ -228,10 +319,144 @@ mod tests {
         let key = b"key";
         let size = 123;
 
-        let built = impl_build_cmd(cmd, key, Some(size), None, true, true).unwrap();
+        let built = impl_build_cmd(
+            cmd,
+            key,
+            Some(size),
+            None,
+            true,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
         let string = String::from_utf8_lossy(&built.buf);
         println!("{:?}", string);
         assert!(!built.no_reply);
         assert_eq!(built.buf, b"ms key S123\r\n");
     }
+
+    #[test]
+    fn test_delete_remove_value() {
+        let request_flags = RequestFlags::new(
+            false, // no_reply
+            false, // return_client_flag
+            false, // return_cas_token
+            false, // return_value
+            false, // return_ttl
+            false, // return_size
+            false, // return_last_access
+            false, // return_fetched
+            false, // return_key
+            false, // no_update_lru
+            false, // mark_stale
+            true,  // remove_value
+            None,  // cache_ttl
+            None,  // recache_ttl
+            None,  // vivify_on_miss_ttl
+            None,  // client_flag
+            None,  // ma_initial_value
+            None,  // ma_delta_value,
+            None,  // cas_token
+            None,  // explicit_cas
+            None,  // opaque
+            None,  // mode
+        )
+        .unwrap();
+
+        let built = impl_build_cmd(
+            b"md",
+            b"key",
+            None,
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(built.buf, b"md key x\r\n");
+    }
+
+    #[test]
+    fn test_set_explicit_cas() {
+        let request_flags = RequestFlags::new(
+            false,               // no_reply
+            false,               // return_client_flag
+            false,               // return_cas_token
+            false,               // return_value
+            false,               // return_ttl
+            false,               // return_size
+            false,               // return_last_access
+            false,               // return_fetched
+            false,               // return_key
+            false,               // no_update_lru
+            false,               // mark_stale
+            false,               // remove_value
+            None,                // cache_ttl
+            None,                // recache_ttl
+            None,                // vivify_on_miss_ttl
+            None,                // client_flag
+            None,                // ma_initial_value
+            None,                // ma_delta_value,
+            None,                // cas_token
+            Some(1234567890123), // explicit_cas
+            None,                // opaque
+            None,                // mode
+        )
+        .unwrap();
+
+        let built = impl_build_cmd(
+            b"ms",
+            b"key",
+            Some(3),
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(built.buf, b"ms key 3 E1234567890123\r\n");
+    }
+
+    #[test]
+    fn test_set_invalidate_if_cas_older() {
+        // I with C on ms: if the CAS is older than the item's, the item is
+        // marked stale instead of failing with EX
+        let request_flags = RequestFlags::new(
+            false,   // no_reply
+            false,   // return_client_flag
+            false,   // return_cas_token
+            false,   // return_value
+            false,   // return_ttl
+            false,   // return_size
+            false,   // return_last_access
+            false,   // return_fetched
+            false,   // return_key
+            false,   // no_update_lru
+            true,    // mark_stale
+            false,   // remove_value
+            None,    // cache_ttl
+            None,    // recache_ttl
+            None,    // vivify_on_miss_ttl
+            None,    // client_flag
+            None,    // ma_initial_value
+            None,    // ma_delta_value,
+            Some(5), // cas_token
+            None,    // explicit_cas
+            None,    // opaque
+            None,    // mode
+        )
+        .unwrap();
+
+        let built = impl_build_cmd(
+            b"ms",
+            b"key",
+            Some(3),
+            Some(&request_flags),
+            false,
+            true,
+            &KeyPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(built.buf, b"ms key 3 I C5\r\n");
+    }
 }
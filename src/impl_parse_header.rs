@@ -1,6 +1,7 @@
 use memchr::memmem;
 
 use crate::constants::*;
+use crate::debug_info::DebugInfo;
 use crate::response_flags::ResponseFlags;
 
 /// Result of parsing a memcache meta-protocol response header.
@@ -10,6 +11,7 @@ pub struct ParsedHeader {
     pub response_type: Option<u8>,
     pub size: Option<u32>,
     pub flags: Option<ResponseFlags>,
+    pub debug_info: Option<DebugInfo>,
 }
 
 pub fn impl_parse_header(data: &[u8], start: usize, end: usize) -> Option<ParsedHeader> {
@@ -32,12 +34,14 @@ pub fn impl_parse_header(data: &[u8], start: usize, end: usize) -> Option<Parsed
                 response_type: Some(RESPONSE_VALUE),
                 size: Some(size),
                 flags: Some(flags),
+                debug_info: None,
             }),
             None => Some(ParsedHeader {
                 end_pos,
                 response_type: None,
                 size: None,
                 flags: None,
+                debug_info: None,
             }),
         },
         b"HD" | b"OK" => Some(ParsedHeader {
@@ -45,36 +49,58 @@ pub fn impl_parse_header(data: &[u8], start: usize, end: usize) -> Option<Parsed
             response_type: Some(RESPONSE_SUCCESS),
             size: None,
             flags: Some(ResponseFlags::from_success_header(&data[start..n])),
+            debug_info: None,
         }),
         b"NS" => Some(ParsedHeader {
             end_pos,
             response_type: Some(RESPONSE_NOT_STORED),
             size: None,
             flags: None,
+            debug_info: None,
         }),
         b"EX" => Some(ParsedHeader {
             end_pos,
             response_type: Some(RESPONSE_CONFLICT),
             size: None,
             flags: None,
+            debug_info: None,
         }),
         b"EN" | b"NF" => Some(ParsedHeader {
             end_pos,
             response_type: Some(RESPONSE_MISS),
             size: None,
             flags: None,
+            debug_info: None,
         }),
+        b"ME" => match DebugInfo::from_debug_header(&data[start..n]) {
+            Some(info) => Some(ParsedHeader {
+                end_pos,
+                response_type: Some(RESPONSE_DEBUG),
+                size: None,
+                flags: None,
+                debug_info: Some(info),
+            }),
+            None => Some(ParsedHeader {
+                end_pos,
+                response_type: None,
+                size: None,
+                flags: None,
+                debug_info: None,
+            }),
+        },
         b"MN" => Some(ParsedHeader {
             end_pos,
             response_type: Some(RESPONSE_NOOP),
             size: None,
             flags: None,
+            debug_info: None,
         }),
         _ => Some(ParsedHeader {
             end_pos,
             response_type: None,
             size: None,
             flags: None,
+            debug_info: None,
         }),
     }
 }
@@ -206,6 +206,28 @@ mod tests {
         assert!(h.flags.is_none());
     }
 
+    #[test]
+    fn test_debug_response() {
+        let data = b"ME foo exp=-1 la=3 cas=2 fetch=no cls=1 size=63\r\n";
+        let h = impl_parse_header(data, 0, data.len()).unwrap();
+        assert_eq!(h.end_pos, data.len());
+        assert_eq!(h.response_type, Some(RESPONSE_DEBUG));
+        assert!(h.size.is_none());
+        assert!(h.flags.is_none());
+        let info = h.debug_info.unwrap();
+        assert_eq!(info.wire_key, b"foo".to_vec());
+        assert_eq!(info.size, Some(63));
+    }
+
+    #[test]
+    fn test_debug_response_no_key() {
+        let data = b"ME \r\n";
+        let h = impl_parse_header(data, 0, data.len()).unwrap();
+        assert_eq!(h.end_pos, data.len());
+        assert!(h.response_type.is_none());
+        assert!(h.debug_info.is_none());
+    }
+
     #[test]
     fn test_noop_response() {
         let data = b"MN\r\n";
@@ -0,0 +1,128 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::constants::DEFAULT_KEY_RECORDER_SIZE;
+use crate::encode_key::extract_key;
+use crate::sync::lock;
+
+/// Original keys by the wire key they were hashed to, keeping the most
+/// recently recorded `max_entries`.
+pub struct KeyRecords {
+    max_entries: usize,
+    /// Original key and sequence number of its last record, by wire key.
+    keys: HashMap<Vec<u8>, (Vec<u8>, u64)>,
+    /// Wire keys with the sequence number of each record, oldest first. A
+    /// key recorded again is pushed again; its older entries are stale and
+    /// skipped.
+    order: VecDeque<(Vec<u8>, u64)>,
+    next_seq: u64,
+}
+
+impl KeyRecords {
+    pub fn new(max_entries: usize) -> Self {
+        KeyRecords {
+            max_entries,
+            keys: HashMap::new(),
+            order: VecDeque::new(),
+            next_seq: 0,
+        }
+    }
+
+    fn is_current(keys: &HashMap<Vec<u8>, (Vec<u8>, u64)>, wire_key: &[u8], seq: u64) -> bool {
+        keys.get(wire_key)
+            .is_some_and(|&(_, current)| current == seq)
+    }
+
+    pub fn record(&mut self, wire_key: &[u8], key: &[u8]) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some(entry) = self.keys.get_mut(wire_key) {
+            entry.1 = seq;
+        } else {
+            if self.keys.len() >= self.max_entries {
+                while let Some((oldest, oldest_seq)) = self.order.pop_front() {
+                    if Self::is_current(&self.keys, &oldest, oldest_seq) {
+                        self.keys.remove(&oldest);
+                        break;
+                    }
+                }
+            }
+            self.keys.insert(wire_key.to_vec(), (key.to_vec(), seq));
+        }
+        self.order.push_back((wire_key.to_vec(), seq));
+        // Keys recorded over and over would grow it unbounded otherwise
+        if self.order.len() > 2 * self.max_entries {
+            let keys = &self.keys;
+            self.order
+                .retain(|(wire_key, seq)| Self::is_current(keys, wire_key, *seq));
+        }
+    }
+
+    pub fn lookup(&self, wire_key: &[u8]) -> Option<&[u8]> {
+        self.keys.get(wire_key).map(|(key, _)| key.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.order.clear();
+    }
+}
+
+/// Remembers the original key of hashed keys sent through the
+/// MemcacheSocket it is attached to with `MemcacheSocket.set_key_recorder()`,
+/// to map hashes seen on the server back to keys. Bounded: the keys
+/// least recently sent are dropped first. Thread-safe.
+#[pyclass(frozen)]
+pub struct KeyRecorder {
+    records: Mutex<KeyRecords>,
+}
+
+impl KeyRecorder {
+    pub fn records(&self) -> MutexGuard<'_, KeyRecords> {
+        lock(&self.records)
+    }
+}
+
+#[pymethods]
+impl KeyRecorder {
+    #[new]
+    #[pyo3(signature = (max_entries=DEFAULT_KEY_RECORDER_SIZE))]
+    fn new(max_entries: usize) -> PyResult<Self> {
+        if max_entries == 0 {
+            return Err(PyValueError::new_err("max_entries must be positive"));
+        }
+        Ok(KeyRecorder {
+            records: Mutex::new(KeyRecords::new(max_entries)),
+        })
+    }
+
+    /// The original key of `wire_key`, as sent on the wire (base64-encoded
+    /// for binary digests), or None if it wasn't recorded.
+    fn lookup<'py>(
+        &self,
+        py: Python<'py>,
+        wire_key: &Bound<'py, PyAny>,
+    ) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let wire_key = extract_key(wire_key)?;
+        Ok(self
+            .records()
+            .lookup(wire_key)
+            .map(|key| PyBytes::new(py, key)))
+    }
+
+    fn clear(&self) {
+        self.records().clear();
+    }
+
+    fn __len__(&self) -> usize {
+        self.records().len()
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use crate::key_recorder::KeyRecords;
+
+    #[test]
+    fn test_record_and_lookup() {
+        let mut records = KeyRecords::new(10);
+        records.record(b"hash", b"long key");
+        assert_eq!(records.lookup(b"hash"), Some(&b"long key"[..]));
+        assert_eq!(records.lookup(b"other"), None);
+        records.record(b"hash", b"long key");
+        assert_eq!(records.len(), 1);
+        records.clear();
+        assert_eq!(records.len(), 0);
+    }
+
+    #[test]
+    fn test_bounded_oldest_dropped() {
+        let mut records = KeyRecords::new(2);
+        records.record(b"a", b"1");
+        records.record(b"b", b"2");
+        records.record(b"c", b"3");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records.lookup(b"a"), None);
+        assert_eq!(records.lookup(b"b"), Some(&b"2"[..]));
+        assert_eq!(records.lookup(b"c"), Some(&b"3"[..]));
+    }
+
+    #[test]
+    fn test_recorded_again_dropped_last() {
+        let mut records = KeyRecords::new(2);
+        records.record(b"a", b"1");
+        records.record(b"b", b"2");
+        records.record(b"a", b"1");
+        records.record(b"c", b"3");
+        assert_eq!(records.lookup(b"a"), Some(&b"1"[..]));
+        assert_eq!(records.lookup(b"b"), None);
+        assert_eq!(records.lookup(b"c"), Some(&b"3"[..]));
+    }
+
+    #[test]
+    fn test_recorded_many_times() {
+        let mut records = KeyRecords::new(2);
+        for _ in 0..100 {
+            records.record(b"a", b"1");
+            records.record(b"b", b"2");
+        }
+        records.record(b"c", b"3");
+        records.record(b"d", b"4");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records.lookup(b"a"), None);
+        assert_eq!(records.lookup(b"b"), None);
+        assert_eq!(records.lookup(b"d"), Some(&b"4"[..]));
+    }
+}
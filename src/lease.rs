@@ -0,0 +1,88 @@
+use pyo3::prelude::*;
+
+use crate::constants::*;
+use crate::response_flags::ResponseFlags;
+
+/// Classify a lease get response. A value with the win flag (W) means this
+/// client must (re)compute it; with the lost flag (Z) another client already
+/// is. An empty, non-stale value is the placeholder the server vivified on a
+/// miss (N flag).
+pub fn lease_status(flags: Option<&ResponseFlags>, size: Option<u32>) -> u8 {
+    let Some(flags) = flags else {
+        return LEASE_MISS_MUST_COMPUTE;
+    };
+    let is_placeholder = size == Some(0) && !flags.stale;
+    match (flags.win, is_placeholder) {
+        (Some(true), true) => LEASE_MISS_MUST_COMPUTE,
+        (Some(true), false) => LEASE_HIT_STALE_MUST_RECOMPUTE,
+        (Some(false), true) => LEASE_MISS_WAIT_FOR_OTHERS,
+        _ => LEASE_HIT,
+    }
+}
+
+/// Result of `MemcacheSocket.meta_get_lease()`. `status` is one of the
+/// LEASE_* constants. When `must_compute` is set, this client holds the lease
+/// and should write the new value with `fill_lease()`.
+#[pyclass(frozen, skip_from_py_object)]
+pub struct Lease {
+    #[pyo3(get)]
+    pub key: Vec<u8>,
+    #[pyo3(get)]
+    pub status: u8,
+    #[pyo3(get)]
+    pub value: Option<Py<PyAny>>,
+    #[pyo3(get)]
+    pub flags: Option<ResponseFlags>,
+}
+
+#[pymethods]
+impl Lease {
+    #[new]
+    #[pyo3(signature = (key, status, value=None, flags=None))]
+    pub fn new(
+        key: Vec<u8>,
+        status: u8,
+        value: Option<Py<PyAny>>,
+        flags: Option<ResponseFlags>,
+    ) -> Self {
+        Lease {
+            key,
+            status,
+            value,
+            flags,
+        }
+    }
+
+    /// This client won the lease and must (re)compute the value.
+    #[getter]
+    pub fn must_compute(&self) -> bool {
+        self.status == LEASE_HIT_STALE_MUST_RECOMPUTE || self.status == LEASE_MISS_MUST_COMPUTE
+    }
+
+    /// A value is available to serve (possibly stale).
+    #[getter]
+    pub fn has_value(&self) -> bool {
+        self.status == LEASE_HIT || self.status == LEASE_HIT_STALE_MUST_RECOMPUTE
+    }
+
+    #[getter]
+    pub fn cas_token(&self) -> Option<u32> {
+        self.flags.as_ref().and_then(|flags| flags.cas_token)
+    }
+
+    pub fn __repr__(&self) -> String {
+        let status = match self.status {
+            LEASE_HIT => "HIT",
+            LEASE_HIT_STALE_MUST_RECOMPUTE => "HIT_STALE_MUST_RECOMPUTE",
+            LEASE_MISS_MUST_COMPUTE => "MISS_MUST_COMPUTE",
+            LEASE_MISS_WAIT_FOR_OTHERS => "MISS_WAIT_FOR_OTHERS",
+            _ => "UNKNOWN",
+        };
+        format!(
+            "Lease(key={:?}, status={}, cas_token={:?})",
+            String::from_utf8_lossy(&self.key),
+            status,
+            self.cas_token(),
+        )
+    }
+}
@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+    use crate::constants::*;
+    use crate::lease::lease_status;
+    use crate::response_flags::ResponseFlags;
+
+    fn status(header: &[u8], size: u32) -> u8 {
+        let flags = ResponseFlags::parse_flags(header, 4);
+        lease_status(Some(&flags), Some(size))
+    }
+
+    #[test]
+    fn test_hit() {
+        assert_eq!(status(b"VA 3 c1", 3), LEASE_HIT);
+    }
+
+    #[test]
+    fn test_vivified_miss_won() {
+        assert_eq!(status(b"VA 0 c1 W", 0), LEASE_MISS_MUST_COMPUTE);
+    }
+
+    #[test]
+    fn test_vivified_miss_lost() {
+        assert_eq!(status(b"VA 0 c1 Z", 0), LEASE_MISS_WAIT_FOR_OTHERS);
+    }
+
+    #[test]
+    fn test_recache_won() {
+        assert_eq!(status(b"VA 3 c1 W", 3), LEASE_HIT_STALE_MUST_RECOMPUTE);
+    }
+
+    #[test]
+    fn test_stale_won() {
+        assert_eq!(status(b"VA 3 c1 W X", 3), LEASE_HIT_STALE_MUST_RECOMPUTE);
+    }
+
+    #[test]
+    fn test_stale_lost_serves_stale_value() {
+        assert_eq!(status(b"VA 3 c1 X Z", 3), LEASE_HIT);
+    }
+
+    #[test]
+    fn test_stale_empty_value_won() {
+        // An invalidated empty value is still a (stale) hit
+        assert_eq!(status(b"VA 0 c1 W X", 0), LEASE_HIT_STALE_MUST_RECOMPUTE);
+    }
+
+    #[test]
+    fn test_plain_miss() {
+        assert_eq!(lease_status(None, None), LEASE_MISS_MUST_COMPUTE);
+    }
+}
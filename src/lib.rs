@@ -1,20 +1,67 @@
+mod checksum;
+mod checksum_tests;
+mod chunking;
+mod chunking_tests;
+mod cluster_config;
+mod cluster_config_tests;
+mod codecs;
+mod codecs_tests;
+mod compression;
+mod compression_tests;
 mod constants;
+mod debug_info;
+mod debug_info_tests;
+mod discovery;
+mod durations;
+mod durations_tests;
 mod encode_key;
+mod encryption;
+mod encryption_tests;
+mod impl_admin;
+mod impl_admin_tests;
 mod impl_build_cmd;
 mod impl_build_cmd_tests;
 mod impl_parse_header;
 mod impl_parse_header_tests;
+mod key_recorder;
+mod key_recorder_tests;
+mod lease;
+mod lease_tests;
+mod memcache_lock;
+mod memcache_lock_tests;
 mod memcache_socket;
+mod migration;
+mod migration_tests;
+mod namespace;
+mod namespace_tests;
+mod near_cache;
+mod near_cache_tests;
+mod replica_set;
+mod replica_set_tests;
 mod request_flags;
 mod request_flags_tests;
 mod response_flags;
 mod response_flags_tests;
 mod response_types;
+mod response_types_tests;
+mod routing;
+mod routing_tests;
+mod server_capabilities;
+mod server_capabilities_tests;
+mod single_flight;
+mod single_flight_tests;
+mod sync;
+mod sync_tests;
+mod value_pipeline;
+mod value_pipeline_tests;
 pub use constants::*;
+pub use debug_info::DebugInfo;
 use impl_build_cmd::impl_build_cmd;
 use impl_parse_header::impl_parse_header;
+pub use lease::Lease;
 pub use request_flags::RequestFlags;
 pub use response_flags::ResponseFlags;
+pub use server_capabilities::ServerCapabilities;
 
 use std::slice;
 
@@ -23,7 +70,7 @@ use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
 
-use encode_key::extract_key;
+use encode_key::{DEFAULT_KEY_POLICY, KeyPolicy, extract_key};
 
 #[pyfunction]
 #[pyo3(
@@ -58,8 +105,9 @@ pub fn parse_header(
         size=None,
         request_flags=None,
         legacy_size_format=false,
+        key_policy=None,
     ),
-    text_signature = "(cmd: bytes, key: Union[str, bytes], size: Optional[int], request_flags: Optional[RequestFlags], legacy_size_format: bool = False)",
+    text_signature = "(cmd: bytes, key: Union[str, bytes], size: Optional[int], request_flags: Optional[RequestFlags], legacy_size_format: bool = False, key_policy: Optional[KeyPolicy] = None)",
 )]
 pub fn build_cmd<'py>(
     py: Python<'py>,
@@ -68,12 +116,19 @@ pub fn build_cmd<'py>(
     size: Option<u32>,
     request_flags: Option<&RequestFlags>,
     legacy_size_format: bool,
+    key_policy: Option<&KeyPolicy>,
 ) -> PyResult<Bound<'py, PyBytes>> {
     let key = extract_key(key)?;
-    match impl_build_cmd(cmd, key, size, request_flags, legacy_size_format, true) {
-        Some(built) => Ok(PyBytes::new(py, &built.buf)),
-        None => Err(PyValueError::new_err("Key is empty")),
-    }
+    let built = impl_build_cmd(
+        cmd,
+        key,
+        size,
+        request_flags,
+        legacy_size_format,
+        true,
+        key_policy.unwrap_or(&DEFAULT_KEY_POLICY),
+    )?;
+    Ok(PyBytes::new(py, &built.buf))
 }
 
 #[pyfunction]
@@ -81,19 +136,27 @@ pub fn build_cmd<'py>(
     signature = (
         key,
         request_flags=None,
+        key_policy=None,
     ),
-    text_signature = "(key: Union[str, bytes], request_flags: Optional[RequestFlags])",
+    text_signature = "(key: Union[str, bytes], request_flags: Optional[RequestFlags], key_policy: Optional[KeyPolicy] = None)",
 )]
 pub fn build_meta_get<'py>(
     py: Python<'py>,
     key: &Bound<'py, PyAny>,
     request_flags: Option<&RequestFlags>,
+    key_policy: Option<&KeyPolicy>,
 ) -> PyResult<Bound<'py, PyBytes>> {
     let key = extract_key(key)?;
-    match impl_build_cmd(b"mg", key, None, request_flags, false, true) {
-        Some(built) => Ok(PyBytes::new(py, &built.buf)),
-        None => Err(PyValueError::new_err("Key is empty")),
-    }
+    let built = impl_build_cmd(
+        b"mg",
+        key,
+        None,
+        request_flags,
+        false,
+        true,
+        key_policy.unwrap_or(&DEFAULT_KEY_POLICY),
+    )?;
+    Ok(PyBytes::new(py, &built.buf))
 }
 
 #[pyfunction]
@@ -103,8 +166,9 @@ pub fn build_meta_get<'py>(
         size,
         request_flags=None,
         legacy_size_format=false,
+        key_policy=None,
     ),
-    text_signature = "(key: Union[str, bytes], size: int, request_flags: Optional[RequestFlags], legacy_size_format: bool = False)",
+    text_signature = "(key: Union[str, bytes], size: int, request_flags: Optional[RequestFlags], legacy_size_format: bool = False, key_policy: Optional[KeyPolicy] = None)",
 )]
 pub fn build_meta_set<'py>(
     py: Python<'py>,
@@ -112,19 +176,19 @@ pub fn build_meta_set<'py>(
     size: u32,
     request_flags: Option<&RequestFlags>,
     legacy_size_format: bool,
+    key_policy: Option<&KeyPolicy>,
 ) -> PyResult<Bound<'py, PyBytes>> {
     let key = extract_key(key)?;
-    match impl_build_cmd(
+    let built = impl_build_cmd(
         b"ms",
         key,
         Some(size),
         request_flags,
         legacy_size_format,
         true,
-    ) {
-        Some(built) => Ok(PyBytes::new(py, &built.buf)),
-        None => Err(PyValueError::new_err("Key is empty")),
-    }
+        key_policy.unwrap_or(&DEFAULT_KEY_POLICY),
+    )?;
+    Ok(PyBytes::new(py, &built.buf))
 }
 
 #[pyfunction]
@@ -132,19 +196,27 @@ pub fn build_meta_set<'py>(
     signature = (
         key,
         request_flags=None,
+        key_policy=None,
     ),
-    text_signature = "(key: Union[str, bytes], request_flags: Optional[RequestFlags])",
+    text_signature = "(key: Union[str, bytes], request_flags: Optional[RequestFlags], key_policy: Optional[KeyPolicy] = None)",
 )]
 pub fn build_meta_delete<'py>(
     py: Python<'py>,
     key: &Bound<'py, PyAny>,
     request_flags: Option<&RequestFlags>,
+    key_policy: Option<&KeyPolicy>,
 ) -> PyResult<Bound<'py, PyBytes>> {
     let key = extract_key(key)?;
-    match impl_build_cmd(b"md", key, None, request_flags, false, true) {
-        Some(built) => Ok(PyBytes::new(py, &built.buf)),
-        None => Err(PyValueError::new_err("Key is empty")),
-    }
+    let built = impl_build_cmd(
+        b"md",
+        key,
+        None,
+        request_flags,
+        false,
+        true,
+        key_policy.unwrap_or(&DEFAULT_KEY_POLICY),
+    )?;
+    Ok(PyBytes::new(py, &built.buf))
 }
 
 #[pyfunction]
@@ -152,19 +224,141 @@ pub fn build_meta_delete<'py>(
     signature = (
         key,
         request_flags=None,
+        key_policy=None,
     ),
-    text_signature = "(key: Union[str, bytes], request_flags: Optional[RequestFlags])",
+    text_signature = "(key: Union[str, bytes], request_flags: Optional[RequestFlags], key_policy: Optional[KeyPolicy] = None)",
 )]
 pub fn build_meta_arithmetic<'py>(
     py: Python<'py>,
     key: &Bound<'py, PyAny>,
     request_flags: Option<&RequestFlags>,
+    key_policy: Option<&KeyPolicy>,
 ) -> PyResult<Bound<'py, PyBytes>> {
     let key = extract_key(key)?;
-    match impl_build_cmd(b"ma", key, None, request_flags, false, true) {
-        Some(built) => Ok(PyBytes::new(py, &built.buf)),
-        None => Err(PyValueError::new_err("Key is empty")),
-    }
+    let built = impl_build_cmd(
+        b"ma",
+        key,
+        None,
+        request_flags,
+        false,
+        true,
+        key_policy.unwrap_or(&DEFAULT_KEY_POLICY),
+    )?;
+    Ok(PyBytes::new(py, &built.buf))
+}
+
+#[pyfunction]
+#[pyo3(
+    signature = (
+        key,
+        ttl,
+        request_flags=None,
+        key_policy=None,
+    ),
+    text_signature = "(key: Union[str, bytes], ttl: int, request_flags: Optional[RequestFlags], key_policy: Optional[KeyPolicy] = None)",
+)]
+pub fn build_meta_touch<'py>(
+    py: Python<'py>,
+    key: &Bound<'py, PyAny>,
+    ttl: u32,
+    request_flags: Option<&RequestFlags>,
+    key_policy: Option<&KeyPolicy>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let key = extract_key(key)?;
+    let flags = RequestFlags::for_touch(request_flags, ttl, false);
+    let built = impl_build_cmd(
+        b"mg",
+        key,
+        None,
+        Some(&flags),
+        false,
+        true,
+        key_policy.unwrap_or(&DEFAULT_KEY_POLICY),
+    )?;
+    Ok(PyBytes::new(py, &built.buf))
+}
+
+#[pyfunction]
+#[pyo3(
+    signature = (
+        key,
+        key_policy=None,
+    ),
+    text_signature = "(key: Union[str, bytes], key_policy: Optional[KeyPolicy] = None)",
+)]
+pub fn build_meta_debug<'py>(
+    py: Python<'py>,
+    key: &Bound<'py, PyAny>,
+    key_policy: Option<&KeyPolicy>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let key = extract_key(key)?;
+    let built = impl_build_cmd(
+        b"me",
+        key,
+        None,
+        None,
+        false,
+        false,
+        key_policy.unwrap_or(&DEFAULT_KEY_POLICY),
+    )?;
+    Ok(PyBytes::new(py, &built.buf))
+}
+
+#[pyfunction]
+#[pyo3(
+    name = "encode_key",
+    signature = (
+        key,
+        key_policy=None,
+    ),
+    text_signature = "(key: Union[str, bytes], key_policy: Optional[KeyPolicy] = None)",
+)]
+pub fn py_encode_key<'py>(
+    py: Python<'py>,
+    key: &Bound<'py, PyAny>,
+    key_policy: Option<&KeyPolicy>,
+) -> PyResult<(Bound<'py, PyBytes>, bool)> {
+    let key = extract_key(key)?;
+    let encoded = key_policy
+        .unwrap_or(&DEFAULT_KEY_POLICY)
+        .encode(key)
+        .map_err(impl_build_cmd::BuildCmdError::from)?;
+    Ok((PyBytes::new(py, &encoded.value), encoded.is_binary))
+}
+
+#[pyfunction]
+#[pyo3(
+    name = "decode_wire_key",
+    signature = (
+        wire_key,
+        is_binary,
+    ),
+    text_signature = "(wire_key: Union[str, bytes], is_binary: bool)",
+)]
+pub fn py_decode_wire_key<'py>(
+    py: Python<'py>,
+    wire_key: &Bound<'py, PyAny>,
+    is_binary: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let wire_key = extract_key(wire_key)?;
+    let key = encode_key::decode_wire_key(wire_key, is_binary).map_err(PyValueError::new_err)?;
+    Ok(PyBytes::new(py, &key))
+}
+
+#[pyfunction]
+#[pyo3(
+    name = "routing_key",
+    signature = (
+        key,
+    ),
+    text_signature = "(key: Union[str, bytes])",
+)]
+pub fn py_routing_key<'py>(
+    py: Python<'py>,
+    key: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let key = extract_key(key)?;
+    Ok(PyBytes::new(py, routing::routing_key(key)))
 }
 
 #[pymodule(gil_used = false)]
@@ -173,13 +367,41 @@ fn meta_memcache_socket(module: &Bound<'_, PyModule>) -> PyResult<()> {
     // Classes
     module.add_class::<ResponseFlags>()?;
     module.add_class::<RequestFlags>()?;
+    module.add_class::<DebugInfo>()?;
+    module.add_class::<Lease>()?;
+    module.add_class::<ServerCapabilities>()?;
     module.add_class::<memcache_socket::MemcacheSocket>()?;
+    module.add_class::<memcache_lock::MemcacheLock>()?;
+    module.add_class::<near_cache::NearCache>()?;
+    module.add_class::<single_flight::SingleFlight>()?;
+    module.add_class::<KeyPolicy>()?;
+    module.add_class::<key_recorder::KeyRecorder>()?;
+    module.add_class::<replica_set::ReplicaSet>()?;
+    module.add_class::<migration::MigrationClient>()?;
+    module.add_class::<cluster_config::ClusterNode>()?;
+    module.add_class::<cluster_config::ClusterConfig>()?;
+    module.add_class::<discovery::ClusterDiscovery>()?;
     module.add_class::<response_types::Value>()?;
     module.add_class::<response_types::Success>()?;
+    module.add_class::<response_types::Counter>()?;
     module.add_class::<response_types::Miss>()?;
     module.add_class::<response_types::NotStored>()?;
     module.add_class::<response_types::Conflict>()?;
 
+    // Exceptions
+    module.add(
+        "DecryptionError",
+        module.py().get_type::<encryption::DecryptionError>(),
+    )?;
+    module.add(
+        "ChecksumError",
+        module.py().get_type::<checksum::ChecksumError>(),
+    )?;
+    module.add(
+        "ReplicationError",
+        module.py().get_type::<replica_set::ReplicationError>(),
+    )?;
+
     // Functions
     module.add_function(wrap_pyfunction!(parse_header, module)?)?;
     module.add_function(wrap_pyfunction!(build_cmd, module)?)?;
@@ -187,6 +409,11 @@ fn meta_memcache_socket(module: &Bound<'_, PyModule>) -> PyResult<()> {
     module.add_function(wrap_pyfunction!(build_meta_set, module)?)?;
     module.add_function(wrap_pyfunction!(build_meta_delete, module)?)?;
     module.add_function(wrap_pyfunction!(build_meta_arithmetic, module)?)?;
+    module.add_function(wrap_pyfunction!(build_meta_touch, module)?)?;
+    module.add_function(wrap_pyfunction!(build_meta_debug, module)?)?;
+    module.add_function(wrap_pyfunction!(py_encode_key, module)?)?;
+    module.add_function(wrap_pyfunction!(py_decode_wire_key, module)?)?;
+    module.add_function(wrap_pyfunction!(py_routing_key, module)?)?;
 
     // Constants
     module.add("RESPONSE_VALUE", RESPONSE_VALUE)?;
@@ -194,6 +421,7 @@ fn meta_memcache_socket(module: &Bound<'_, PyModule>) -> PyResult<()> {
     module.add("RESPONSE_NOT_STORED", RESPONSE_NOT_STORED)?;
     module.add("RESPONSE_CONFLICT", RESPONSE_CONFLICT)?;
     module.add("RESPONSE_MISS", RESPONSE_MISS)?;
+    module.add("RESPONSE_DEBUG", RESPONSE_DEBUG)?;
     module.add("RESPONSE_NOOP", RESPONSE_NOOP)?;
     module.add("SET_MODE_ADD", SET_MODE_ADD)?;
     module.add("SET_MODE_APPEND", SET_MODE_APPEND)?;
@@ -202,6 +430,46 @@ fn meta_memcache_socket(module: &Bound<'_, PyModule>) -> PyResult<()> {
     module.add("SET_MODE_SET", SET_MODE_SET)?;
     module.add("MA_MODE_INC", MA_MODE_INC)?;
     module.add("MA_MODE_DEC", MA_MODE_DEC)?;
+    module.add("CODEC_MASK", CODEC_MASK)?;
+    module.add("CODEC_STR", CODEC_STR)?;
+    module.add("CODEC_PICKLE", CODEC_PICKLE)?;
+    module.add("CODEC_INT", CODEC_INT)?;
+    module.add("CODEC_BINARY", CODEC_BINARY)?;
+    module.add("CODEC_JSON", CODEC_JSON)?;
+    module.add("COMPRESSION_ZSTD", COMPRESSION_ZSTD)?;
+    module.add("COMPRESSION_LZ4", COMPRESSION_LZ4)?;
+    module.add("COMPRESSION_MASK", COMPRESSION_MASK)?;
+    module.add("ENCRYPTION_AES_GCM", ENCRYPTION_AES_GCM)?;
+    module.add("ENCRYPTION_CHACHA20_POLY1305", ENCRYPTION_CHACHA20_POLY1305)?;
+    module.add("ENCRYPTION_MASK", ENCRYPTION_MASK)?;
+    module.add("ENCRYPTION_KEY_ID_MASK", ENCRYPTION_KEY_ID_MASK)?;
+    module.add("ENCRYPTION_KEY_ID_SHIFT", ENCRYPTION_KEY_ID_SHIFT)?;
+    module.add("CHECKSUM_CRC32C", CHECKSUM_CRC32C)?;
+    module.add("CHECKSUM_XXH3", CHECKSUM_XXH3)?;
+    module.add("CHECKSUM_MASK", CHECKSUM_MASK)?;
+    module.add("CHUNKED", CHUNKED)?;
+    module.add("DEFAULT_CHUNK_SIZE", DEFAULT_CHUNK_SIZE)?;
+    module.add("DEFAULT_NEAR_CACHE_TTL", DEFAULT_NEAR_CACHE_TTL)?;
+    module.add(
+        "DEFAULT_NAMESPACE_REFRESH_INTERVAL",
+        DEFAULT_NAMESPACE_REFRESH_INTERVAL,
+    )?;
+    module.add("DEFAULT_KEY_RECORDER_SIZE", DEFAULT_KEY_RECORDER_SIZE)?;
+    module.add(
+        "DEFAULT_DISCOVERY_POLL_INTERVAL",
+        DEFAULT_DISCOVERY_POLL_INTERVAL,
+    )?;
+    module.add("KEY_HASH_BLAKE2B", KEY_HASH_BLAKE2B)?;
+    module.add("KEY_HASH_MD5", KEY_HASH_MD5)?;
+    module.add("KEY_HASH_SHA1", KEY_HASH_SHA1)?;
+    module.add("KEY_HASH_XXH3", KEY_HASH_XXH3)?;
+    module.add("LEASE_HIT", LEASE_HIT)?;
+    module.add(
+        "LEASE_HIT_STALE_MUST_RECOMPUTE",
+        LEASE_HIT_STALE_MUST_RECOMPUTE,
+    )?;
+    module.add("LEASE_MISS_MUST_COMPUTE", LEASE_MISS_MUST_COMPUTE)?;
+    module.add("LEASE_MISS_WAIT_FOR_OTHERS", LEASE_MISS_WAIT_FOR_OTHERS)?;
     module.add("SERVER_VERSION_AWS_1_6_6", SERVER_VERSION_AWS_1_6_6)?;
     module.add("SERVER_VERSION_STABLE", SERVER_VERSION_STABLE)?;
     Ok(())
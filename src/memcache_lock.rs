@@ -0,0 +1,243 @@
+use std::time::{Duration, Instant};
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::durations::{positive_seconds, seconds};
+use crate::encode_key::extract_key;
+use crate::impl_build_cmd::BuildCmdError;
+use crate::memcache_socket::MemcacheSocket;
+use crate::request_flags::RequestFlags;
+use crate::response_types::*;
+
+const TOKEN_LEN: usize = 16;
+
+/// Hex-encode random bytes into a printable owner token.
+pub fn hex_token(random: &[u8]) -> Vec<u8> {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    random
+        .iter()
+        .flat_map(|&b| [HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]])
+        .collect()
+}
+
+/// Seed for the polling jitter, taken from the random owner token.
+pub fn jitter_seed(random: &[u8]) -> u64 {
+    random.iter().fold(0x9e37_79b9_7f4a_7c15, |seed, &b| {
+        (seed ^ b as u64).rotate_left(8)
+    }) | 1
+}
+
+/// Scale `interval` by a random factor in [0.5, 1.5) so waiting clients
+/// don't poll in lockstep. Advances the xorshift state in `rng`.
+pub fn jittered(rng: &mut u64, interval: Duration) -> Duration {
+    *rng ^= *rng << 13;
+    *rng ^= *rng >> 7;
+    *rng ^= *rng << 17;
+    let factor = 0.5 + (*rng >> 11) as f64 / (1u64 << 53) as f64;
+    interval.mul_f64(factor)
+}
+
+/// Short-lived distributed lock. Acquiring adds the key (`ms ... ME`) with a
+/// random owner token and a TTL, so it expires if the owner dies. Release
+/// and extend are guarded by the CAS returned on acquire, so they fail
+/// instead of touching a lock that expired and was taken by someone else.
+#[pyclass]
+pub struct MemcacheLock {
+    socket: Py<MemcacheSocket>,
+    key: Py<PyAny>,
+    #[pyo3(get)]
+    ttl: u32,
+    poll_interval: Duration,
+    /// Owner token of the current (or last) acquire.
+    token: Option<Vec<u8>>,
+    /// CAS of the lock item while we hold it.
+    cas_token: Option<u32>,
+    rng: u64,
+}
+
+impl MemcacheLock {
+    fn new_token(&mut self) -> PyResult<Vec<u8>> {
+        let mut random = [0u8; TOKEN_LEN];
+        getrandom::getrandom(&mut random)
+            .map_err(|e| PyRuntimeError::new_err(format!("Error generating lock token: {e}")))?;
+        self.rng = jitter_seed(&random);
+        Ok(hex_token(&random))
+    }
+
+    /// Store the token, returning the new CAS on success or None if the
+    /// write was rejected (key exists, or CAS mismatch).
+    fn write_token(
+        &self,
+        py: Python<'_>,
+        token: &[u8],
+        flags: &RequestFlags,
+    ) -> PyResult<Option<u32>> {
+        let response = self.socket.bind(py).try_borrow_mut()?.meta_set(
+            py,
+            self.key.bind(py),
+            token,
+            Some(flags),
+        )?;
+        let response = response.bind(py);
+        if let Ok(success) = response.extract::<PyRef<'_, Success>>() {
+            return match success.flags.cas_token {
+                Some(cas_token) => Ok(Some(cas_token)),
+                None => Err(PyRuntimeError::new_err(
+                    "Server did not return a CAS token for the lock",
+                )),
+            };
+        }
+        if response.is_instance_of::<NotStored>()
+            || response.is_instance_of::<Conflict>()
+            || response.is_instance_of::<Miss>()
+        {
+            return Ok(None);
+        }
+        Err(PyRuntimeError::new_err(format!(
+            "Unexpected response for lock: {response}"
+        )))
+    }
+
+    fn held_cas(&self) -> PyResult<u32> {
+        self.cas_token
+            .ok_or_else(|| PyRuntimeError::new_err("Lock is not acquired"))
+    }
+}
+
+#[pymethods]
+impl MemcacheLock {
+    #[new]
+    #[pyo3(signature = (socket, key, ttl=30, poll_interval=0.05))]
+    fn new(
+        socket: Py<MemcacheSocket>,
+        key: &Bound<'_, PyAny>,
+        ttl: u32,
+        poll_interval: f64,
+    ) -> PyResult<Self> {
+        if extract_key(key)?.is_empty() {
+            return Err(BuildCmdError::EmptyKey.into());
+        }
+        if ttl == 0 {
+            return Err(PyValueError::new_err("ttl must be positive"));
+        }
+        Ok(MemcacheLock {
+            socket,
+            key: key.clone().unbind(),
+            ttl,
+            poll_interval: positive_seconds("poll_interval", poll_interval)
+                .map_err(PyValueError::new_err)?,
+            token: None,
+            cas_token: None,
+            rng: 1,
+        })
+    }
+
+    /// Try to take the lock. With `blocking`, poll with jitter until it is
+    /// free or `timeout` seconds have passed (forever if None). Returns True
+    /// if the lock was acquired.
+    #[pyo3(signature = (blocking=true, timeout=None))]
+    pub fn acquire(
+        &mut self,
+        py: Python<'_>,
+        blocking: bool,
+        timeout: Option<f64>,
+    ) -> PyResult<bool> {
+        if self.cas_token.is_some() {
+            return Err(PyRuntimeError::new_err("Lock is already acquired"));
+        }
+        let deadline = match timeout {
+            Some(timeout) => {
+                Some(Instant::now() + seconds("timeout", timeout).map_err(PyValueError::new_err)?)
+            }
+            None => None,
+        };
+        let token = self.new_token()?;
+        let flags = RequestFlags::for_lock(self.ttl, None);
+        loop {
+            if let Some(cas_token) = self.write_token(py, &token, &flags)? {
+                self.token = Some(token);
+                self.cas_token = Some(cas_token);
+                return Ok(true);
+            }
+            if !blocking {
+                return Ok(false);
+            }
+            let mut delay = jittered(&mut self.rng, self.poll_interval);
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Ok(false);
+                }
+                delay = delay.min(remaining);
+            }
+            py.detach(|| std::thread::sleep(delay));
+            py.check_signals()?;
+        }
+    }
+
+    /// Release the lock. Returns False if it was no longer ours (expired,
+    /// or taken over by another owner), in which case nothing is deleted.
+    /// If the delete raises, the lock is still held and release() can be
+    /// retried.
+    pub fn release(&mut self, py: Python<'_>) -> PyResult<bool> {
+        let cas_token = self.held_cas()?;
+        let flags = RequestFlags::for_cas_delete(cas_token);
+        let response = self.socket.bind(py).try_borrow_mut()?.meta_delete(
+            py,
+            self.key.bind(py),
+            Some(&flags),
+        )?;
+        self.cas_token = None;
+        Ok(response.bind(py).is_instance_of::<Success>())
+    }
+
+    /// Reset the TTL of a held lock to `ttl` (or the lock's ttl). Returns
+    /// False, and drops the lock, if it was no longer ours.
+    #[pyo3(signature = (ttl=None))]
+    pub fn extend(&mut self, py: Python<'_>, ttl: Option<u32>) -> PyResult<bool> {
+        let cas_token = self.held_cas()?;
+        let token = self.token.clone().unwrap_or_default();
+        let flags = RequestFlags::for_lock(ttl.unwrap_or(self.ttl), Some(cas_token));
+        self.cas_token = self.write_token(py, &token, &flags)?;
+        Ok(self.cas_token.is_some())
+    }
+
+    /// True while this instance holds the lock (as of the last operation).
+    #[getter]
+    pub fn locked(&self) -> bool {
+        self.cas_token.is_some()
+    }
+
+    /// Owner token stored in the lock item by the last acquire.
+    #[getter]
+    pub fn token<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        self.token.as_ref().map(|token| PyBytes::new(py, token))
+    }
+
+    pub fn __enter__<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        py: Python<'py>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.acquire(py, true, None)?;
+        Ok(slf)
+    }
+
+    #[pyo3(signature = (*_args))]
+    pub fn __exit__(&mut self, py: Python<'_>, _args: &Bound<'_, PyAny>) -> PyResult<bool> {
+        if self.cas_token.is_some() {
+            self.release(py)?;
+        }
+        Ok(false)
+    }
+
+    pub fn __repr__(&self, py: Python<'_>) -> String {
+        format!(
+            "MemcacheLock(key={}, ttl={}, locked={})",
+            self.key.bind(py),
+            self.ttl,
+            self.locked()
+        )
+    }
+}
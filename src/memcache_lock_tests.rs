@@ -0,0 +1,33 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::memcache_lock::{hex_token, jitter_seed, jittered};
+
+    #[test]
+    fn test_hex_token() {
+        assert_eq!(hex_token(&[0x00, 0x9f, 0xff]), b"009fff");
+        assert_eq!(hex_token(&[]), b"");
+    }
+
+    #[test]
+    fn test_jitter_seed_is_never_zero() {
+        // xorshift gets stuck at 0
+        assert_ne!(jitter_seed(&[]), 0);
+        assert_ne!(jitter_seed(&[0; 16]), 0);
+    }
+
+    #[test]
+    fn test_jittered_range() {
+        let interval = Duration::from_millis(100);
+        let mut rng = jitter_seed(b"seed");
+        let delays: Vec<Duration> = (0..1000).map(|_| jittered(&mut rng, interval)).collect();
+        assert!(
+            delays
+                .iter()
+                .all(|d| *d >= interval / 2 && *d < interval * 3 / 2)
+        );
+        // Not constant
+        assert!(delays.iter().any(|d| *d != delays[0]));
+    }
+}
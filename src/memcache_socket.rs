@@ -1,21 +1,45 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::os::fd::RawFd;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use atoi::FromRadix10Checked;
 use log::warn;
+use memchr::memmem;
 
 use pyo3::BoundObject;
 use pyo3::exceptions::{PyConnectionError, PyTimeoutError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::PyBytes;
+use pyo3::types::{PyBytes, PyDict};
 
+use crate::checksum::{Checksum, ChecksumError};
+use crate::chunking::{Manifest, random_version, validate_chunk_size};
+use crate::cluster_config::{ClusterConfig, parse_config_header};
+use crate::codecs::{CodecRegistry, with_codec};
+use crate::compression::Compression;
 use crate::constants::*;
-use crate::encode_key::extract_key;
+use crate::encode_key::{DEFAULT_KEY_POLICY, KeyPolicy, extract_key};
+use crate::encryption::{DecryptionError, Encryption};
+use crate::impl_admin::{
+    build_flush_all_cmd, build_stats_cmd, build_verbosity_cmd, parse_stat_line, parse_stat_value,
+    split_stat_name,
+};
 use crate::impl_build_cmd::{BuiltCmd, impl_build_cmd};
 use crate::impl_parse_header::{ParsedHeader, impl_parse_header};
+use crate::key_recorder::KeyRecorder;
+use crate::lease::{Lease, lease_status};
+use crate::namespace::Namespace;
+use crate::near_cache::{Cached, NearCache};
 use crate::request_flags::RequestFlags;
 use crate::response_flags::ResponseFlags;
 use crate::response_types::*;
+use crate::server_capabilities::{ServerCapabilities, parse_server_version};
+use crate::single_flight::{Joined, SingleFlight};
+use crate::value_pipeline::{UnpackError, ValuePipeline};
 
 const DEFAULT_BUFFER_SIZE: usize = 4096;
+/// Upper bound for the exponential backoff between cas_update() retries.
+const MAX_CAS_BACKOFF: Duration = Duration::from_secs(1);
 
 /// Convert a Rust pyclass into a `Py<PyAny>` for returning from methods
 /// that return different Python types (union return).
@@ -30,6 +54,24 @@ where
         .unbind())
 }
 
+/// Copy of a shared response for another caller.
+fn clone_result(py: Python<'_>, result: &PyResult<Py<PyAny>>) -> PyResult<Py<PyAny>> {
+    match result {
+        Ok(response) => Ok(response.clone_ref(py)),
+        Err(e) => Err(e.clone_ref(py)),
+    }
+}
+
+/// `header` turned into a Miss, for values that can't be returned.
+fn miss_header(header: ParsedHeader) -> ParsedHeader {
+    ParsedHeader {
+        response_type: Some(RESPONSE_MISS),
+        size: None,
+        flags: None,
+        ..header
+    }
+}
+
 fn socket_err(msg: &str) -> PyErr {
     PyConnectionError::new_err(msg.to_string())
 }
@@ -230,6 +272,21 @@ fn recv_fill(fd: RawFd, buf: &mut [u8], timeout_ms: libc::c_int) -> Result<usize
     Ok(total)
 }
 
+fn unexpected_line(line: &[u8]) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Unexpected response: {}", String::from_utf8_lossy(line)),
+    )
+}
+
+#[inline]
+fn parse_index(value: &[u8]) -> Option<usize> {
+    match usize::from_radix_10_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
 /// Where the value data ended up after recv.
 enum ValueData {
     /// Value is in io.buf starting at this position, for `size` bytes.
@@ -277,13 +334,18 @@ impl SocketIO {
         self.read = remaining;
     }
 
-    fn get_single_header(&mut self) -> Result<ParsedHeader, std::io::Error> {
+    /// Rewind or compact the buffer before reading the next response.
+    fn prepare_buffer(&mut self) {
         if self.read == self.pos {
             self.read = 0;
             self.pos = 0;
         } else if self.pos > self.reset_buffer_size {
             self.reset_buffer();
         }
+    }
+
+    fn get_single_header(&mut self) -> Result<ParsedHeader, std::io::Error> {
+        self.prepare_buffer();
 
         loop {
             if self.read != self.pos
@@ -319,6 +381,113 @@ impl SocketIO {
         self.get_single_header()
     }
 
+    /// Read the next `\r\n`-terminated line and return its range in buf,
+    /// excluding the ENDL. Used for text protocol responses (VERSION,
+    /// STAT ... END) that impl_parse_header doesn't frame.
+    fn get_line(&mut self) -> Result<(usize, usize), std::io::Error> {
+        if self.noop_expected > 0 {
+            self.read_until_noop_header()?;
+        }
+        self.prepare_buffer();
+
+        let mut search_start = self.pos;
+        loop {
+            if let Some(n) = memmem::find(&self.buf[search_start..self.read], ENDL) {
+                let start = self.pos;
+                let end = search_start + n;
+                self.pos = end + ENDL_LEN;
+                return Ok((start, end));
+            }
+            // The last byte might be the '\r' of a split ENDL
+            search_start = self.read.saturating_sub(1).max(self.pos);
+            if self.read == self.buffer_size {
+                if self.pos == 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Response line exceeds buffer size",
+                    ));
+                }
+                search_start -= self.pos;
+                self.reset_buffer();
+            }
+            let n = self.recv_into_buffer()?;
+            if n == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "Bad response. Socket might have closed unexpectedly",
+                ));
+            }
+        }
+    }
+
+    /// Read a `VERSION <version>` response.
+    fn read_version(&mut self) -> Result<String, std::io::Error> {
+        let (start, end) = self.get_line()?;
+        let line = &self.buf[start..end];
+        match line.strip_prefix(b"VERSION ") {
+            Some(version) => Ok(String::from_utf8_lossy(version).into_owned()),
+            None => Err(unexpected_line(line)),
+        }
+    }
+
+    /// Read `STAT <name> <value>` lines until `END`.
+    #[allow(clippy::type_complexity)]
+    fn read_stats(&mut self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, std::io::Error> {
+        let mut stats = Vec::new();
+        loop {
+            let (start, end) = self.get_line()?;
+            let line = &self.buf[start..end];
+            if line == b"END" {
+                return Ok(stats);
+            }
+            match parse_stat_line(line) {
+                Some((name, value)) => stats.push((name.to_vec(), value.to_vec())),
+                None => return Err(unexpected_line(line)),
+            }
+        }
+    }
+
+    /// Read the response to a `mg` returning the value and client_flag, and
+    /// decode the chunk manifest stored there. None if the item is missing
+    /// or not chunked.
+    fn read_manifest(&mut self) -> Result<Option<Manifest>, std::io::Error> {
+        let (header, value_data) = self.get_response_with_value()?;
+        let chunked = header
+            .flags
+            .as_ref()
+            .and_then(|flags| flags.client_flag)
+            .is_some_and(|flag| flag & CHUNKED != 0);
+        let size = header.size.unwrap_or(0) as usize;
+        Ok(match value_data {
+            Some(ValueData::InBuffer(start)) if chunked => {
+                Manifest::decode(&self.buf[start..start + size])
+            }
+            Some(ValueData::Allocated(data)) if chunked => Manifest::decode(&data),
+            _ => None,
+        })
+    }
+
+    /// Read a `CONFIG cluster <flags> <size>` header, its data block and
+    /// `END`.
+    fn read_cluster_config(&mut self) -> Result<ClusterConfig, std::io::Error> {
+        let (start, end) = self.get_line()?;
+        let line = &self.buf[start..end];
+        let Some(size) = parse_config_header(line) else {
+            return Err(unexpected_line(line));
+        };
+        let config = match self.ensure_value(size)? {
+            ValueData::InBuffer(start) => ClusterConfig::parse(&self.buf[start..start + size]),
+            ValueData::Allocated(data) => ClusterConfig::parse(&data),
+        }
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let (start, end) = self.get_line()?;
+        let line = &self.buf[start..end];
+        if line != b"END" {
+            return Err(unexpected_line(line));
+        }
+        Ok(config)
+    }
+
     fn send_cmd(&mut self, cmd: &[u8], with_noop: bool) -> Result<(), std::io::Error> {
         if with_noop {
             send_iovecs(self.fd, &[cmd, NOOP_CMD], self.timeout_ms)?;
@@ -434,6 +603,48 @@ impl SocketIO {
         };
         Ok((header, value_data))
     }
+
+    /// Read the responses to `count` pipelined quiet-mode commands, tagged
+    /// with their index as opaque, up to the terminating MN. Misses are
+    /// suppressed by the server and left as None. Values are copied out of
+    /// the buffer since it is reused by the next read. A response without a
+    /// valid opaque is an error, raised once the MN is read so the
+    /// connection stays in sync.
+    #[allow(clippy::type_complexity)]
+    fn read_quiet_responses(
+        &mut self,
+        count: usize,
+    ) -> Result<Vec<Option<(ParsedHeader, Option<ValueData>)>>, std::io::Error> {
+        let mut responses: Vec<Option<(ParsedHeader, Option<ValueData>)>> =
+            (0..count).map(|_| None).collect();
+        loop {
+            let (header, value_data) = self.get_response_with_value()?;
+            if header.response_type == Some(RESPONSE_NOOP) {
+                return Ok(responses);
+            }
+            let index = header
+                .flags
+                .as_ref()
+                .and_then(|flags| flags.opaque.as_deref())
+                .and_then(parse_index)
+                .filter(|&index| index < count);
+            let Some(index) = index else {
+                while self.get_response_with_value()?.0.response_type != Some(RESPONSE_NOOP) {}
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Pipelined response with missing or invalid opaque",
+                ));
+            };
+            let value_data = match value_data {
+                Some(ValueData::InBuffer(start)) => {
+                    let size = header.size.unwrap_or(0) as usize;
+                    Some(ValueData::Allocated(self.buf[start..start + size].to_vec()))
+                }
+                other => other,
+            };
+            responses[index] = Some((header, value_data));
+        }
+    }
 }
 
 #[pyclass]
@@ -442,6 +653,20 @@ pub struct MemcacheSocket {
     /// Hold a reference to the Python socket to prevent GC.
     _conn: Py<PyAny>,
     version: u8,
+    capabilities: ServerCapabilities,
+    codecs: CodecRegistry,
+    pipeline: ValuePipeline,
+    checksum_failures: u64,
+    /// Values larger than this are stored in chunks, see set_chunking().
+    chunk_size: Option<usize>,
+    near_cache: Option<Py<NearCache>>,
+    single_flight: Option<Py<SingleFlight>>,
+    namespace: Option<Namespace>,
+    key_policy: KeyPolicy,
+    key_recorder: Option<Py<KeyRecorder>>,
+    /// One entry per send_meta_* awaiting get_response(): the key for gets,
+    /// so their values can be decrypted, None otherwise.
+    pending_keys: VecDeque<Option<Py<PyAny>>>,
 }
 
 /// Private helpers
@@ -453,18 +678,528 @@ impl MemcacheSocket {
         size: Option<u32>,
         request_flags: Option<&RequestFlags>,
     ) -> PyResult<BuiltCmd> {
-        let key = extract_key(key)?;
-        let legacy_size_format = cmd == b"ms" && self.version == SERVER_VERSION_AWS_1_6_6;
         let allow_no_reply_flag = cmd != b"mg";
-        impl_build_cmd(
+        self.build_cmd_impl(cmd, key, size, request_flags, allow_no_reply_flag)
+    }
+
+    /// build_cmd, but `allow_no_reply_flag` can enable quiet mode on `mg`
+    /// for pipelined batches that read up to a NOOP.
+    fn build_cmd_impl<'py>(
+        &self,
+        cmd: &[u8],
+        key: &'py Bound<'py, PyAny>,
+        size: Option<u32>,
+        request_flags: Option<&RequestFlags>,
+        allow_no_reply_flag: bool,
+    ) -> PyResult<BuiltCmd> {
+        let key = self.namespaced_key(key)?;
+        self.build_raw_cmd(cmd, &key, size, request_flags, allow_no_reply_flag)
+    }
+
+    /// build_cmd_impl for a key as stored, without the namespace applied.
+    fn build_raw_cmd(
+        &self,
+        cmd: &[u8],
+        key: &[u8],
+        size: Option<u32>,
+        request_flags: Option<&RequestFlags>,
+        allow_no_reply_flag: bool,
+    ) -> PyResult<BuiltCmd> {
+        if let Some(flag) = request_flags.and_then(|f| f.unsupported_flag(&self.capabilities)) {
+            return Err(PyValueError::new_err(format!(
+                "Flag {flag} is not supported by the server"
+            )));
+        }
+        let legacy_size_format = cmd == b"ms" && self.capabilities.legacy_size_format;
+        let built = impl_build_cmd(
             cmd,
             key,
             size,
             request_flags,
             legacy_size_format,
             allow_no_reply_flag,
-        )
-        .ok_or_else(|| PyValueError::new_err("Key is empty"))
+            &self.key_policy,
+        )?;
+        if built.binary_key && !self.capabilities.binary_keys {
+            return Err(PyValueError::new_err(
+                "Binary and hashed keys are not supported by the server",
+            ));
+        }
+        if let Some(recorder) = &self.key_recorder
+            && self.key_policy.needs_hashing(key)
+        {
+            recorder.get().records().record(built.wire_key(), key);
+        }
+        Ok(built)
+    }
+
+    /// `key` as stored: with the namespace, if any, prepended.
+    fn namespaced_key<'py>(&self, key: &'py Bound<'py, PyAny>) -> PyResult<Cow<'py, [u8]>> {
+        let key = extract_key(key)?;
+        match &self.namespace {
+            Some(namespace) => namespace
+                .apply(key)
+                .map(Cow::Owned)
+                .map_err(PyValueError::new_err),
+            None => Ok(Cow::Borrowed(key)),
+        }
+    }
+
+    /// Reload the generation of a versioned namespace if it is due. Only
+    /// blocking commands call this: pipelined ones can't read a response
+    /// out of turn, so they use the generation loaded last.
+    fn refresh_namespace(&mut self, py: Python<'_>) -> PyResult<()> {
+        if self
+            .namespace
+            .as_ref()
+            .is_some_and(|namespace| namespace.needs_refresh(Instant::now()))
+        {
+            self.update_generation(py, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Add `delta` to the generation counter of a versioned namespace and
+    /// use the result. A missing counter is created at the current Unix
+    /// time, so one that was evicted doesn't restart at a generation
+    /// already used.
+    fn update_generation(&mut self, py: Python<'_>, delta: u64) -> PyResult<u64> {
+        let Some(counter_key) = self.namespace.as_ref().and_then(Namespace::counter_key) else {
+            return Err(PyValueError::new_err("Namespace is not versioned"));
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let flags = RequestFlags::for_counter(None, MA_MODE_INC, delta, Some(now), 0);
+        let cmd = self.build_raw_cmd(b"ma", &counter_key, None, Some(&flags), false)?;
+        let io = &mut self.io;
+        let (header, value_data) = py
+            .detach(|| {
+                io.send_cmd(&cmd.buf, false)?;
+                io.get_response_with_value()
+            })
+            .map_err(|e| socket_err_io("Error loading namespace generation", e))?;
+        let generation = match (header.response_type, header.size) {
+            (Some(RESPONSE_VALUE), Some(size)) => {
+                parse_counter(self.value_slice(size, &value_data))
+            }
+            _ => None,
+        }
+        .ok_or_else(|| {
+            socket_err(&format!(
+                "Unexpected response to namespace generation: {:?}",
+                header.response_type
+            ))
+        })?;
+        if let Some(namespace) = self.namespace.as_mut() {
+            namespace.set_generation(generation, Instant::now());
+        }
+        Ok(generation)
+    }
+
+    /// The value bytes read by get_response_with_value().
+    fn value_slice<'a>(&'a self, size: u32, value_data: &'a Option<ValueData>) -> &'a [u8] {
+        match value_data {
+            Some(ValueData::InBuffer(start)) => &self.io.buf[*start..*start + size as usize],
+            Some(ValueData::Allocated(data)) => data.as_slice(),
+            None => b"",
+        }
+    }
+
+    /// Flags to use for a read instead of `request_flags`, if any: with the
+    /// value pipeline or chunking enabled the client_flag must come back to
+    /// unpack values and spot chunk manifests.
+    fn read_flags(&self, request_flags: Option<&RequestFlags>) -> Option<RequestFlags> {
+        if !(self.pipeline.is_enabled() || self.chunk_size.is_some())
+            || request_flags.is_some_and(RequestFlags::returns_client_flag)
+        {
+            return None;
+        }
+        Some(RequestFlags::with_return_client_flag(request_flags))
+    }
+
+    /// Run a value through the value pipeline before storing it. Returns the
+    /// bytes to send and, if the client_flag changes, the flags to use
+    /// instead of `request_flags`. Runs with the GIL released.
+    fn pack_value<'a>(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        value: &'a [u8],
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<(Cow<'a, [u8]>, Option<RequestFlags>)> {
+        if !self.pipeline.is_enabled() {
+            return Ok((Cow::Borrowed(value), None));
+        }
+        if RequestFlags::appends(request_flags) {
+            self.pipeline
+                .check_append()
+                .map_err(PyValueError::new_err)?;
+        }
+        let key = self.namespaced_key(key)?;
+        let key = key.as_ref();
+        let client_flag = request_flags.and_then(RequestFlags::client_flag);
+        let pipeline = &self.pipeline;
+        let (value, packed_flag) = py
+            .detach(|| pipeline.pack(key, value, client_flag))
+            .map_err(PyValueError::new_err)?;
+        let flags = (packed_flag != client_flag)
+            .then(|| RequestFlags::with_client_flag(request_flags, packed_flag.unwrap_or(0)));
+        Ok((value, flags))
+    }
+
+    /// Undo pack_value() on a response to a read of `key` (None for
+    /// pipelined reads), clearing the pipeline bits from its client_flag.
+    /// A value that fails to decrypt or verify raises DecryptionError or
+    /// ChecksumError, or becomes a Miss if so configured. Runs with the GIL
+    /// released.
+    fn unpack_value(
+        &mut self,
+        py: Python<'_>,
+        key: Option<&Bound<'_, PyAny>>,
+        mut header: ParsedHeader,
+        value_data: Option<ValueData>,
+    ) -> PyResult<(ParsedHeader, Option<ValueData>)> {
+        // Only values were packed: flag-only hits (HD) are returned as-is
+        if header.response_type != Some(RESPONSE_VALUE) {
+            return Ok((header, value_data));
+        }
+        let size = header.size.unwrap_or(0);
+        let flags = header.flags.as_ref();
+        let client_flag = flags.and_then(|flags| flags.client_flag).unwrap_or(0);
+        // The empty placeholder a lease get vivified on a miss was never packed
+        let is_placeholder =
+            size == 0 && flags.is_some_and(|flags| flags.win.is_some() && !flags.stale);
+        if !self.pipeline.is_packed(client_flag) || is_placeholder {
+            return Ok((header, value_data));
+        }
+        let key = key.map(|key| self.namespaced_key(key)).transpose()?;
+        let key = key.as_deref();
+        let data = self.value_slice(size, &value_data);
+        let pipeline = &self.pipeline;
+        let (error, miss_on_error) = match py.detach(|| pipeline.unpack(key, data, client_flag)) {
+            Ok((value, client_flag)) => {
+                if let Some(flags) = header.flags.as_mut() {
+                    flags.client_flag = Some(client_flag);
+                }
+                header.size = Some(value.len() as u32);
+                return Ok((header, Some(ValueData::Allocated(value))));
+            }
+            Err(UnpackError::Decompression(e)) => {
+                return Err(PyValueError::new_err(format!(
+                    "Error decompressing value: {e}"
+                )));
+            }
+            Err(UnpackError::Decryption(e)) => (
+                DecryptionError::new_err(format!("Error decrypting value: {e}")),
+                pipeline
+                    .encryption
+                    .as_ref()
+                    .is_some_and(Encryption::miss_on_error),
+            ),
+            Err(UnpackError::Checksum(e)) => {
+                self.checksum_failures += 1;
+                (
+                    ChecksumError::new_err(format!("Error verifying value: {e}")),
+                    pipeline
+                        .checksum
+                        .as_ref()
+                        .is_some_and(Checksum::miss_on_error),
+                )
+            }
+        };
+        if !miss_on_error {
+            return Err(error);
+        }
+        warn!("{error}, returning a miss");
+        Ok((miss_header(header), None))
+    }
+
+    /// Send a built `mg` and read, resolve and unpack its response. With a
+    /// near cache, misses are cached if so configured and values if
+    /// `cache_values` is set.
+    fn fetch_value(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        cmd: &[u8],
+        cache: Option<&Py<NearCache>>,
+        cache_values: bool,
+    ) -> PyResult<Py<PyAny>> {
+        let io = &mut self.io;
+        let (header, value_data) = py
+            .detach(|| {
+                io.send_cmd(cmd, false)?;
+                io.get_response_with_value()
+            })
+            .map_err(|e| socket_err_io("Error in meta_get", e))?;
+        if let Some(cache) = cache
+            && header.response_type == Some(RESPONSE_MISS)
+        {
+            cache
+                .get()
+                .store()
+                .insert_miss(&self.namespaced_key(key)?, Instant::now());
+        }
+        let (header, value_data) = self.resolve_chunks(py, Some(key), header, value_data)?;
+        let (header, value_data) = self.unpack_value(py, Some(key), header, value_data)?;
+        if let Some(cache) = cache.filter(|_| cache_values)
+            && header.response_type == Some(RESPONSE_VALUE)
+            && let Some(flags) = header
+                .flags
+                .as_ref()
+                .filter(|flags| !flags.stale && flags.win.is_none())
+        {
+            let value = self.value_slice(header.size.unwrap_or(0), &value_data);
+            cache
+                .get()
+                .store()
+                .insert(&self.namespaced_key(key)?, value, flags, Instant::now());
+        }
+        self.make_response(py, header, value_data)
+    }
+
+    /// Drop `key` from the near cache, if any, on a write to it.
+    fn invalidate_near_cache(&self, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        if let Some(cache) = &self.near_cache {
+            cache.get().store().remove(&self.namespaced_key(key)?);
+        }
+        Ok(())
+    }
+
+    /// Whether `value` must be stored in chunks.
+    fn needs_chunking(&self, value: &[u8]) -> bool {
+        self.chunk_size
+            .is_some_and(|chunk_size| value.len() > chunk_size)
+    }
+
+    /// Whether meta_set may store `value` in chunks, which send_meta_set
+    /// can't: if it may be above the chunk size once packed.
+    pub(crate) fn may_chunk(&self, value: &[u8]) -> bool {
+        self.chunk_size
+            .is_some_and(|chunk_size| value.len() + self.pipeline.max_overhead() > chunk_size)
+    }
+
+    /// Whether deletes must go through meta_delete, to delete the chunks
+    /// of chunked values too.
+    pub(crate) fn is_chunking(&self) -> bool {
+        self.chunk_size.is_some()
+    }
+
+    /// Store a value larger than the chunk size: first the chunks, in a
+    /// single pipelined write, then the manifest at `key` with
+    /// `request_flags`, so readers never find a manifest without its
+    /// chunks. If a chunk can't be stored its response is returned and the
+    /// manifest is not written. The manifest being replaced is read in the
+    /// same pipeline, and its chunks deleted once the new one is stored.
+    fn meta_set_chunked(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        value: &[u8],
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        // The manifest would be concatenated to the stored value
+        if RequestFlags::appends(request_flags) {
+            return Err(PyValueError::new_err(
+                "Values that need chunking can't be appended or prepended",
+            ));
+        }
+        let chunk_size = self.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+        let manifest = random_version()
+            .and_then(|version| Manifest::new(value, chunk_size, version))
+            .map_err(PyValueError::new_err)?;
+        let chunk_flags = RequestFlags::for_chunk_set(request_flags);
+        let manifest_get = RequestFlags::drops_chunks(request_flags)
+            .then(|| self.build_cmd(b"mg", key, None, Some(&RequestFlags::for_value_get(None))))
+            .transpose()?;
+        let raw_key = extract_key(key)?;
+        let chunks: Vec<&[u8]> = value.chunks(chunk_size).collect();
+        let cmds = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let chunk_key = PyBytes::new(py, &manifest.chunk_key(raw_key, index as u64));
+                let cmd = self.build_cmd(
+                    b"ms",
+                    chunk_key.as_any(),
+                    Some(chunk.len() as u32),
+                    Some(&chunk_flags),
+                )?;
+                Ok(cmd.buf)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let io = &mut self.io;
+        let (old_manifest, failed) = py
+            .detach(|| {
+                if let Some(manifest_get) = &manifest_get {
+                    io.send_cmd(&manifest_get.buf, false)?;
+                }
+                for (cmd, chunk) in cmds.iter().zip(&chunks) {
+                    io.send_cmd_with_value(cmd, chunk, false)?;
+                }
+                let old_manifest = match manifest_get {
+                    Some(_) => io.read_manifest()?,
+                    None => None,
+                };
+                let mut failed = None;
+                for _ in 0..cmds.len() {
+                    let (header, _) = io.get_response_with_value()?;
+                    if failed.is_none() && header.response_type != Some(RESPONSE_SUCCESS) {
+                        failed = Some(header);
+                    }
+                }
+                Ok((old_manifest, failed))
+            })
+            .map_err(|e| socket_err_io("Error storing chunks", e))?;
+        if let Some(header) = failed {
+            // Without a manifest, the stored chunks would never be read
+            self.delete_chunks(py, key, &manifest)?;
+            return self.make_response(py, header, None);
+        }
+        let client_flag = request_flags
+            .and_then(RequestFlags::client_flag)
+            .unwrap_or(0)
+            | CHUNKED;
+        let flags = RequestFlags::with_client_flag(request_flags, client_flag);
+        let response = self.meta_set_value(py, key, &manifest.encode(), Some(&flags))?;
+        if !response.bind(py).is_instance_of::<Success>() {
+            // e.g. NotStored in add mode or Conflict on a CAS mismatch
+            self.delete_chunks(py, key, &manifest)?;
+        } else if let Some(old_manifest) = old_manifest {
+            self.delete_chunks(py, key, &old_manifest)?;
+        }
+        Ok(response)
+    }
+
+    /// Keys of the chunks described by `manifest` for the value at `key`.
+    fn chunk_keys<'py>(
+        py: Python<'py>,
+        key: &Bound<'py, PyAny>,
+        manifest: &Manifest,
+    ) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        let raw_key = extract_key(key)?;
+        Ok((0..manifest.chunk_count())
+            .map(|index| PyBytes::new(py, &manifest.chunk_key(raw_key, index)).into_any())
+            .collect())
+    }
+
+    /// Delete the chunks described by `manifest`, of a replaced, deleted or
+    /// unstored value, in a single round trip. Chunks that can't be deleted
+    /// are logged and left to expire.
+    fn delete_chunks(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        manifest: &Manifest,
+    ) -> PyResult<()> {
+        let chunk_keys = Self::chunk_keys(py, key, manifest)?;
+        let responses = self.quiet_many(py, b"md", &chunk_keys, &RequestFlags::default())?;
+        // Quiet deletes only answer failures
+        let failed = responses
+            .iter()
+            .filter(|response| response.is_some())
+            .count();
+        if failed > 0 {
+            warn!("{failed} chunks of {key} could not be deleted");
+        }
+        Ok(())
+    }
+
+    /// Replace a chunk manifest read for `key` (None for pipelined reads)
+    /// by the value reassembled from its chunks, fetched in a single round
+    /// trip, clearing CHUNKED from its client_flag. Missing, truncated or
+    /// corrupted chunks make it a Miss.
+    fn resolve_chunks(
+        &mut self,
+        py: Python<'_>,
+        key: Option<&Bound<'_, PyAny>>,
+        mut header: ParsedHeader,
+        value_data: Option<ValueData>,
+    ) -> PyResult<(ParsedHeader, Option<ValueData>)> {
+        if self.chunk_size.is_none() || header.response_type != Some(RESPONSE_VALUE) {
+            return Ok((header, value_data));
+        }
+        let Some(client_flag) = header
+            .flags
+            .as_ref()
+            .and_then(|flags| flags.client_flag)
+            .filter(|&flag| flag & CHUNKED != 0)
+        else {
+            return Ok((header, value_data));
+        };
+        let Some(key) = key else {
+            return Err(PyValueError::new_err(
+                "Chunked values can only be read with meta_get",
+            ));
+        };
+        let manifest = Manifest::decode(self.value_slice(header.size.unwrap_or(0), &value_data));
+        let value = match manifest {
+            Some(manifest) => self.fetch_chunks(py, key, &manifest)?,
+            None => None,
+        };
+        let Some(value) = value else {
+            warn!("Missing or corrupted chunks, returning a miss");
+            return Ok((miss_header(header), None));
+        };
+        if let Some(flags) = header.flags.as_mut() {
+            flags.client_flag = Some(client_flag & !CHUNKED);
+        }
+        header.size = Some(u32::try_from(value.len()).map_err(|_| {
+            PyValueError::new_err(format!(
+                "Chunked value of {} bytes is too large to return",
+                value.len()
+            ))
+        })?);
+        Ok((header, Some(ValueData::Allocated(value))))
+    }
+
+    /// Fetch and join the chunks described by `manifest`. None if any is
+    /// missing or the result does not match the manifest.
+    fn fetch_chunks(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        manifest: &Manifest,
+    ) -> PyResult<Option<Vec<u8>>> {
+        let chunk_keys = Self::chunk_keys(py, key, manifest)?;
+        let responses = self.quiet_many(py, b"mg", &chunk_keys, &RequestFlags::for_chunk_get())?;
+        let mut value = Vec::new();
+        for response in responses {
+            match response {
+                Some((header, chunk)) if header.response_type == Some(RESPONSE_VALUE) => {
+                    value.extend_from_slice(self.value_slice(header.size.unwrap_or(0), &chunk));
+                }
+                _ => return Ok(None),
+            }
+        }
+        Ok(manifest.verify(&value).then_some(value))
+    }
+
+    /// Send a meta set of an already packed value and return the response.
+    fn meta_set_value(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        value: &[u8],
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        let cmd = self.build_cmd(b"ms", key, Some(value.len() as u32), request_flags)?;
+        let io = &mut self.io;
+        let result = py.detach(|| {
+            io.send_cmd_with_value(&cmd.buf, value, cmd.no_reply)?;
+            if cmd.no_reply {
+                Ok(CmdResult::NoReply)
+            } else {
+                Ok(CmdResult::Response(io.get_response_with_value()?))
+            }
+        });
+        self.invalidate_near_cache(key)?;
+        let result = result.map_err(|e| socket_err_io("Error in meta_set", e))?;
+        match result {
+            CmdResult::NoReply => Self::success_no_reply(py),
+            CmdResult::Response((header, value_data)) => self.make_response(py, header, value_data),
+        }
     }
 
     /// Convert a parsed header + optional value data into a Python response object.
@@ -482,13 +1217,7 @@ impl MemcacheSocket {
                 let flags = header
                     .flags
                     .ok_or_else(|| socket_err("Value response missing flags"))?;
-                let py_bytes = match value_data {
-                    Some(ValueData::InBuffer(start)) => {
-                        PyBytes::new(py, &self.io.buf[start..start + size as usize])
-                    }
-                    Some(ValueData::Allocated(data)) => PyBytes::new(py, &data),
-                    None => PyBytes::new(py, b""),
-                };
+                let py_bytes = PyBytes::new(py, self.value_slice(size, &value_data));
                 Py::new(py, Value::new(size, flags, Some(py_bytes.into_any().unbind())))
                     .map(|obj| obj.into_any())
             }
@@ -501,6 +1230,12 @@ impl MemcacheSocket {
             Some(RESPONSE_NOT_STORED) => into_py(py, NotStored::new()),
             Some(RESPONSE_CONFLICT) => into_py(py, Conflict::new()),
             Some(RESPONSE_MISS) => into_py(py, Miss::new()),
+            Some(RESPONSE_DEBUG) => {
+                let info = header
+                    .debug_info
+                    .ok_or_else(|| socket_err("Debug response missing info"))?;
+                into_py(py, info)
+            }
             _ => Err(socket_err(&format!(
                 "Unknown response code: {:?}",
                 header.response_type
@@ -508,30 +1243,131 @@ impl MemcacheSocket {
         }
     }
 
-    /// Create a Success response with empty flags (for no_reply commands).
-    fn success_no_reply(py: Python<'_>) -> PyResult<Py<PyAny>> {
-        let flags = ResponseFlags {
-            cas_token: None,
-            fetched: None,
-            last_access: None,
-            ttl: None,
-            client_flag: None,
-            win: None,
-            stale: false,
-            size: None,
-            opaque: None,
-        };
-        into_py(py, Success::new(flags))
+    /// Like make_response, but a Value response of a meta arithmetic command
+    /// is returned as a Counter with the value parsed into an integer.
+    fn make_arithmetic_response(
+        &self,
+        py: Python<'_>,
+        header: ParsedHeader,
+        value_data: Option<ValueData>,
+    ) -> PyResult<Py<PyAny>> {
+        if header.response_type != Some(RESPONSE_VALUE) {
+            return self.make_response(py, header, value_data);
+        }
+        let size = header
+            .size
+            .ok_or_else(|| socket_err("Value response missing size"))?;
+        let flags = header
+            .flags
+            .ok_or_else(|| socket_err("Value response missing flags"))?;
+        let digits = self.value_slice(size, &value_data);
+        let value = parse_counter(digits).ok_or_else(|| {
+            socket_err(&format!(
+                "Invalid counter value: {:?}",
+                String::from_utf8_lossy(digits)
+            ))
+        })?;
+        Py::new(py, Counter::new(value, flags)).map(|obj| obj.into_any())
     }
-}
 
-#[pymethods]
-impl MemcacheSocket {
-    #[new]
-    #[pyo3(signature = (conn, buffer_size=DEFAULT_BUFFER_SIZE, version=SERVER_VERSION_STABLE))]
-    pub fn new(conn: &Bound<'_, PyAny>, buffer_size: usize, version: u8) -> PyResult<Self> {
-        let fd: RawFd = conn.call_method0("fileno")?.extract()?;
-        let timeout_ms = get_timeout_ms(conn)?;
+    /// Shared implementation of meta_incr / meta_decr.
+    #[allow(clippy::too_many_arguments)]
+    fn meta_counter(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        mode: u8,
+        delta: u64,
+        initial_value: Option<u64>,
+        initial_ttl: u32,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        let flags =
+            RequestFlags::for_counter(request_flags, mode, delta, initial_value, initial_ttl);
+        self.meta_arithmetic(py, key, Some(&flags))
+    }
+
+    /// Send a quiet `cmd` (`mg` or `md`) per key followed by a NOOP, in a
+    /// single write, and return the responses in key order (None for those
+    /// the server suppressed: misses, and successful deletes).
+    #[allow(clippy::type_complexity)]
+    fn quiet_many(
+        &mut self,
+        py: Python<'_>,
+        cmd: &[u8],
+        keys: &[Bound<'_, PyAny>],
+        request_flags: &RequestFlags,
+    ) -> PyResult<Vec<Option<(ParsedHeader, Option<ValueData>)>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut buf: Vec<u8> = Vec::with_capacity(keys.len() * 64 + NOOP_CMD.len());
+        for (index, key) in keys.iter().enumerate() {
+            let flags = request_flags.quiet_with_index(index);
+            let cmd = self.build_cmd_impl(cmd, key, None, Some(&flags), true)?;
+            buf.extend_from_slice(&cmd.buf);
+        }
+        buf.extend_from_slice(NOOP_CMD);
+        let count = keys.len();
+        let io = &mut self.io;
+        py.detach(|| {
+            io.send_cmd(&buf, false)?;
+            io.read_quiet_responses(count)
+        })
+        .map_err(|e| socket_err_io("Error in pipelined quiet commands", e))
+    }
+
+    /// Convert raw STAT entries into a dict. Per-id stats (slabs, items,
+    /// conns) are nested under their integer id.
+    fn make_stats<'py>(
+        py: Python<'py>,
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let stats = PyDict::new(py);
+        for (name, value) in entries {
+            let value = parse_stat_value(&value);
+            match split_stat_name(&name) {
+                (Some(id), field) => {
+                    let group = match stats.get_item(id)? {
+                        Some(group) => group.cast_into::<PyDict>()?,
+                        None => {
+                            let group = PyDict::new(py);
+                            stats.set_item(id, &group)?;
+                            group
+                        }
+                    };
+                    group.set_item(String::from_utf8_lossy(field), value)?;
+                }
+                (None, field) => stats.set_item(String::from_utf8_lossy(field), value)?,
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Create a Success response with empty flags (for no_reply commands).
+    fn success_no_reply(py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let flags = ResponseFlags {
+            cas_token: None,
+            fetched: None,
+            last_access: None,
+            ttl: None,
+            client_flag: None,
+            win: None,
+            stale: false,
+            size: None,
+            opaque: None,
+        };
+        into_py(py, Success::new(flags))
+    }
+}
+
+#[pymethods]
+impl MemcacheSocket {
+    #[new]
+    #[pyo3(signature = (conn, buffer_size=DEFAULT_BUFFER_SIZE, version=SERVER_VERSION_STABLE))]
+    pub fn new(conn: &Bound<'_, PyAny>, buffer_size: usize, version: u8) -> PyResult<Self> {
+        let fd: RawFd = conn.call_method0("fileno")?.extract()?;
+        let timeout_ms = get_timeout_ms(conn)?;
 
         // Set SO_RCVBUF — failure is non-fatal (kernel may reject the size)
         let recv_buf_size: libc::c_int = buffer_size as libc::c_int;
@@ -566,6 +1402,17 @@ impl MemcacheSocket {
             },
             _conn: conn.clone().unbind(),
             version,
+            capabilities: ServerCapabilities::from_server_version(version),
+            codecs: CodecRegistry::default(),
+            pipeline: ValuePipeline::default(),
+            checksum_failures: 0,
+            chunk_size: None,
+            near_cache: None,
+            single_flight: None,
+            namespace: None,
+            key_policy: KeyPolicy::default(),
+            key_recorder: None,
+            pending_keys: VecDeque::new(),
         })
     }
 
@@ -577,6 +1424,28 @@ impl MemcacheSocket {
         self.version
     }
 
+    pub fn get_capabilities(&self) -> ServerCapabilities {
+        self.capabilities.clone()
+    }
+
+    /// Ask the server for its version and select the matching capability
+    /// profile. Returns the closest SERVER_VERSION_* constant.
+    pub fn detect_version(&mut self, py: Python<'_>) -> PyResult<u8> {
+        let server_version = self.version(py)?;
+        self.capabilities = match parse_server_version(&server_version) {
+            Some(version) => ServerCapabilities::from_version_tuple(version),
+            None => {
+                warn!(
+                    "Unrecognized server version {:?}, assuming latest capabilities",
+                    server_version
+                );
+                ServerCapabilities::from_server_version(SERVER_VERSION_STABLE)
+            }
+        };
+        self.version = self.capabilities.server_version();
+        Ok(self.version)
+    }
+
     pub fn set_socket(&mut self, conn: &Bound<'_, PyAny>) -> PyResult<()> {
         self.io.fd = conn.call_method0("fileno")?.extract()?;
         self.io.timeout_ms = get_timeout_ms(conn)?;
@@ -584,6 +1453,7 @@ impl MemcacheSocket {
         self.io.pos = 0;
         self.io.read = 0;
         self.io.noop_expected = 0;
+        self.pending_keys.clear();
         Ok(())
     }
 
@@ -593,6 +1463,7 @@ impl MemcacheSocket {
         self.io.pos = 0;
         self.io.read = 0;
         self.io.noop_expected = 0;
+        self.pending_keys.clear();
         Ok(())
     }
 
@@ -610,13 +1481,19 @@ impl MemcacheSocket {
     }
 
     /// Read and parse the next response, including value data for Value responses.
-    /// For Value responses, `.value` is set to the raw bytes from the wire.
+    /// For Value responses, `.value` is set to the raw bytes from the wire,
+    /// unpacked by the value pipeline using the key of the matching
+    /// send_meta_get(). Responses to sendall() are not matched to keys.
     /// Releases the GIL during socket I/O.
     pub fn get_response(&mut self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let key = self.pending_keys.pop_front().flatten();
+        let key = key.as_ref().map(|key| key.bind(py));
         let io = &mut self.io;
         let (header, value_data) = py
             .detach(|| io.get_response_with_value())
             .map_err(|e| socket_err_io("Error reading response", e))?;
+        let (header, value_data) = self.resolve_chunks(py, None, header, value_data)?;
+        let (header, value_data) = self.unpack_value(py, key, header, value_data)?;
         self.make_response(py, header, value_data)
     }
 
@@ -634,7 +1511,8 @@ impl MemcacheSocket {
         key: &Bound<'_, PyAny>,
         request_flags: Option<&RequestFlags>,
     ) -> PyResult<()> {
-        let cmd = self.build_cmd(b"mg", key, None, request_flags)?;
+        let read_flags = self.read_flags(request_flags);
+        let cmd = self.build_cmd(b"mg", key, None, read_flags.as_ref().or(request_flags))?;
         if cmd.no_reply {
             return Err(socket_err(
                 "internal error: build_cmd produced no_reply=true for mg command",
@@ -643,6 +1521,7 @@ impl MemcacheSocket {
         let io = &mut self.io;
         py.detach(|| io.send_cmd(&cmd.buf, false))
             .map_err(|e| socket_err_io("Error sending meta get", e))?;
+        self.pending_keys.push_back(Some(key.clone().unbind()));
         Ok(())
     }
 
@@ -657,10 +1536,21 @@ impl MemcacheSocket {
         value: &[u8],
         request_flags: Option<&RequestFlags>,
     ) -> PyResult<()> {
+        let (value, packed_flags) = self.pack_value(py, key, value, request_flags)?;
+        if self.needs_chunking(&value) {
+            return Err(PyValueError::new_err(
+                "Values above the chunk size can only be stored with meta_set",
+            ));
+        }
+        let request_flags = packed_flags.as_ref().or(request_flags);
         let cmd = self.build_cmd(b"ms", key, Some(value.len() as u32), request_flags)?;
+        self.invalidate_near_cache(key)?;
         let io = &mut self.io;
-        py.detach(|| io.send_cmd_with_value(&cmd.buf, value, cmd.no_reply))
+        py.detach(|| io.send_cmd_with_value(&cmd.buf, &value, cmd.no_reply))
             .map_err(|e| socket_err_io("Error sending meta set", e))?;
+        if !cmd.no_reply {
+            self.pending_keys.push_back(None);
+        }
         Ok(())
     }
 
@@ -674,9 +1564,13 @@ impl MemcacheSocket {
         request_flags: Option<&RequestFlags>,
     ) -> PyResult<()> {
         let cmd = self.build_cmd(b"md", key, None, request_flags)?;
+        self.invalidate_near_cache(key)?;
         let io = &mut self.io;
         py.detach(|| io.send_cmd(&cmd.buf, cmd.no_reply))
             .map_err(|e| socket_err_io("Error sending meta delete", e))?;
+        if !cmd.no_reply {
+            self.pending_keys.push_back(None);
+        }
         Ok(())
     }
 
@@ -690,9 +1584,13 @@ impl MemcacheSocket {
         request_flags: Option<&RequestFlags>,
     ) -> PyResult<()> {
         let cmd = self.build_cmd(b"ma", key, None, request_flags)?;
+        self.invalidate_near_cache(key)?;
         let io = &mut self.io;
         py.detach(|| io.send_cmd(&cmd.buf, cmd.no_reply))
             .map_err(|e| socket_err_io("Error sending meta arithmetic", e))?;
+        if !cmd.no_reply {
+            self.pending_keys.push_back(None);
+        }
         Ok(())
     }
 
@@ -702,27 +1600,74 @@ impl MemcacheSocket {
 
     /// Send a meta get command and return the response.
     /// The entire send + recv happens in a single GIL-released block.
-    #[pyo3(signature = (key, request_flags=None))]
+    /// With `near_cache`, values are served from and cached in the near
+    /// cache attached with set_near_cache(), if any; the value and its TTL
+    /// are always fetched then. Misses of keys the near cache caches misses
+    /// for are served from it regardless. Reads that may vivify or recache
+    /// (N or R flags) always go to the server. With a SingleFlight attached,
+    /// concurrent identical calls share one request.
+    #[pyo3(signature = (key, request_flags=None, near_cache=false))]
     pub fn meta_get(
         &mut self,
         py: Python<'_>,
         key: &Bound<'_, PyAny>,
         request_flags: Option<&RequestFlags>,
+        near_cache: bool,
     ) -> PyResult<Py<PyAny>> {
-        let cmd = self.build_cmd(b"mg", key, None, request_flags)?;
+        self.refresh_namespace(py)?;
+        let namespaced_key = self.namespaced_key(key)?;
+        let cache = match &self.near_cache {
+            _ if RequestFlags::bypasses_near_cache(request_flags) => None,
+            Some(cache) if near_cache || cache.get().store().caches_misses(&namespaced_key) => {
+                Some(cache.clone_ref(py))
+            }
+            _ => None,
+        };
+        if let Some(cache) = &cache {
+            let cached = cache
+                .get()
+                .store()
+                .get(&namespaced_key, Instant::now(), near_cache);
+            match cached {
+                Some(Cached::Value(value, flags)) => {
+                    let py_bytes = PyBytes::new(py, &value);
+                    let value = Value::new(
+                        value.len() as u32,
+                        flags,
+                        Some(py_bytes.into_any().unbind()),
+                    );
+                    return Py::new(py, value).map(|obj| obj.into_any());
+                }
+                Some(Cached::Miss) => return into_py(py, Miss::new()),
+                None => {}
+            }
+        }
+        let cache_flags =
+            (near_cache && cache.is_some()).then(|| RequestFlags::for_near_cache(request_flags));
+        let request_flags = cache_flags.as_ref().or(request_flags);
+        let read_flags = self.read_flags(request_flags);
+        let cmd = self.build_cmd(b"mg", key, None, read_flags.as_ref().or(request_flags))?;
         if cmd.no_reply {
             return Err(socket_err(
                 "internal error: build_cmd produced no_reply=true for mg command",
             ));
         }
-        let io = &mut self.io;
-        let (header, value_data) = py
-            .detach(|| {
-                io.send_cmd(&cmd.buf, false)?;
-                io.get_response_with_value()
-            })
-            .map_err(|e| socket_err_io("Error in meta_get", e))?;
-        self.make_response(py, header, value_data)
+        let cache = cache.as_ref();
+        let Some(single_flight) = self.single_flight.as_ref().map(|sf| sf.clone_ref(py)) else {
+            return self.fetch_value(py, key, &cmd.buf, cache, near_cache);
+        };
+        match single_flight.get().flights.join(&cmd.buf) {
+            Joined::Leader(leader) => {
+                let response = self.fetch_value(py, key, &cmd.buf, cache, near_cache);
+                leader.finish(clone_result(py, &response));
+                response
+            }
+            Joined::Follower(call) => {
+                py.detach(|| call.wait());
+                call.with_result(|result| result.map(|result| clone_result(py, result)))
+                    .unwrap_or_else(|| self.fetch_value(py, key, &cmd.buf, cache, near_cache))
+            }
+        }
     }
 
     /// Send a meta set command with value and return the response.
@@ -736,26 +1681,19 @@ impl MemcacheSocket {
         value: &[u8],
         request_flags: Option<&RequestFlags>,
     ) -> PyResult<Py<PyAny>> {
-        let cmd = self.build_cmd(b"ms", key, Some(value.len() as u32), request_flags)?;
-        let io = &mut self.io;
-        let result = py
-            .detach(|| {
-                io.send_cmd_with_value(&cmd.buf, value, cmd.no_reply)?;
-                if cmd.no_reply {
-                    Ok(CmdResult::NoReply)
-                } else {
-                    Ok(CmdResult::Response(io.get_response_with_value()?))
-                }
-            })
-            .map_err(|e| socket_err_io("Error in meta_set", e))?;
-        match result {
-            CmdResult::NoReply => Self::success_no_reply(py),
-            CmdResult::Response((header, value_data)) => self.make_response(py, header, value_data),
+        self.refresh_namespace(py)?;
+        let (value, packed_flags) = self.pack_value(py, key, value, request_flags)?;
+        let request_flags = packed_flags.as_ref().or(request_flags);
+        if self.needs_chunking(&value) {
+            return self.meta_set_chunked(py, key, &value, request_flags);
         }
+        self.meta_set_value(py, key, &value, request_flags)
     }
 
     /// Send a meta delete command and return the response.
     /// For no_reply commands, sends with NOOP and returns Success immediately.
+    /// With chunking enabled, the manifest is read in the same round trip
+    /// and the chunks of a deleted chunked value are deleted too.
     #[pyo3(signature = (key, request_flags=None))]
     pub fn meta_delete(
         &mut self,
@@ -763,48 +1701,718 @@ impl MemcacheSocket {
         key: &Bound<'_, PyAny>,
         request_flags: Option<&RequestFlags>,
     ) -> PyResult<Py<PyAny>> {
+        self.refresh_namespace(py)?;
         let cmd = self.build_cmd(b"md", key, None, request_flags)?;
+        let manifest_get = (self.chunk_size.is_some() && RequestFlags::drops_chunks(request_flags))
+            .then(|| self.build_cmd(b"mg", key, None, Some(&RequestFlags::for_value_get(None))))
+            .transpose()?;
+        let io = &mut self.io;
+        let result = py.detach(|| {
+            if let Some(manifest_get) = &manifest_get {
+                io.send_cmd(&manifest_get.buf, false)?;
+            }
+            io.send_cmd(&cmd.buf, cmd.no_reply)?;
+            let old_manifest = match manifest_get {
+                Some(_) => io.read_manifest()?,
+                None => None,
+            };
+            if cmd.no_reply {
+                Ok((old_manifest, CmdResult::NoReply))
+            } else {
+                Ok((
+                    old_manifest,
+                    CmdResult::Response(io.get_response_with_value()?),
+                ))
+            }
+        });
+        self.invalidate_near_cache(key)?;
+        let (old_manifest, result) =
+            result.map_err(|e| socket_err_io("Error in meta_delete", e))?;
+        let response = match result {
+            CmdResult::NoReply => Self::success_no_reply(py)?,
+            CmdResult::Response((header, value_data)) => {
+                self.make_response(py, header, value_data)?
+            }
+        };
+        if let Some(old_manifest) = old_manifest
+            && response.bind(py).is_instance_of::<Success>()
+        {
+            self.delete_chunks(py, key, &old_manifest)?;
+        }
+        Ok(response)
+    }
+
+    /// Send a meta arithmetic command and return the response.
+    /// With return_value, the new value is returned as a Counter.
+    /// For no_reply commands, sends with NOOP and returns Success immediately.
+    #[pyo3(signature = (key, request_flags=None))]
+    pub fn meta_arithmetic(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.refresh_namespace(py)?;
+        let cmd = self.build_cmd(b"ma", key, None, request_flags)?;
+        let io = &mut self.io;
+        let result = py.detach(|| {
+            io.send_cmd(&cmd.buf, cmd.no_reply)?;
+            if cmd.no_reply {
+                Ok(CmdResult::NoReply)
+            } else {
+                Ok(CmdResult::Response(io.get_response_with_value()?))
+            }
+        });
+        self.invalidate_near_cache(key)?;
+        let result = result.map_err(|e| socket_err_io("Error in meta_arithmetic", e))?;
+        match result {
+            CmdResult::NoReply => Self::success_no_reply(py),
+            CmdResult::Response((header, value_data)) => {
+                self.make_arithmetic_response(py, header, value_data)
+            }
+        }
+    }
+
+    /// Increment a counter by `delta` and return a Counter with the new value.
+    /// With `initial_value`, a missing key is created with that value and
+    /// `initial_ttl`; otherwise a missing key returns Miss.
+    #[pyo3(signature = (key, delta=1, initial_value=None, initial_ttl=0, request_flags=None))]
+    pub fn meta_incr(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        delta: u64,
+        initial_value: Option<u64>,
+        initial_ttl: u32,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.meta_counter(
+            py,
+            key,
+            MA_MODE_INC,
+            delta,
+            initial_value,
+            initial_ttl,
+            request_flags,
+        )
+    }
+
+    /// Decrement a counter by `delta` and return a Counter with the new value.
+    /// memcached clamps decrements at 0. See meta_incr for `initial_value`.
+    #[pyo3(signature = (key, delta=1, initial_value=None, initial_ttl=0, request_flags=None))]
+    pub fn meta_decr(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        delta: u64,
+        initial_value: Option<u64>,
+        initial_ttl: u32,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.meta_counter(
+            py,
+            key,
+            MA_MODE_DEC,
+            delta,
+            initial_value,
+            initial_ttl,
+            request_flags,
+        )
+    }
+
+    /// Update the TTL of a key without fetching its value.
+    /// Returns True if the key exists, False on a miss.
+    #[pyo3(signature = (key, ttl, request_flags=None))]
+    pub fn meta_touch(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        ttl: u32,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<bool> {
+        self.refresh_namespace(py)?;
+        let flags = RequestFlags::for_touch(request_flags, ttl, false);
+        let cmd = self.build_cmd(b"mg", key, None, Some(&flags))?;
+        let io = &mut self.io;
+        let (header, _) = py
+            .detach(|| {
+                io.send_cmd(&cmd.buf, false)?;
+                io.get_response_with_value()
+            })
+            .map_err(|e| socket_err_io("Error in meta_touch", e))?;
+        match header.response_type {
+            Some(RESPONSE_SUCCESS) | Some(RESPONSE_VALUE) => Ok(true),
+            Some(RESPONSE_MISS) => Ok(false),
+            other => Err(socket_err(&format!(
+                "Unexpected response to touch: {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Fetch a value and update its TTL in one round trip.
+    /// Returns Value on hit, or Miss if the key does not exist.
+    #[pyo3(signature = (key, ttl, request_flags=None))]
+    pub fn meta_get_and_touch(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        ttl: u32,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        let flags = RequestFlags::for_touch(request_flags, ttl, true);
+        self.meta_get(py, key, Some(&flags), false)
+    }
+
+    /// Fetch a value with thundering-herd protection. A miss vivifies a
+    /// placeholder for `lease_ttl` seconds and, like a TTL below
+    /// `recache_ttl` or a value marked stale by invalidate(), hands the
+    /// recompute to a single client. Returns a Lease; if `must_compute`
+    /// is set, write the new value back with fill_lease().
+    #[pyo3(signature = (key, lease_ttl, recache_ttl=None, request_flags=None))]
+    pub fn meta_get_lease(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        lease_ttl: u32,
+        recache_ttl: Option<u32>,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Lease> {
+        self.refresh_namespace(py)?;
+        let flags = RequestFlags::for_lease(request_flags, lease_ttl, recache_ttl);
+        let flags = self.read_flags(Some(&flags)).unwrap_or(flags);
+        let cmd = self.build_cmd(b"mg", key, None, Some(&flags))?;
+        let io = &mut self.io;
+        let (header, value_data) = py
+            .detach(|| {
+                io.send_cmd(&cmd.buf, false)?;
+                io.get_response_with_value()
+            })
+            .map_err(|e| socket_err_io("Error in meta_get_lease", e))?;
+        let (header, value_data) = self.resolve_chunks(py, Some(key), header, value_data)?;
+        let (header, value_data) = self.unpack_value(py, Some(key), header, value_data)?;
+        let key = extract_key(key)?.to_vec();
+        let status = match header.response_type {
+            Some(RESPONSE_VALUE) | Some(RESPONSE_SUCCESS) => {
+                lease_status(header.flags.as_ref(), header.size)
+            }
+            Some(RESPONSE_MISS) => lease_status(None, None),
+            other => {
+                return Err(socket_err(&format!(
+                    "Unexpected response to lease get: {:?}",
+                    other
+                )));
+            }
+        };
+        let value = match header.size {
+            Some(size) if status == LEASE_HIT || status == LEASE_HIT_STALE_MUST_RECOMPUTE => Some(
+                PyBytes::new(py, self.value_slice(size, &value_data))
+                    .into_any()
+                    .unbind(),
+            ),
+            _ => None,
+        };
+        Ok(Lease::new(key, status, value, header.flags))
+    }
+
+    /// Write the value computed for a lease won by meta_get_lease(). The set
+    /// is guarded by the lease CAS, so it returns Conflict if the item was
+    /// changed or invalidated meanwhile. Set the TTL with
+    /// request_flags.cache_ttl; otherwise the value does not expire.
+    #[pyo3(signature = (lease, value, request_flags=None))]
+    pub fn fill_lease(
+        &mut self,
+        py: Python<'_>,
+        lease: &Lease,
+        value: &[u8],
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        if !lease.must_compute() {
+            return Err(PyValueError::new_err(
+                "Lease was not won, the value is being computed by another client",
+            ));
+        }
+        let flags = RequestFlags::for_fill(request_flags, lease.cas_token());
+        let key = PyBytes::new(py, &lease.key);
+        self.meta_set(py, key.as_any(), value, Some(&flags))
+    }
+
+    /// Read-modify-write a key with optimistic locking. Fetches the value
+    /// and its CAS, calls `func(value)` (None on a miss) for the new value
+    /// and writes it back guarded by the CAS, or in add mode on a miss, so a
+    /// concurrent create is detected too. On Conflict, NotStored or Miss (the
+    /// key was deleted meanwhile) the whole cycle is retried up to `max_retries` times, sleeping
+    /// `backoff * 2**attempt` seconds (at most 1) in between. Returns the last write
+    /// response. `request_flags` apply to the write.
+    #[pyo3(signature = (key, func, max_retries=3, request_flags=None, backoff=0.0))]
+    pub fn cas_update(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        func: &Bound<'_, PyAny>,
+        max_retries: u32,
+        request_flags: Option<&RequestFlags>,
+        backoff: f64,
+    ) -> PyResult<Py<PyAny>> {
+        if request_flags.is_some_and(RequestFlags::is_no_reply) {
+            return Err(PyValueError::new_err(
+                "cas_update needs the write result, no_reply is not supported",
+            ));
+        }
+        if !backoff.is_finite() || backoff < 0.0 {
+            return Err(PyValueError::new_err(
+                "backoff must be a non-negative number",
+            ));
+        }
+        self.refresh_namespace(py)?;
+        let read_flags = RequestFlags::for_cas_read();
+        let read_flags = self.read_flags(Some(&read_flags)).unwrap_or(read_flags);
+        let read_cmd = self.build_cmd(b"mg", key, None, Some(&read_flags))?;
+        let mut attempt: u32 = 0;
+        loop {
+            let io = &mut self.io;
+            let (header, value_data) = py
+                .detach(|| {
+                    io.send_cmd(&read_cmd.buf, false)?;
+                    io.get_response_with_value()
+                })
+                .map_err(|e| socket_err_io("Error in cas_update", e))?;
+            let (header, value_data) = self.resolve_chunks(py, Some(key), header, value_data)?;
+            let (header, value_data) = self.unpack_value(py, Some(key), header, value_data)?;
+            let (current, cas_token) = match (header.response_type, header.size) {
+                (Some(RESPONSE_VALUE), Some(size)) => {
+                    let current = PyBytes::new(py, self.value_slice(size, &value_data));
+                    let cas_token = header.flags.as_ref().and_then(|flags| flags.cas_token);
+                    if cas_token.is_none() {
+                        return Err(socket_err("Value response missing CAS token"));
+                    }
+                    (Some(current), cas_token)
+                }
+                (Some(RESPONSE_MISS), _) => (None, None),
+                (other, _) => {
+                    return Err(socket_err(&format!(
+                        "Unexpected response to cas_update get: {:?}",
+                        other
+                    )));
+                }
+            };
+            let new_value: Vec<u8> = func.call1((current,))?.extract()?;
+            let write_flags = RequestFlags::for_fill(request_flags, cas_token);
+            let response = self.meta_set(py, key, &new_value, Some(&write_flags))?;
+            let bound = response.bind(py);
+            let lost_race = bound.is_instance_of::<Conflict>()
+                || bound.is_instance_of::<NotStored>()
+                || bound.is_instance_of::<Miss>();
+            if !lost_race || attempt >= max_retries {
+                return Ok(response);
+            }
+            if backoff > 0.0 {
+                let delay = Duration::try_from_secs_f64(backoff * 2f64.powi(attempt as i32))
+                    .map_or(MAX_CAS_BACKOFF, |delay| delay.min(MAX_CAS_BACKOFF));
+                py.detach(|| std::thread::sleep(delay));
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Mark a key as stale instead of deleting it (`md I`), optionally
+    /// setting its remaining TTL. The next meta_get_lease() wins the
+    /// recompute while other clients keep serving the stale value.
+    #[pyo3(signature = (key, ttl=None, request_flags=None))]
+    pub fn invalidate(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        ttl: Option<u32>,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        let flags = RequestFlags::for_invalidate(request_flags, ttl);
+        self.meta_delete(py, key, Some(&flags))
+    }
+
+    /// Compress values of at least `threshold` bytes with `algorithm`
+    /// (COMPRESSION_ZSTD or COMPRESSION_LZ4) on meta_set, marking them with
+    /// the algorithm's client_flag bit, and decompress marked values on read.
+    /// zstd takes an optional `level` and `dictionary`. Pass None to disable.
+    #[pyo3(signature = (algorithm, threshold=DEFAULT_COMPRESSION_THRESHOLD, level=None, dictionary=None))]
+    pub fn set_compression(
+        &mut self,
+        algorithm: Option<u32>,
+        threshold: usize,
+        level: Option<i32>,
+        dictionary: Option<&[u8]>,
+    ) -> PyResult<()> {
+        self.pipeline.compression = algorithm
+            .map(|algorithm| Compression::new(algorithm, threshold, level, dictionary))
+            .transpose()
+            .map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+
+    /// Encrypt values on meta_set with `algorithm` (ENCRYPTION_AES_GCM or
+    /// ENCRYPTION_CHACHA20_POLY1305) and decrypt them on meta_get. `keys`
+    /// maps key ids (0-255, stored in client_flag) to 32-byte keys; writes
+    /// use `key_id`, by default the highest id. A value that fails to
+    /// decrypt, or isn't encrypted, raises DecryptionError, or is returned
+    /// as a Miss with `miss_on_error`. Pass None to disable.
+    #[pyo3(signature = (keys, key_id=None, algorithm=ENCRYPTION_AES_GCM, miss_on_error=false))]
+    pub fn set_encryption(
+        &mut self,
+        keys: Option<HashMap<u32, Vec<u8>>>,
+        key_id: Option<u32>,
+        algorithm: u32,
+        miss_on_error: bool,
+    ) -> PyResult<()> {
+        self.pipeline.encryption = keys
+            .map(|keys| {
+                Encryption::new(keys.into_iter().collect(), key_id, algorithm, miss_on_error)
+            })
+            .transpose()
+            .map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+
+    /// Append a checksum trailer (CHECKSUM_CRC32C or CHECKSUM_XXH3) to
+    /// values on meta_set, marked with the algorithm's client_flag bit, and
+    /// verify and strip it on read. A mismatch is counted (see
+    /// get_checksum_failures()) and raises ChecksumError, or is returned as a
+    /// Miss with `miss_on_error`. Pass None to disable.
+    #[pyo3(signature = (algorithm, miss_on_error=false))]
+    pub fn set_checksum(&mut self, algorithm: Option<u32>, miss_on_error: bool) -> PyResult<()> {
+        self.pipeline.checksum = algorithm
+            .map(|algorithm| Checksum::new(algorithm, miss_on_error))
+            .transpose()
+            .map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+
+    /// Store values larger than `chunk_size` bytes (after compression and
+    /// encryption) on meta_set as chunk items plus a manifest at the key,
+    /// marked with the CHUNKED client_flag bit, and reassemble them on read.
+    /// Chunks share the manifest's TTL and are deleted with the value they
+    /// belong to; missing or corrupted chunks read as a Miss. Pass None to
+    /// disable.
+    #[pyo3(signature = (chunk_size=Some(DEFAULT_CHUNK_SIZE)))]
+    pub fn set_chunking(&mut self, chunk_size: Option<usize>) -> PyResult<()> {
+        if let Some(chunk_size) = chunk_size {
+            validate_chunk_size(chunk_size).map_err(PyValueError::new_err)?;
+        }
+        self.chunk_size = chunk_size;
+        Ok(())
+    }
+
+    /// Attach a NearCache, shared with other sockets to the same servers if
+    /// desired, or None to detach it. meta_get(near_cache=True) reads go
+    /// through it and writes of a key through this socket invalidate it.
+    #[pyo3(signature = (cache))]
+    pub fn set_near_cache(&mut self, cache: Option<Py<NearCache>>) {
+        self.near_cache = cache;
+    }
+
+    /// Attach a SingleFlight, shared with other sockets to the same servers
+    /// if desired, or None to detach it. Concurrent identical meta_get()
+    /// calls then share a single request and response object.
+    #[pyo3(signature = (single_flight))]
+    pub fn set_single_flight(&mut self, single_flight: Option<Py<SingleFlight>>) {
+        self.single_flight = single_flight;
+    }
+
+    /// Prepend `namespace` to every key, before it is hashed or
+    /// base64-encoded, or None to remove it. With `versioned`, the
+    /// namespace's generation, kept in the `{namespace}generation` counter
+    /// on the server, is folded in as `{namespace}{generation}:{key}` and
+    /// bump_namespace() invalidates every key at once. The generation is
+    /// loaded now and reloaded by blocking commands every
+    /// `refresh_interval` seconds.
+    #[pyo3(signature = (namespace, versioned=false, refresh_interval=DEFAULT_NAMESPACE_REFRESH_INTERVAL))]
+    pub fn set_namespace(
+        &mut self,
+        py: Python<'_>,
+        namespace: Option<&Bound<'_, PyAny>>,
+        versioned: bool,
+        refresh_interval: u32,
+    ) -> PyResult<()> {
+        let Some(namespace) = namespace else {
+            self.namespace = None;
+            return Ok(());
+        };
+        let mut namespace =
+            Namespace::new(extract_key(namespace)?).map_err(PyValueError::new_err)?;
+        if versioned {
+            namespace = namespace.versioned(Duration::from_secs(refresh_interval as u64));
+        }
+        self.namespace = Some(namespace);
+        if versioned && let Err(e) = self.update_generation(py, 0) {
+            self.namespace = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Encode keys with `key_policy` (hash threshold and algorithm, strict
+    /// mode, tag) instead of the default, or None to restore it.
+    #[pyo3(signature = (key_policy))]
+    pub fn set_key_policy(&mut self, key_policy: Option<&KeyPolicy>) {
+        self.key_policy = key_policy.unwrap_or(&DEFAULT_KEY_POLICY).clone();
+    }
+
+    /// Attach a KeyRecorder, shared with other sockets if desired, or None
+    /// to detach it. Hashed keys sent through this socket are recorded with
+    /// their original key (including the namespace).
+    #[pyo3(signature = (recorder))]
+    pub fn set_key_recorder(&mut self, recorder: Option<Py<KeyRecorder>>) {
+        self.key_recorder = recorder;
+    }
+
+    /// Increment the generation of the versioned namespace, so every key
+    /// written before reads as a miss, and return the new generation.
+    /// Other clients pick it up when they next reload it.
+    pub fn bump_namespace(&mut self, py: Python<'_>) -> PyResult<u64> {
+        self.update_generation(py, 1)
+    }
+
+    /// Generation of the versioned namespace in use, None without one.
+    pub fn get_namespace_generation(&self) -> Option<u64> {
+        self.namespace.as_ref().and_then(Namespace::generation)
+    }
+
+    /// Number of values that failed checksum verification.
+    pub fn get_checksum_failures(&self) -> u64 {
+        self.checksum_failures
+    }
+
+    /// Register a Python codec for client_flag codec `code` (up to 255, not a
+    /// built-in CODEC_*). `encode(obj) -> bytes` and `decode(bytes) -> obj`.
+    /// meta_set_object() uses it by default for instances of `types`.
+    #[pyo3(signature = (code, encode, decode, types=None))]
+    pub fn register_codec(
+        &mut self,
+        code: u32,
+        encode: Py<PyAny>,
+        decode: Py<PyAny>,
+        types: Option<Py<PyAny>>,
+    ) -> PyResult<()> {
+        self.codecs.register(code, encode, decode, types)
+    }
+
+    /// Encode `obj` and store it, with the codec code in client_flag. Uses
+    /// `codec`, or picks one from the type: registered types, bytes, str,
+    /// int, then pickle for anything else.
+    #[pyo3(signature = (key, obj, request_flags=None, codec=None))]
+    pub fn meta_set_object(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        obj: &Bound<'_, PyAny>,
+        request_flags: Option<&RequestFlags>,
+        codec: Option<u32>,
+    ) -> PyResult<Py<PyAny>> {
+        let (value, code) = self.codecs.encode(py, obj, codec)?;
+        let client_flag = with_codec(request_flags.and_then(RequestFlags::client_flag), code);
+        let flags = RequestFlags::with_client_flag(request_flags, client_flag);
+        self.meta_set(py, key, value.as_bytes(), Some(&flags))
+    }
+
+    /// Fetch a value and decode it with the codec in its client_flag.
+    /// Returns a Value whose `.value` is the decoded object, or Miss.
+    /// `near_cache` is as in meta_get().
+    #[pyo3(signature = (key, request_flags=None, near_cache=false))]
+    pub fn meta_get_object(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        request_flags: Option<&RequestFlags>,
+        near_cache: bool,
+    ) -> PyResult<Py<PyAny>> {
+        let flags = RequestFlags::for_value_get(request_flags);
+        let response = self.meta_get(py, key, Some(&flags), near_cache)?;
+        // meta_get() may return the same Value to several callers with
+        // single-flight, so decode into a new one.
+        let Ok(value) = response.bind(py).extract::<PyRef<'_, Value>>() else {
+            return Ok(response);
+        };
+        let Some(raw) = value.value.as_ref() else {
+            return Ok(response);
+        };
+        let flags = value.as_super().flags.clone();
+        let decoded = self.codecs.decode(
+            py,
+            raw.bind(py).cast::<PyBytes>()?.as_bytes(),
+            flags.client_flag,
+        )?;
+        Py::new(py, Value::new(value.size, flags, Some(decoded.unbind()))).map(|obj| obj.into_any())
+    }
+
+    /// Send a meta debug command and return the item state.
+    /// Returns a DebugInfo on hit, or Miss if the key does not exist.
+    pub fn meta_debug(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        self.refresh_namespace(py)?;
+        let cmd = self.build_cmd(b"me", key, None, None)?;
+        let io = &mut self.io;
+        let (header, value_data) = py
+            .detach(|| {
+                io.send_cmd(&cmd.buf, false)?;
+                io.get_response_with_value()
+            })
+            .map_err(|e| socket_err_io("Error in meta_debug", e))?;
+        self.make_response(py, header, value_data)
+    }
+
+    // -----------------------------------------------------------------------
+    // Batched: pipelined quiet-mode commands, one write and one read pass
+    // -----------------------------------------------------------------------
+
+    /// Touch many keys in a single round trip. Returns one bool per key,
+    /// True if the key exists.
+    #[pyo3(signature = (keys, ttl, request_flags=None))]
+    pub fn meta_touch_many(
+        &mut self,
+        py: Python<'_>,
+        keys: Vec<Bound<'_, PyAny>>,
+        ttl: u32,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Vec<bool>> {
+        let flags = RequestFlags::for_touch(request_flags, ttl, false);
+        self.refresh_namespace(py)?;
+        let responses = self.quiet_many(py, b"mg", &keys, &flags)?;
+        Ok(responses.iter().map(Option::is_some).collect())
+    }
+
+    /// Fetch and touch many keys in a single round trip. Returns one
+    /// Value or Miss per key.
+    #[pyo3(signature = (keys, ttl, request_flags=None))]
+    pub fn meta_get_and_touch_many(
+        &mut self,
+        py: Python<'_>,
+        keys: Vec<Bound<'_, PyAny>>,
+        ttl: u32,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let flags = RequestFlags::for_touch(request_flags, ttl, true);
+        let flags = self.read_flags(Some(&flags)).unwrap_or(flags);
+        self.refresh_namespace(py)?;
+        let responses = self.quiet_many(py, b"mg", &keys, &flags)?;
+        responses
+            .into_iter()
+            .zip(&keys)
+            .map(|(response, key)| match response {
+                Some((header, value_data)) => {
+                    let (header, value_data) =
+                        self.resolve_chunks(py, Some(key), header, value_data)?;
+                    let (header, value_data) =
+                        self.unpack_value(py, Some(key), header, value_data)?;
+                    self.make_response(py, header, value_data)
+                }
+                None => into_py(py, Miss::new()),
+            })
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+    // Admin commands
+    // -----------------------------------------------------------------------
+
+    /// Return the server version string (e.g. "1.6.21").
+    pub fn version(&mut self, py: Python<'_>) -> PyResult<String> {
+        let io = &mut self.io;
+        py.detach(|| {
+            io.send_cmd(VERSION_CMD, false)?;
+            io.read_version()
+        })
+        .map_err(|e| socket_err_io("Error in version", e))
+    }
+
+    /// Return server statistics as a dict. `group` selects a stats
+    /// sub-command: "slabs", "items", "settings" or "conns".
+    /// Per-id stats are nested under their integer id.
+    #[pyo3(signature = (group=None))]
+    pub fn stats<'py>(
+        &mut self,
+        py: Python<'py>,
+        group: Option<&str>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let cmd = build_stats_cmd(group).ok_or_else(|| {
+            PyValueError::new_err(format!("Unknown stats group: {:?}", group.unwrap_or("")))
+        })?;
+        let io = &mut self.io;
+        let entries = py
+            .detach(|| {
+                io.send_cmd(&cmd, false)?;
+                io.read_stats()
+            })
+            .map_err(|e| socket_err_io("Error in stats", e))?;
+        Self::make_stats(py, entries)
+    }
+
+    /// Return the cluster configuration of an ElastiCache configuration
+    /// endpoint (`config get cluster`): its version and cache nodes.
+    pub fn config_get_cluster(&mut self, py: Python<'_>) -> PyResult<ClusterConfig> {
+        let io = &mut self.io;
+        py.detach(|| {
+            io.send_cmd(CONFIG_GET_CLUSTER_CMD, false)?;
+            io.read_cluster_config()
+        })
+        .map_err(|e| socket_err_io("Error in config_get_cluster", e))
+    }
+
+    /// Invalidate all items, optionally after `delay` seconds, and clear the
+    /// near cache, if any.
+    /// For no_reply commands, sends with NOOP and returns Success immediately.
+    #[pyo3(signature = (delay=None, no_reply=false))]
+    pub fn flush_all(
+        &mut self,
+        py: Python<'_>,
+        delay: Option<u32>,
+        no_reply: bool,
+    ) -> PyResult<Py<PyAny>> {
+        if let Some(cache) = &self.near_cache {
+            cache.get().store().clear();
+        }
+        let cmd = build_flush_all_cmd(delay, no_reply);
         let io = &mut self.io;
         let result = py
             .detach(|| {
-                io.send_cmd(&cmd.buf, cmd.no_reply)?;
-                if cmd.no_reply {
+                io.send_cmd(&cmd, no_reply)?;
+                if no_reply {
                     Ok(CmdResult::NoReply)
                 } else {
                     Ok(CmdResult::Response(io.get_response_with_value()?))
                 }
             })
-            .map_err(|e| socket_err_io("Error in meta_delete", e))?;
+            .map_err(|e| socket_err_io("Error in flush_all", e))?;
         match result {
             CmdResult::NoReply => Self::success_no_reply(py),
             CmdResult::Response((header, value_data)) => self.make_response(py, header, value_data),
         }
     }
 
-    /// Send a meta arithmetic command and return the response.
+    /// Set the server logging verbosity.
     /// For no_reply commands, sends with NOOP and returns Success immediately.
-    #[pyo3(signature = (key, request_flags=None))]
-    pub fn meta_arithmetic(
-        &mut self,
-        py: Python<'_>,
-        key: &Bound<'_, PyAny>,
-        request_flags: Option<&RequestFlags>,
-    ) -> PyResult<Py<PyAny>> {
-        let cmd = self.build_cmd(b"ma", key, None, request_flags)?;
+    #[pyo3(signature = (level, no_reply=false))]
+    pub fn verbosity(&mut self, py: Python<'_>, level: u32, no_reply: bool) -> PyResult<Py<PyAny>> {
+        let cmd = build_verbosity_cmd(level, no_reply);
         let io = &mut self.io;
         let result = py
             .detach(|| {
-                io.send_cmd(&cmd.buf, cmd.no_reply)?;
-                if cmd.no_reply {
+                io.send_cmd(&cmd, no_reply)?;
+                if no_reply {
                     Ok(CmdResult::NoReply)
                 } else {
                     Ok(CmdResult::Response(io.get_response_with_value()?))
                 }
             })
-            .map_err(|e| socket_err_io("Error in meta_arithmetic", e))?;
+            .map_err(|e| socket_err_io("Error in verbosity", e))?;
         match result {
             CmdResult::NoReply => Self::success_no_reply(py),
             CmdResult::Response((header, value_data)) => self.make_response(py, header, value_data),
         }
     }
+
+    /// Ask the server to close the connection, then close the socket.
+    pub fn quit(&mut self, py: Python<'_>) -> PyResult<()> {
+        let io = &mut self.io;
+        py.detach(|| io.send_cmd(QUIT_CMD, false))
+            .map_err(|e| socket_err_io("Error in quit", e))?;
+        self.close(py)
+    }
 }
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use log::warn;
+use pyo3::exceptions::PyOSError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::memcache_socket::MemcacheSocket;
+use crate::request_flags::RequestFlags;
+use crate::response_types::{Miss, Value};
+use crate::sync::lock;
+
+/// How a shadow read compares to the primary read of the same key.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShadowOutcome {
+    Match,
+    /// The primary hit and the shadow missed: not migrated yet.
+    Miss,
+    /// Different values, or a shadow hit on a primary miss: stale.
+    Mismatch,
+}
+
+/// Compare the values of a primary and a shadow read, None being a miss.
+pub fn compare(primary: Option<&[u8]>, shadow: Option<&[u8]>) -> ShadowOutcome {
+    match (primary, shadow) {
+        (Some(_), None) => ShadowOutcome::Miss,
+        (primary, shadow) if primary == shadow => ShadowOutcome::Match,
+        _ => ShadowOutcome::Mismatch,
+    }
+}
+
+/// The value of a response for comparison: Some(bytes) for a Value, None for
+/// a Miss, Err(()) for responses not compared (e.g. a Success without value).
+fn comparable(response: &Bound<'_, PyAny>) -> Result<Option<Vec<u8>>, ()> {
+    if response.is_instance_of::<Miss>() {
+        return Ok(None);
+    }
+    let value = response.extract::<PyRef<'_, Value>>().map_err(|_| ())?;
+    let data = value.value.as_ref().ok_or(())?;
+    let data = data.bind(response.py()).cast::<PyBytes>().map_err(|_| ())?;
+    Ok(Some(data.as_bytes().to_vec()))
+}
+
+/// A shadow get whose response hasn't been read from `socket` yet.
+struct PendingShadow {
+    socket: Py<MemcacheSocket>,
+    key: Py<PyAny>,
+    /// The primary's response, None if the primary read failed.
+    primary: Option<Py<PyAny>>,
+}
+
+/// Wraps the sockets to an old and a new cluster while moving between them.
+/// Writes go to both, the primary (initially the old one) first; reads come
+/// from the primary, optionally shadowed by a read from the other to compare
+/// the values. The shadow response is read on the next call, so it doesn't
+/// add to the read latency. Not thread-safe, like the sockets.
+#[pyclass(frozen)]
+pub struct MigrationClient {
+    old: Py<MemcacheSocket>,
+    new: Py<MemcacheSocket>,
+    flipped: AtomicBool,
+    shadow_reads: AtomicBool,
+    pending_shadow: Mutex<Option<PendingShadow>>,
+    reads: AtomicU64,
+    shadow_reads_done: AtomicU64,
+    shadow_misses: AtomicU64,
+    mismatches: AtomicU64,
+    shadow_errors: AtomicU64,
+    secondary_write_errors: AtomicU64,
+}
+
+impl MigrationClient {
+    fn sockets(&self) -> (&Py<MemcacheSocket>, &Py<MemcacheSocket>) {
+        if self.flipped.load(Ordering::Relaxed) {
+            (&self.new, &self.old)
+        } else {
+            (&self.old, &self.new)
+        }
+    }
+
+    /// Count a failed shadow read. On I/O errors its socket is closed: a
+    /// response arriving late would otherwise be read as the answer to the
+    /// next request. Other errors, e.g. a value that fails to decrypt, come
+    /// once the response was read whole and leave the socket usable.
+    fn shadow_failed(&self, py: Python<'_>, socket: &Py<MemcacheSocket>, e: PyErr) {
+        warn!("Shadow read failed: {e}");
+        self.shadow_errors.fetch_add(1, Ordering::Relaxed);
+        if !e.is_instance_of::<PyOSError>(py) {
+            return;
+        }
+        if let Err(e) = socket.bind(py).borrow_mut().close(py) {
+            warn!("Closing the secondary after a failed shadow read failed: {e}");
+        }
+    }
+
+    /// Read and compare the response to the last shadow get, if pending.
+    fn drain_shadow(&self, py: Python<'_>) {
+        let pending = lock(&self.pending_shadow).take();
+        let Some(pending) = pending else {
+            return;
+        };
+        let result = pending.socket.bind(py).borrow_mut().get_response(py);
+        match result {
+            Ok(shadow) => {
+                if let Some(primary) = &pending.primary {
+                    self.record_shadow(pending.key.bind(py), primary.bind(py), shadow.bind(py));
+                }
+            }
+            Err(e) => self.shadow_failed(py, &pending.socket, e),
+        }
+    }
+
+    fn secondary_write_failed(&self, e: PyErr) {
+        warn!("Write to the secondary cluster failed: {e}");
+        self.secondary_write_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Write with `send` to the primary, then the secondary, before reading
+    /// both responses, so the secondary write overlaps the primary's. The
+    /// primary's response is returned; secondary failures are logged and
+    /// counted. no_reply writes are sent with `write` as there is nothing to
+    /// wait for, and so are those `needs_write` says a socket can only do
+    /// with `write` (e.g. chunked values).
+    fn dual_write(
+        &self,
+        py: Python<'_>,
+        request_flags: Option<&RequestFlags>,
+        needs_write: impl Fn(&MemcacheSocket) -> bool,
+        send: impl Fn(&mut MemcacheSocket) -> PyResult<()>,
+        write: impl Fn(&mut MemcacheSocket) -> PyResult<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        self.drain_shadow(py);
+        let (primary, secondary) = self.sockets();
+        if request_flags.is_some_and(RequestFlags::is_no_reply)
+            || needs_write(&primary.bind(py).borrow())
+            || needs_write(&secondary.bind(py).borrow())
+        {
+            let response = write(&mut primary.bind(py).borrow_mut())?;
+            if let Err(e) = write(&mut secondary.bind(py).borrow_mut()) {
+                self.secondary_write_failed(e);
+            }
+            return Ok(response);
+        }
+        send(&mut primary.bind(py).borrow_mut())?;
+        let secondary_sent = send(&mut secondary.bind(py).borrow_mut())
+            .map_err(|e| self.secondary_write_failed(e))
+            .is_ok();
+        let response = primary.bind(py).borrow_mut().get_response(py);
+        if secondary_sent && let Err(e) = secondary.bind(py).borrow_mut().get_response(py) {
+            self.secondary_write_failed(e);
+        }
+        response
+    }
+
+    fn record_shadow(
+        &self,
+        key: &Bound<'_, PyAny>,
+        primary: &Bound<'_, PyAny>,
+        shadow: &Bound<'_, PyAny>,
+    ) {
+        let (Ok(primary), Ok(shadow)) = (comparable(primary), comparable(shadow)) else {
+            return;
+        };
+        self.shadow_reads_done.fetch_add(1, Ordering::Relaxed);
+        match compare(primary.as_deref(), shadow.as_deref()) {
+            ShadowOutcome::Match => {}
+            ShadowOutcome::Miss => {
+                self.shadow_misses.fetch_add(1, Ordering::Relaxed);
+            }
+            ShadowOutcome::Mismatch => {
+                warn!("Shadow read of {key} doesn't match the primary");
+                self.mismatches.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl MigrationClient {
+    /// Migrate from `old` to `new`, `old` being the primary until flip().
+    /// With `shadow_reads`, reads are also sent to the secondary and
+    /// compared.
+    #[new]
+    #[pyo3(signature = (old, new, shadow_reads=false))]
+    fn new(old: Py<MemcacheSocket>, new: Py<MemcacheSocket>, shadow_reads: bool) -> Self {
+        MigrationClient {
+            old,
+            new,
+            flipped: AtomicBool::new(false),
+            shadow_reads: AtomicBool::new(shadow_reads),
+            pending_shadow: Mutex::new(None),
+            reads: AtomicU64::new(0),
+            shadow_reads_done: AtomicU64::new(0),
+            shadow_misses: AtomicU64::new(0),
+            mismatches: AtomicU64::new(0),
+            shadow_errors: AtomicU64::new(0),
+            secondary_write_errors: AtomicU64::new(0),
+        }
+    }
+
+    /// Make the other socket the primary. Returns whether `new` is now.
+    fn flip(&self) -> bool {
+        !self.flipped.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Whether `new` is the primary.
+    #[getter]
+    fn flipped(&self) -> bool {
+        self.flipped.load(Ordering::Relaxed)
+    }
+
+    #[getter]
+    fn primary(&self, py: Python<'_>) -> Py<MemcacheSocket> {
+        self.sockets().0.clone_ref(py)
+    }
+
+    #[getter]
+    fn secondary(&self, py: Python<'_>) -> Py<MemcacheSocket> {
+        self.sockets().1.clone_ref(py)
+    }
+
+    fn set_shadow_reads(&self, enabled: bool) {
+        self.shadow_reads.store(enabled, Ordering::Relaxed);
+    }
+
+    /// meta_set on both, returning the primary's response. Values that may
+    /// be chunked are written to one socket after the other.
+    #[pyo3(signature = (key, value, request_flags=None))]
+    fn meta_set(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        value: &[u8],
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.dual_write(
+            py,
+            request_flags,
+            |socket| socket.may_chunk(value),
+            |socket| socket.send_meta_set(py, key, value, request_flags),
+            |socket| socket.meta_set(py, key, value, request_flags),
+        )
+    }
+
+    /// meta_delete on both, returning the primary's response. With chunking
+    /// enabled, one socket after the other, to delete the chunks too.
+    #[pyo3(signature = (key, request_flags=None))]
+    fn meta_delete(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.dual_write(
+            py,
+            request_flags,
+            MemcacheSocket::is_chunking,
+            |socket| socket.send_meta_delete(py, key, request_flags),
+            |socket| socket.meta_delete(py, key, request_flags),
+        )
+    }
+
+    /// meta_get from the primary. With shadow reads, the same get is sent
+    /// to the secondary first, so it runs while the primary answers. Its
+    /// response is read on the next call, or flush_shadow_read(), then
+    /// compared to the primary's and dropped.
+    #[pyo3(signature = (key, request_flags=None))]
+    fn meta_get(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.drain_shadow(py);
+        let (primary, secondary) = self.sockets();
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        // A quiet shadow get would send nothing back on a miss
+        let shadow = self.shadow_reads.load(Ordering::Relaxed)
+            && !request_flags.is_some_and(RequestFlags::is_no_reply);
+        let shadow = shadow && {
+            let sent = secondary
+                .bind(py)
+                .borrow_mut()
+                .send_meta_get(py, key, request_flags);
+            sent.map_err(|e| self.shadow_failed(py, secondary, e))
+                .is_ok()
+        };
+        let response = primary
+            .bind(py)
+            .borrow_mut()
+            .meta_get(py, key, request_flags, false);
+        if shadow {
+            *lock(&self.pending_shadow) = Some(PendingShadow {
+                socket: secondary.clone_ref(py),
+                key: key.clone().unbind(),
+                primary: response
+                    .as_ref()
+                    .ok()
+                    .map(|response| response.clone_ref(py)),
+            });
+        }
+        response
+    }
+
+    /// Read the response to the last shadow get, if still pending, e.g.
+    /// before using the secondary directly or reading the stats.
+    fn flush_shadow_read(&self, py: Python<'_>) {
+        self.drain_shadow(py);
+    }
+
+    /// Counters: `reads`, `shadow_reads` compared, `shadow_misses` (primary
+    /// hits the secondary missed), `mismatches` (different values, or
+    /// secondary hits the primary missed), `shadow_errors` and
+    /// `secondary_write_errors`. The miss and mismatch rates are
+    /// `shadow_misses` and `mismatches` over `shadow_reads`. The last shadow
+    /// read only counts once flushed.
+    fn get_stats(&self) -> HashMap<&'static str, u64> {
+        HashMap::from([
+            ("reads", self.reads.load(Ordering::Relaxed)),
+            (
+                "shadow_reads",
+                self.shadow_reads_done.load(Ordering::Relaxed),
+            ),
+            ("shadow_misses", self.shadow_misses.load(Ordering::Relaxed)),
+            ("mismatches", self.mismatches.load(Ordering::Relaxed)),
+            ("shadow_errors", self.shadow_errors.load(Ordering::Relaxed)),
+            (
+                "secondary_write_errors",
+                self.secondary_write_errors.load(Ordering::Relaxed),
+            ),
+        ])
+    }
+}
@@ -0,0 +1,18 @@
+#[cfg(test)]
+mod tests {
+    use crate::migration::{ShadowOutcome, compare};
+
+    #[test]
+    fn test_compare_hits() {
+        assert_eq!(compare(Some(b"a"), Some(b"a")), ShadowOutcome::Match);
+        assert_eq!(compare(Some(b"a"), Some(b"b")), ShadowOutcome::Mismatch);
+        assert_eq!(compare(Some(b""), Some(b"")), ShadowOutcome::Match);
+    }
+
+    #[test]
+    fn test_compare_misses() {
+        assert_eq!(compare(None, None), ShadowOutcome::Match);
+        assert_eq!(compare(Some(b"a"), None), ShadowOutcome::Miss);
+        assert_eq!(compare(None, Some(b"a")), ShadowOutcome::Mismatch);
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+/// Suffix of the counter key holding a versioned namespace's generation.
+const GENERATION_KEY_SUFFIX: &[u8] = b"generation";
+
+struct Generation {
+    value: Option<u64>,
+    loaded_at: Option<Instant>,
+    refresh_interval: Duration,
+}
+
+/// Prefix prepended to every key before it is encoded for the wire. A
+/// versioned namespace also folds in a generation number, kept in a counter
+/// on the server, so bumping it makes every key of the namespace miss.
+pub struct Namespace {
+    prefix: Vec<u8>,
+    generation: Option<Generation>,
+}
+
+impl Namespace {
+    pub fn new(prefix: &[u8]) -> Result<Self, String> {
+        if prefix.is_empty() {
+            return Err("Namespace must not be empty".to_string());
+        }
+        Ok(Namespace {
+            prefix: prefix.to_vec(),
+            generation: None,
+        })
+    }
+
+    /// Also fold the generation into the prefix, re-read from the server
+    /// at most every `refresh_interval`.
+    pub fn versioned(mut self, refresh_interval: Duration) -> Self {
+        self.generation = Some(Generation {
+            value: None,
+            loaded_at: None,
+            refresh_interval,
+        });
+        self
+    }
+
+    /// The generation in use, None until loaded or if not versioned.
+    pub fn generation(&self) -> Option<u64> {
+        self.generation.as_ref().and_then(|g| g.value)
+    }
+
+    /// Key of the generation counter: `{namespace}generation`. It never
+    /// collides with a namespaced key, which has a generation in between.
+    pub fn counter_key(&self) -> Option<Vec<u8>> {
+        self.generation
+            .as_ref()
+            .map(|_| [self.prefix.as_slice(), GENERATION_KEY_SUFFIX].concat())
+    }
+
+    /// Whether the generation must be (re)loaded before use.
+    pub fn needs_refresh(&self, now: Instant) -> bool {
+        self.generation.as_ref().is_some_and(|g| {
+            g.loaded_at
+                .is_none_or(|loaded_at| now.duration_since(loaded_at) >= g.refresh_interval)
+        })
+    }
+
+    pub fn set_generation(&mut self, value: u64, now: Instant) {
+        if let Some(generation) = self.generation.as_mut() {
+            generation.value = Some(value);
+            generation.loaded_at = Some(now);
+        }
+    }
+
+    /// `key` in the namespace: `{namespace}{key}`, or
+    /// `{namespace}{generation}:{key}` if versioned.
+    pub fn apply(&self, key: &[u8]) -> Result<Vec<u8>, String> {
+        let mut namespaced = Vec::with_capacity(self.prefix.len() + 21 + key.len());
+        namespaced.extend_from_slice(&self.prefix);
+        if let Some(generation) = &self.generation {
+            let value = generation
+                .value
+                .ok_or_else(|| "Namespace generation not loaded".to_string())?;
+            let mut itoa_buf = itoa::Buffer::new();
+            namespaced.extend_from_slice(itoa_buf.format(value).as_bytes());
+            namespaced.push(b':');
+        }
+        namespaced.extend_from_slice(key);
+        Ok(namespaced)
+    }
+}
@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::namespace::Namespace;
+
+    #[test]
+    fn test_plain_prefix() {
+        let namespace = Namespace::new(b"app:").unwrap();
+        assert_eq!(namespace.apply(b"key").unwrap(), b"app:key");
+        assert_eq!(namespace.counter_key(), None);
+        assert!(!namespace.needs_refresh(Instant::now()));
+    }
+
+    #[test]
+    fn test_empty_rejected() {
+        assert!(Namespace::new(b"").is_err());
+    }
+
+    #[test]
+    fn test_versioned_prefix() {
+        let mut namespace = Namespace::new(b"app:")
+            .unwrap()
+            .versioned(Duration::from_secs(5));
+        assert_eq!(namespace.counter_key(), Some(b"app:generation".to_vec()));
+        assert!(namespace.apply(b"key").is_err());
+        namespace.set_generation(17, Instant::now());
+        assert_eq!(namespace.generation(), Some(17));
+        assert_eq!(namespace.apply(b"key").unwrap(), b"app:17:key");
+    }
+
+    #[test]
+    fn test_needs_refresh() {
+        let mut namespace = Namespace::new(b"app:")
+            .unwrap()
+            .versioned(Duration::from_secs(5));
+        let now = Instant::now();
+        assert!(namespace.needs_refresh(now));
+        namespace.set_generation(1, now);
+        assert!(!namespace.needs_refresh(now + Duration::from_secs(4)));
+        assert!(namespace.needs_refresh(now + Duration::from_secs(5)));
+    }
+}
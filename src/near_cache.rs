@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::constants::DEFAULT_NEAR_CACHE_TTL;
+use crate::encode_key::extract_key;
+use crate::response_flags::ResponseFlags;
+use crate::sync::lock;
+
+/// A cached response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cached {
+    Value(Vec<u8>, ResponseFlags),
+    /// A known miss.
+    Miss,
+}
+
+impl Cached {
+    fn len(&self) -> usize {
+        match self {
+            Cached::Value(value, _) => value.len(),
+            Cached::Miss => 0,
+        }
+    }
+}
+
+struct Entry {
+    cached: Cached,
+    expires_at: Instant,
+    /// Position in the LRU order.
+    tick: u64,
+}
+
+/// LRU map of values, and optionally misses, with a byte budget and a TTL
+/// per entry. Entries are charged the size of their key and value.
+pub struct NearCacheStore {
+    max_bytes: usize,
+    max_ttl: Duration,
+    /// How long misses are cached, if at all.
+    negative_ttl: Option<Duration>,
+    /// Cache misses only for keys starting with one of these, if set.
+    negative_prefixes: Option<Vec<Vec<u8>>>,
+    entries: HashMap<Vec<u8>, Entry>,
+    /// Keys by last use, oldest first.
+    lru: BTreeMap<u64, Vec<u8>>,
+    next_tick: u64,
+    bytes: usize,
+    pub hits: u64,
+    pub negative_hits: u64,
+    pub misses: u64,
+}
+
+impl NearCacheStore {
+    pub fn new(max_bytes: usize, max_ttl: Duration) -> Self {
+        NearCacheStore {
+            max_bytes,
+            max_ttl,
+            negative_ttl: None,
+            negative_prefixes: None,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            next_tick: 0,
+            bytes: 0,
+            hits: 0,
+            negative_hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Also cache misses for `ttl`, of all keys or only those starting with
+    /// one of `prefixes`.
+    pub fn with_negative_caching(mut self, ttl: Duration, prefixes: Option<Vec<Vec<u8>>>) -> Self {
+        self.negative_ttl = Some(ttl);
+        self.negative_prefixes = prefixes;
+        self
+    }
+
+    /// Whether misses of `key` are cached.
+    pub fn caches_misses(&self, key: &[u8]) -> bool {
+        self.negative_ttl.is_some()
+            && self
+                .negative_prefixes
+                .as_ref()
+                .is_none_or(|prefixes| prefixes.iter().any(|prefix| key.starts_with(prefix)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    /// Look up `key`, counting a hit or a miss. Expired entries are dropped.
+    /// Cached values are skipped unless `values` is set.
+    pub fn get(&mut self, key: &[u8], now: Instant, values: bool) -> Option<Cached> {
+        let (expired, is_value) = match self.entries.get(key) {
+            Some(entry) => (
+                entry.expires_at <= now,
+                matches!(entry.cached, Cached::Value(..)),
+            ),
+            None => {
+                self.misses += 1;
+                return None;
+            }
+        };
+        if is_value && !values {
+            return None;
+        }
+        if expired {
+            self.remove(key);
+            self.misses += 1;
+            return None;
+        }
+        let tick = self.tick();
+        let entry = self.entries.get_mut(key)?;
+        let key = self.lru.remove(&entry.tick)?;
+        entry.tick = tick;
+        self.lru.insert(tick, key);
+        match entry.cached {
+            Cached::Value(..) => self.hits += 1,
+            Cached::Miss => self.negative_hits += 1,
+        }
+        Some(entry.cached.clone())
+    }
+
+    /// Cache `value` for at most max_ttl, or less if the item expires sooner
+    /// on the server (`flags.ttl`, -1 if it never does). Values that don't
+    /// fit the budget on their own are not cached.
+    pub fn insert(&mut self, key: &[u8], value: &[u8], flags: &ResponseFlags, now: Instant) {
+        let ttl = match flags.ttl {
+            Some(ttl) if ttl >= 0 => self.max_ttl.min(Duration::from_secs(ttl as u64)),
+            _ => self.max_ttl,
+        };
+        self.insert_entry(key, Cached::Value(value.to_vec(), flags.clone()), ttl, now);
+    }
+
+    /// Remember that `key` is missing, if misses of it are cached.
+    pub fn insert_miss(&mut self, key: &[u8], now: Instant) {
+        if let Some(ttl) = self.negative_ttl.filter(|_| self.caches_misses(key)) {
+            self.insert_entry(key, Cached::Miss, ttl, now);
+        }
+    }
+
+    fn insert_entry(&mut self, key: &[u8], cached: Cached, ttl: Duration, now: Instant) {
+        self.remove(key);
+        let size = key.len() + cached.len();
+        if ttl.is_zero() || size > self.max_bytes {
+            return;
+        }
+        while self.bytes + size > self.max_bytes {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.bytes -= oldest.len() + entry.cached.len();
+            }
+        }
+        let tick = self.tick();
+        self.entries.insert(
+            key.to_vec(),
+            Entry {
+                cached,
+                expires_at: now + ttl,
+                tick,
+            },
+        );
+        self.lru.insert(tick, key.to_vec());
+        self.bytes += size;
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.tick);
+            self.bytes -= key.len() + entry.cached.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+        self.bytes = 0;
+    }
+}
+
+/// In-process cache of hot values in front of one or more MemcacheSocket,
+/// attached with `MemcacheSocket.set_near_cache()`. Share one instance
+/// between the sockets of a pool so a write through any of them invalidates
+/// it. Thread-safe.
+#[pyclass(frozen)]
+pub struct NearCache {
+    store: Mutex<NearCacheStore>,
+}
+
+impl NearCache {
+    pub fn store(&self) -> MutexGuard<'_, NearCacheStore> {
+        lock(&self.store)
+    }
+}
+
+#[pymethods]
+impl NearCache {
+    /// Cache up to `max_bytes` of keys and values, each for at most
+    /// `max_ttl` seconds. With `negative_ttl`, misses are cached for that
+    /// many seconds too, for all keys or only those starting with one of
+    /// `negative_prefixes`.
+    #[new]
+    #[pyo3(signature = (max_bytes, max_ttl=DEFAULT_NEAR_CACHE_TTL, negative_ttl=None, negative_prefixes=None))]
+    fn new(
+        max_bytes: usize,
+        max_ttl: u32,
+        negative_ttl: Option<u32>,
+        negative_prefixes: Option<Vec<Bound<'_, PyAny>>>,
+    ) -> PyResult<Self> {
+        if max_bytes == 0 || max_ttl == 0 || negative_ttl == Some(0) {
+            return Err(PyValueError::new_err(
+                "max_bytes, max_ttl and negative_ttl must be positive",
+            ));
+        }
+        let mut store = NearCacheStore::new(max_bytes, Duration::from_secs(max_ttl as u64));
+        if let Some(negative_ttl) = negative_ttl {
+            let prefixes = negative_prefixes
+                .map(|prefixes| {
+                    prefixes
+                        .iter()
+                        .map(|prefix| extract_key(prefix).map(<[u8]>::to_vec))
+                        .collect::<PyResult<Vec<_>>>()
+                })
+                .transpose()?;
+            store = store.with_negative_caching(Duration::from_secs(negative_ttl as u64), prefixes);
+        }
+        Ok(NearCache {
+            store: Mutex::new(store),
+        })
+    }
+
+    /// Drop `key` from the cache.
+    fn invalidate(&self, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.store().remove(extract_key(key)?);
+        Ok(())
+    }
+
+    /// Drop all entries. Stats are kept.
+    fn clear(&self) {
+        self.store().clear();
+    }
+
+    /// Counters: `hits`, `negative_hits` (cached misses served), `misses`,
+    /// and the current `entries` and `bytes`.
+    fn get_stats(&self) -> HashMap<&'static str, u64> {
+        let store = self.store();
+        HashMap::from([
+            ("hits", store.hits),
+            ("negative_hits", store.negative_hits),
+            ("misses", store.misses),
+            ("entries", store.len() as u64),
+            ("bytes", store.bytes() as u64),
+        ])
+    }
+
+    fn __len__(&self) -> usize {
+        self.store().len()
+    }
+}
@@ -0,0 +1,160 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::near_cache::{Cached, NearCacheStore};
+    use crate::response_flags::ResponseFlags;
+
+    fn flags(ttl: Option<i32>) -> ResponseFlags {
+        let mut flags = ResponseFlags::parse_flags(b"VA 1", 4);
+        flags.ttl = ttl;
+        flags
+    }
+
+    fn store(max_bytes: usize) -> NearCacheStore {
+        NearCacheStore::new(max_bytes, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn test_hit_and_miss() {
+        let mut cache = store(1024);
+        let now = Instant::now();
+        assert!(cache.get(b"key", now, true).is_none());
+        cache.insert(b"key", b"value", &flags(Some(-1)), now);
+        assert_eq!(
+            cache.get(b"key", now, true),
+            Some(Cached::Value(b"value".to_vec(), flags(Some(-1))))
+        );
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+        assert_eq!(cache.bytes(), 8);
+    }
+
+    #[test]
+    fn test_expires_after_max_ttl() {
+        let mut cache = store(1024);
+        let now = Instant::now();
+        cache.insert(b"key", b"value", &flags(None), now);
+        assert!(
+            cache
+                .get(b"key", now + Duration::from_secs(9), true)
+                .is_some()
+        );
+        assert!(
+            cache
+                .get(b"key", now + Duration::from_secs(10), true)
+                .is_none()
+        );
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.bytes(), 0);
+    }
+
+    #[test]
+    fn test_bounded_by_server_ttl() {
+        let mut cache = store(1024);
+        let now = Instant::now();
+        cache.insert(b"key", b"value", &flags(Some(2)), now);
+        assert!(
+            cache
+                .get(b"key", now + Duration::from_secs(1), true)
+                .is_some()
+        );
+        assert!(
+            cache
+                .get(b"key", now + Duration::from_secs(2), true)
+                .is_none()
+        );
+        // About to expire on the server: not cached
+        cache.insert(b"key", b"value", &flags(Some(0)), now);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = store(24);
+        let now = Instant::now();
+        cache.insert(b"a", b"1234567", &flags(None), now);
+        cache.insert(b"b", b"1234567", &flags(None), now);
+        cache.insert(b"c", b"1234567", &flags(None), now);
+        assert!(cache.get(b"a", now, true).is_some());
+        cache.insert(b"d", b"1234567", &flags(None), now);
+        assert!(cache.get(b"b", now, true).is_none());
+        assert!(cache.get(b"a", now, true).is_some());
+        assert!(cache.get(b"c", now, true).is_some());
+        assert!(cache.get(b"d", now, true).is_some());
+        assert_eq!(cache.bytes(), 24);
+    }
+
+    #[test]
+    fn test_too_large_not_cached() {
+        let mut cache = store(16);
+        let now = Instant::now();
+        cache.insert(b"small", b"1", &flags(None), now);
+        cache.insert(b"key", &[0u8; 16], &flags(None), now);
+        assert!(cache.get(b"key", now, true).is_none());
+        assert!(cache.get(b"small", now, true).is_some());
+    }
+
+    #[test]
+    fn test_replace_and_remove() {
+        let mut cache = store(1024);
+        let now = Instant::now();
+        cache.insert(b"key", b"old value", &flags(None), now);
+        cache.insert(b"key", b"new", &flags(None), now);
+        assert_eq!(
+            cache.get(b"key", now, true),
+            Some(Cached::Value(b"new".to_vec(), flags(None)))
+        );
+        assert_eq!(cache.bytes(), 6);
+        cache.remove(b"key");
+        assert!(cache.get(b"key", now, true).is_none());
+        assert_eq!((cache.len(), cache.bytes()), (0, 0));
+        cache.insert(b"key", b"value", &flags(None), now);
+        cache.clear();
+        assert_eq!((cache.len(), cache.bytes()), (0, 0));
+    }
+
+    #[test]
+    fn test_negative_caching_disabled_by_default() {
+        let mut cache = store(1024);
+        let now = Instant::now();
+        assert!(!cache.caches_misses(b"key"));
+        cache.insert_miss(b"key", now);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_negative_caching() {
+        let mut cache = store(1024).with_negative_caching(Duration::from_secs(2), None);
+        let now = Instant::now();
+        cache.insert_miss(b"key", now);
+        assert_eq!(cache.get(b"key", now, false), Some(Cached::Miss));
+        assert_eq!(cache.get(b"key", now, true), Some(Cached::Miss));
+        assert_eq!((cache.hits, cache.negative_hits), (0, 2));
+        assert_eq!(cache.bytes(), 3);
+        assert_eq!(cache.get(b"key", now + Duration::from_secs(2), false), None);
+        assert_eq!(cache.misses, 1);
+    }
+
+    #[test]
+    fn test_negative_prefixes() {
+        let prefixes = vec![b"flags:".to_vec(), b"exact".to_vec()];
+        let mut cache = store(1024).with_negative_caching(Duration::from_secs(2), Some(prefixes));
+        assert!(cache.caches_misses(b"flags:user:1"));
+        assert!(cache.caches_misses(b"exact"));
+        assert!(!cache.caches_misses(b"other"));
+        let now = Instant::now();
+        cache.insert_miss(b"other", now);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_values_skipped_unless_requested() {
+        let mut cache = store(1024).with_negative_caching(Duration::from_secs(2), None);
+        let now = Instant::now();
+        cache.insert(b"key", b"value", &flags(None), now);
+        assert_eq!(cache.get(b"key", now, false), None);
+        assert_eq!((cache.hits, cache.misses), (0, 0));
+        cache.insert_miss(b"key", now);
+        assert_eq!(cache.get(b"key", now, false), Some(Cached::Miss));
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::warn;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyConnectionError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::memcache_socket::MemcacheSocket;
+use crate::request_flags::RequestFlags;
+use crate::response_types::{Miss, Success, Value};
+
+create_exception!(
+    meta_memcache_socket,
+    ReplicationError,
+    PyConnectionError,
+    "A replicated write was not acknowledged by enough replicas."
+);
+
+/// Number of replicas whose write results are read: with quiet replicas,
+/// only the primary's.
+fn acknowledging(replicas: usize, quiet_replicas: bool) -> usize {
+    if quiet_replicas {
+        replicas.min(1)
+    } else {
+        replicas
+    }
+}
+
+/// Write quorum, by default a majority of the acknowledging replicas.
+pub fn write_quorum(
+    replicas: usize,
+    quiet_replicas: bool,
+    quorum: Option<usize>,
+) -> Result<usize, String> {
+    if replicas == 0 {
+        return Err("A replica set needs at least one socket".to_string());
+    }
+    let acknowledging = acknowledging(replicas, quiet_replicas);
+    let quorum = quorum.unwrap_or(acknowledging / 2 + 1);
+    if quorum == 0 || quorum > acknowledging {
+        return Err(format!(
+            "write_quorum must be between 1 and {acknowledging}, the replicas whose writes are acknowledged"
+        ));
+    }
+    Ok(quorum)
+}
+
+/// TTL to copy a hit with on read repair, from its remaining TTL (-1 if it
+/// doesn't expire). None if it is about to expire and shouldn't be copied.
+pub fn repair_ttl(ttl: Option<i32>) -> Option<Option<u32>> {
+    match ttl {
+        None | Some(-1) => Some(None),
+        Some(ttl) if ttl > 0 => Some(Some(ttl as u32)),
+        _ => None,
+    }
+}
+
+/// Keeps copies of keys on several MemcacheSocket, the first being the
+/// primary. Writes go to all of them, pipelined so the replicas are written
+/// concurrently, and succeed once `write_quorum` acknowledge them. Reads try
+/// the replicas in order until one hits. Not thread-safe, like the sockets.
+#[pyclass(frozen)]
+pub struct ReplicaSet {
+    sockets: Vec<Py<MemcacheSocket>>,
+    write_quorum: usize,
+    quiet_replicas: bool,
+    read_repair: bool,
+    write_failures: AtomicU64,
+    read_fallbacks: AtomicU64,
+    read_repairs: AtomicU64,
+}
+
+impl ReplicaSet {
+    /// Send a write to every replica with `send`, then read the results of
+    /// those not in quiet mode. If `needs_write` says a replica can only do
+    /// it with `write` (e.g. chunked values), it is written to one replica
+    /// after the other with `write` instead. Returns the first acknowledged
+    /// response if at least write_quorum were. Otherwise raises
+    /// ReplicationError if some replica failed, or returns the first
+    /// response that wasn't an acknowledgement (e.g. NotStored).
+    fn replicate(
+        &self,
+        py: Python<'_>,
+        request_flags: Option<&RequestFlags>,
+        needs_write: impl Fn(&MemcacheSocket) -> bool,
+        send: impl Fn(&mut MemcacheSocket, Option<&RequestFlags>) -> PyResult<()>,
+        write: impl Fn(&mut MemcacheSocket, Option<&RequestFlags>) -> PyResult<Py<PyAny>>,
+        acknowledged: impl Fn(&Bound<'_, PyAny>) -> bool,
+    ) -> PyResult<Py<PyAny>> {
+        if request_flags.is_some_and(RequestFlags::is_no_reply) {
+            return Err(PyValueError::new_err(
+                "Replicated writes need the results, no_reply is not supported",
+            ));
+        }
+        let quiet_flags = self
+            .quiet_replicas
+            .then(|| RequestFlags::with_no_reply(request_flags));
+        let sequential = self
+            .sockets
+            .iter()
+            .any(|socket| needs_write(&socket.bind(py).borrow()));
+        let mut errors = Vec::new();
+        let mut responses = Vec::with_capacity(self.sockets.len());
+        let mut pending = Vec::with_capacity(self.sockets.len());
+        for (index, socket) in self.sockets.iter().enumerate() {
+            let quiet = index > 0 && quiet_flags.is_some();
+            let flags = if quiet {
+                quiet_flags.as_ref()
+            } else {
+                request_flags
+            };
+            let socket = &mut socket.bind(py).borrow_mut();
+            if sequential {
+                match write(socket, flags) {
+                    Ok(response) if !quiet => responses.push(response),
+                    Ok(_) => {}
+                    Err(e) => errors.push(e),
+                }
+                continue;
+            }
+            match send(socket, flags) {
+                Ok(()) if !quiet => pending.push(index),
+                Ok(()) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        for index in pending {
+            match self.sockets[index].bind(py).borrow_mut().get_response(py) {
+                Ok(response) => responses.push(response),
+                Err(e) => errors.push(e),
+            }
+        }
+        let mut acks = 0;
+        let mut first_ack = None;
+        let mut first_rejection = None;
+        for response in responses {
+            if acknowledged(response.bind(py)) {
+                acks += 1;
+                first_ack.get_or_insert(response);
+            } else {
+                first_rejection.get_or_insert(response);
+            }
+        }
+        let failures = acknowledging(self.sockets.len(), self.quiet_replicas) - acks;
+        self.write_failures
+            .fetch_add(failures as u64, Ordering::Relaxed);
+        if acks >= self.write_quorum
+            && let Some(response) = first_ack
+        {
+            return Ok(response);
+        }
+        if let Some(cause) = errors.into_iter().next() {
+            let err = ReplicationError::new_err(format!(
+                "Write acknowledged by {acks} replicas, {} needed",
+                self.write_quorum
+            ));
+            err.set_cause(py, Some(cause));
+            return Err(err);
+        }
+        first_rejection.ok_or_else(|| {
+            ReplicationError::new_err(format!(
+                "Write acknowledged by {acks} replicas, {} needed",
+                self.write_quorum
+            ))
+        })
+    }
+
+    /// Copy a hit to the replicas that missed it, add-only so a concurrent
+    /// write wins, and with the same CAS so the copies stay comparable.
+    /// Failures are logged and ignored.
+    fn repair(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        response: &Bound<'_, PyAny>,
+        missed: &[usize],
+    ) -> PyResult<()> {
+        let Ok(value) = response.extract::<PyRef<'_, Value>>() else {
+            return Ok(());
+        };
+        let Some(data) = value.value.as_ref() else {
+            return Ok(());
+        };
+        let flags = &value.as_super().flags;
+        let Some(ttl) = repair_ttl(flags.ttl) else {
+            return Ok(());
+        };
+        let repair_flags = RequestFlags::for_read_repair(flags.cas_token, flags.client_flag, ttl);
+        let data = data.bind(py).cast::<PyBytes>()?.as_bytes();
+        for &index in missed {
+            let result = self.sockets[index].bind(py).borrow_mut().meta_set(
+                py,
+                key,
+                data,
+                Some(&repair_flags),
+            );
+            match result {
+                Ok(response) if response.bind(py).is_instance_of::<Success>() => {
+                    self.read_repairs.fetch_add(1, Ordering::Relaxed);
+                }
+                // Written meanwhile
+                Ok(_) => {}
+                Err(e) => warn!("Read repair of replica {index} failed: {e}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl ReplicaSet {
+    /// Replicate over `sockets`, the first being the primary. Writes need
+    /// `write_quorum` acknowledgements, by default a majority. With
+    /// `quiet_replicas`, all but the primary are written in quiet mode, so
+    /// only the primary acknowledges. With `read_repair`, a hit is copied
+    /// to the replicas that missed it before.
+    #[new]
+    #[pyo3(signature = (sockets, write_quorum=None, quiet_replicas=false, read_repair=false))]
+    fn new(
+        sockets: Vec<Py<MemcacheSocket>>,
+        write_quorum: Option<usize>,
+        quiet_replicas: bool,
+        read_repair: bool,
+    ) -> PyResult<Self> {
+        let write_quorum = self::write_quorum(sockets.len(), quiet_replicas, write_quorum)
+            .map_err(PyValueError::new_err)?;
+        Ok(ReplicaSet {
+            sockets,
+            write_quorum,
+            quiet_replicas,
+            read_repair,
+            write_failures: AtomicU64::new(0),
+            read_fallbacks: AtomicU64::new(0),
+            read_repairs: AtomicU64::new(0),
+        })
+    }
+
+    /// meta_set on every replica. Returns the first Success if the write
+    /// quorum is met. Otherwise raises ReplicationError if replicas failed,
+    /// or returns the first other response (e.g. NotStored). Values that
+    /// may be chunked are written to one replica after the other.
+    #[pyo3(signature = (key, value, request_flags=None))]
+    fn meta_set(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        value: &[u8],
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.replicate(
+            py,
+            request_flags,
+            |socket| socket.may_chunk(value),
+            |socket, flags| socket.send_meta_set(py, key, value, flags),
+            |socket, flags| socket.meta_set(py, key, value, flags),
+            |response| response.is_instance_of::<Success>(),
+        )
+    }
+
+    /// meta_delete on every replica, as meta_set. A Miss counts as
+    /// acknowledged: the key is gone from that replica. With chunking
+    /// enabled, one replica after the other, to delete the chunks too.
+    #[pyo3(signature = (key, request_flags=None))]
+    fn meta_delete(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        self.replicate(
+            py,
+            request_flags,
+            MemcacheSocket::is_chunking,
+            |socket, flags| socket.send_meta_delete(py, key, flags),
+            |socket, flags| socket.meta_delete(py, key, flags),
+            |response| response.is_instance_of::<Success>() || response.is_instance_of::<Miss>(),
+        )
+    }
+
+    /// meta_get from the replicas in order, falling back to the next on a
+    /// miss or error. Returns the first hit, or Miss; raises the last error
+    /// if every replica failed.
+    #[pyo3(signature = (key, request_flags=None))]
+    fn meta_get(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        request_flags: Option<&RequestFlags>,
+    ) -> PyResult<Py<PyAny>> {
+        let repair_flags = self
+            .read_repair
+            .then(|| RequestFlags::for_replica_read(request_flags));
+        let request_flags = repair_flags.as_ref().or(request_flags);
+        let mut missed = Vec::new();
+        let mut miss = None;
+        let mut last_error = None;
+        for (index, socket) in self.sockets.iter().enumerate() {
+            let result = socket
+                .bind(py)
+                .borrow_mut()
+                .meta_get(py, key, request_flags, false);
+            match result {
+                Ok(response) if response.bind(py).is_instance_of::<Miss>() => {
+                    missed.push(index);
+                    miss = Some(response);
+                }
+                Ok(response) => {
+                    if index > 0 {
+                        self.read_fallbacks.fetch_add(1, Ordering::Relaxed);
+                    }
+                    if self.read_repair && !missed.is_empty() {
+                        self.repair(py, key, response.bind(py), &missed)?;
+                    }
+                    return Ok(response);
+                }
+                Err(e) => {
+                    warn!("Read from replica {index} failed: {e}");
+                    last_error = Some(e);
+                }
+            }
+        }
+        match (miss, last_error) {
+            (Some(miss), _) => Ok(miss),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(PyValueError::new_err("No replicas")),
+        }
+    }
+
+    /// Counters: `write_failures` (acknowledging replicas that failed or
+    /// didn't store a write), `read_fallbacks` (hits served by a replica
+    /// other than the primary) and `read_repairs` (copies written back).
+    fn get_stats(&self) -> HashMap<&'static str, u64> {
+        HashMap::from([
+            (
+                "write_failures",
+                self.write_failures.load(Ordering::Relaxed),
+            ),
+            (
+                "read_fallbacks",
+                self.read_fallbacks.load(Ordering::Relaxed),
+            ),
+            ("read_repairs", self.read_repairs.load(Ordering::Relaxed)),
+        ])
+    }
+
+    fn __len__(&self) -> usize {
+        self.sockets.len()
+    }
+}
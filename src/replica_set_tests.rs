@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod tests {
+    use crate::replica_set::{repair_ttl, write_quorum};
+
+    #[test]
+    fn test_default_write_quorum_is_majority() {
+        assert_eq!(write_quorum(1, false, None), Ok(1));
+        assert_eq!(write_quorum(2, false, None), Ok(2));
+        assert_eq!(write_quorum(3, false, None), Ok(2));
+        assert_eq!(write_quorum(5, false, None), Ok(3));
+        assert_eq!(write_quorum(3, true, None), Ok(1));
+    }
+
+    #[test]
+    fn test_explicit_write_quorum() {
+        assert_eq!(write_quorum(3, false, Some(3)), Ok(3));
+        assert_eq!(write_quorum(3, false, Some(1)), Ok(1));
+        assert!(write_quorum(3, false, Some(0)).is_err());
+        assert!(write_quorum(3, false, Some(4)).is_err());
+        assert!(write_quorum(3, true, Some(2)).is_err());
+        assert!(write_quorum(0, false, None).is_err());
+    }
+
+    #[test]
+    fn test_repair_ttl() {
+        assert_eq!(repair_ttl(None), Some(None));
+        assert_eq!(repair_ttl(Some(-1)), Some(None));
+        assert_eq!(repair_ttl(Some(30)), Some(Some(30)));
+        assert_eq!(repair_ttl(Some(0)), None);
+    }
+}
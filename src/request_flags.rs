@@ -1,10 +1,79 @@
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
 
-use crate::{MA_MODE_INC, SET_MODE_SET};
+use crate::chunking::chunk_ttl;
+use crate::server_capabilities::ServerCapabilities;
+use crate::{
+    MA_MODE_DEC, MA_MODE_INC, SET_MODE_ADD, SET_MODE_APPEND, SET_MODE_PREPEND, SET_MODE_REPLACE,
+    SET_MODE_SET,
+};
+
+/// Max opaque length accepted by memcached.
+const MAX_OPAQUE_LEN: usize = 32;
+
+/// The SET_MODE_* and MA_MODE_* bytes.
+const MODES: [u8; 7] = [
+    SET_MODE_ADD,
+    SET_MODE_APPEND,
+    SET_MODE_PREPEND,
+    SET_MODE_REPLACE,
+    SET_MODE_SET,
+    MA_MODE_INC,
+    MA_MODE_DEC,
+];
+
+/// Reasons a `RequestFlags` can't be constructed.
+#[derive(Debug, PartialEq)]
+pub enum RequestFlagsError {
+    /// The opaque is longer than `MAX_OPAQUE_LEN`.
+    OpaqueTooLong,
+    /// The opaque contains whitespace, control or non-ASCII bytes.
+    OpaqueNotPrintable,
+    /// The mode is not one of the SET_MODE_* or MA_MODE_* bytes.
+    InvalidMode,
+}
+
+impl From<RequestFlagsError> for PyErr {
+    fn from(err: RequestFlagsError) -> PyErr {
+        match err {
+            RequestFlagsError::OpaqueTooLong => {
+                PyValueError::new_err(format!("opaque must be at most {MAX_OPAQUE_LEN} bytes"))
+            }
+            RequestFlagsError::OpaqueNotPrintable => {
+                PyValueError::new_err("opaque must be printable ASCII without whitespace")
+            }
+            RequestFlagsError::InvalidMode => {
+                PyValueError::new_err("mode must be one of the SET_MODE_* or MA_MODE_* constants")
+            }
+        }
+    }
+}
+
+/// The opaque is written verbatim to the wire, so it must be printable ASCII
+/// without whitespace to avoid injecting extra flags or commands.
+fn validate_opaque(opaque: &Option<Vec<u8>>) -> Result<(), RequestFlagsError> {
+    if let Some(opaque) = opaque {
+        if opaque.len() > MAX_OPAQUE_LEN {
+            return Err(RequestFlagsError::OpaqueTooLong);
+        }
+        if !opaque.iter().all(|&c| c > b' ' && c <= b'~') {
+            return Err(RequestFlagsError::OpaqueNotPrintable);
+        }
+    }
+    Ok(())
+}
+
+/// The mode is also written verbatim, after `M`.
+fn validate_mode(mode: Option<u8>) -> Result<(), RequestFlagsError> {
+    match mode {
+        Some(mode) if !MODES.contains(&mode) => Err(RequestFlagsError::InvalidMode),
+        _ => Ok(()),
+    }
+}
 
 #[pyclass(eq, skip_from_py_object, frozen)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct RequestFlags {
     #[pyo3(get)]
     no_reply: bool,
@@ -29,6 +98,8 @@ pub struct RequestFlags {
     #[pyo3(get)]
     mark_stale: bool,
     #[pyo3(get)]
+    remove_value: bool,
+    #[pyo3(get)]
     cache_ttl: Option<u32>,
     #[pyo3(get)]
     recache_ttl: Option<u32>,
@@ -43,6 +114,8 @@ pub struct RequestFlags {
     #[pyo3(get)]
     cas_token: Option<u32>,
     #[pyo3(get)]
+    explicit_cas: Option<u64>,
+    #[pyo3(get)]
     opaque: Option<Vec<u8>>,
     #[pyo3(get)]
     mode: Option<u8>,
@@ -54,6 +127,263 @@ impl RequestFlags {
         self.no_reply
     }
 
+    /// Copy of `base` (or empty flags) set up for an incr/decr by `delta`
+    /// that returns the new value. With an `initial_value`, a miss creates
+    /// the counter with that value and `initial_ttl` instead of failing.
+    pub(crate) fn for_counter(
+        base: Option<&RequestFlags>,
+        mode: u8,
+        delta: u64,
+        initial_value: Option<u64>,
+        initial_ttl: u32,
+    ) -> Self {
+        let base = base.cloned().unwrap_or_default();
+        let (ma_initial_value, vivify_on_miss_ttl) = match initial_value {
+            Some(value) => (Some(value), Some(initial_ttl)),
+            None => (base.ma_initial_value, base.vivify_on_miss_ttl),
+        };
+        RequestFlags {
+            return_value: true,
+            ma_delta_value: Some(delta),
+            ma_initial_value,
+            vivify_on_miss_ttl,
+            mode: Some(mode),
+            ..base
+        }
+    }
+
+    /// Copy of `base` (or empty flags) for a `mg` that sets the TTL to `ttl`,
+    /// optionally returning the value.
+    pub(crate) fn for_touch(base: Option<&RequestFlags>, ttl: u32, return_value: bool) -> Self {
+        RequestFlags {
+            return_value,
+            cache_ttl: Some(ttl),
+            ..base.cloned().unwrap_or_default()
+        }
+    }
+
+    /// Copy of `base` (or empty flags) for a lease `mg`: a miss vivifies a
+    /// placeholder for `lease_ttl` and a TTL below `recache_ttl` hands out a
+    /// recompute win, so only one client computes the value.
+    pub(crate) fn for_lease(
+        base: Option<&RequestFlags>,
+        lease_ttl: u32,
+        recache_ttl: Option<u32>,
+    ) -> Self {
+        let base = base.cloned().unwrap_or_default();
+        RequestFlags {
+            return_value: true,
+            return_cas_token: true,
+            vivify_on_miss_ttl: Some(lease_ttl),
+            recache_ttl: recache_ttl.or(base.recache_ttl),
+            ..base
+        }
+    }
+
+    /// Copy of `base` (or empty flags) storing `client_flag`.
+    pub(crate) fn with_client_flag(base: Option<&RequestFlags>, client_flag: u32) -> Self {
+        RequestFlags {
+            client_flag: Some(client_flag),
+            ..base.cloned().unwrap_or_default()
+        }
+    }
+
+    /// Copy of `base` (or empty flags) for a `mg` that returns the value and
+    /// its client_flag.
+    pub(crate) fn for_value_get(base: Option<&RequestFlags>) -> Self {
+        RequestFlags {
+            return_value: true,
+            return_client_flag: true,
+            ..base.cloned().unwrap_or_default()
+        }
+    }
+
+    /// Copy of `base` (or empty flags) that also returns the client_flag.
+    pub(crate) fn with_return_client_flag(base: Option<&RequestFlags>) -> Self {
+        RequestFlags {
+            return_client_flag: true,
+            ..base.cloned().unwrap_or_default()
+        }
+    }
+
+    pub(crate) fn returns_client_flag(&self) -> bool {
+        self.return_client_flag
+    }
+
+    /// client_flag to store, if any.
+    pub(crate) fn client_flag(&self) -> Option<u32> {
+        self.client_flag
+    }
+
+    /// Flags for the `mg` of a read-modify-write: the value and its CAS.
+    pub(crate) fn for_cas_read() -> Self {
+        RequestFlags {
+            return_value: true,
+            return_cas_token: true,
+            ..Default::default()
+        }
+    }
+
+    /// Copy of `base` (or empty flags) for a CAS-guarded plain `ms`, or an
+    /// add-only one if there is no CAS (the key was missing).
+    pub(crate) fn for_fill(base: Option<&RequestFlags>, cas_token: Option<u32>) -> Self {
+        let base = base.cloned().unwrap_or_default();
+        match cas_token {
+            Some(cas_token) => RequestFlags {
+                cas_token: Some(cas_token),
+                mode: None,
+                ..base
+            },
+            None => RequestFlags {
+                mode: Some(SET_MODE_ADD),
+                ..base
+            },
+        }
+    }
+
+    /// Flags for a lock `ms` that returns the new CAS: add-only to acquire,
+    /// or guarded by `cas_token` to extend a held lock.
+    pub(crate) fn for_lock(ttl: u32, cas_token: Option<u32>) -> Self {
+        let base = RequestFlags {
+            return_cas_token: true,
+            cache_ttl: Some(ttl),
+            ..Default::default()
+        };
+        RequestFlags::for_fill(Some(&base), cas_token)
+    }
+
+    /// Flags for a `md` that only deletes the item if its CAS still matches.
+    pub(crate) fn for_cas_delete(cas_token: u32) -> Self {
+        RequestFlags {
+            cas_token: Some(cas_token),
+            ..Default::default()
+        }
+    }
+
+    /// Copy of `base` (or empty flags) for a `md` that marks the item stale
+    /// instead of deleting it, optionally setting its remaining TTL.
+    pub(crate) fn for_invalidate(base: Option<&RequestFlags>, ttl: Option<u32>) -> Self {
+        let base = base.cloned().unwrap_or_default();
+        RequestFlags {
+            mark_stale: true,
+            cache_ttl: ttl.or(base.cache_ttl),
+            ..base
+        }
+    }
+
+    /// Whether a `mg` with these flags (None being none) must reach the
+    /// server, bypassing the near cache: it may vivify a miss (N), e.g. to
+    /// take a lease, or win a recache (R).
+    pub(crate) fn bypasses_near_cache(flags: Option<&RequestFlags>) -> bool {
+        flags.is_some_and(|flags| flags.vivify_on_miss_ttl.is_some() || flags.recache_ttl.is_some())
+    }
+
+    /// Copy of `base` (or empty flags) for a `mg` through the near cache:
+    /// the value, and its TTL to bound how long it is cached.
+    pub(crate) fn for_near_cache(base: Option<&RequestFlags>) -> Self {
+        RequestFlags {
+            return_value: true,
+            return_ttl: true,
+            ..base.cloned().unwrap_or_default()
+        }
+    }
+
+    /// Flags for the `ms` of one chunk of a chunked value stored with
+    /// `base`: only its TTL applies, so chunks outlive the manifest.
+    pub(crate) fn for_chunk_set(base: Option<&RequestFlags>) -> Self {
+        RequestFlags {
+            cache_ttl: base.and_then(|base| base.cache_ttl).map(chunk_ttl),
+            ..Default::default()
+        }
+    }
+
+    /// Flags for the `mg` of one chunk of a chunked value.
+    pub(crate) fn for_chunk_get() -> Self {
+        RequestFlags {
+            return_value: true,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a write with these flags (None being none) appends or
+    /// prepends to the stored value instead of replacing it.
+    pub(crate) fn appends(flags: Option<&RequestFlags>) -> bool {
+        flags.is_some_and(|flags| matches!(flags.mode, Some(SET_MODE_APPEND | SET_MODE_PREPEND)))
+    }
+
+    /// Whether a write or delete with these flags (None being none) leaves
+    /// the chunks of the value it replaces to be deleted: not if the
+    /// outcome is unknown (no_reply) or the value is kept (mark_stale).
+    pub(crate) fn drops_chunks(flags: Option<&RequestFlags>) -> bool {
+        !flags.is_some_and(|flags| flags.no_reply || flags.mark_stale)
+    }
+
+    /// Copy of `base` (or empty flags) in quiet mode: only failures are
+    /// answered.
+    pub(crate) fn with_no_reply(base: Option<&RequestFlags>) -> Self {
+        RequestFlags {
+            no_reply: true,
+            ..base.cloned().unwrap_or_default()
+        }
+    }
+
+    /// Copy of `base` (or empty flags) for a `mg` whose hit can be copied
+    /// to another server: the value, client_flag, CAS and TTL.
+    pub(crate) fn for_replica_read(base: Option<&RequestFlags>) -> Self {
+        RequestFlags {
+            return_value: true,
+            return_client_flag: true,
+            return_cas_token: true,
+            return_ttl: true,
+            ..base.cloned().unwrap_or_default()
+        }
+    }
+
+    /// Flags for an add-only `ms` copying an item read with
+    /// for_replica_read() to a server that missed it, with the same CAS
+    /// (`E`), client_flag and TTL (None if it doesn't expire).
+    pub(crate) fn for_read_repair(
+        cas_token: Option<u32>,
+        client_flag: Option<u32>,
+        ttl: Option<u32>,
+    ) -> Self {
+        RequestFlags {
+            mode: Some(SET_MODE_ADD),
+            explicit_cas: cas_token.map(u64::from),
+            client_flag,
+            cache_ttl: ttl,
+            ..Default::default()
+        }
+    }
+
+    /// Copy in quiet mode, tagged with the request `index` as opaque so
+    /// pipelined responses can be matched back to their request.
+    pub(crate) fn quiet_with_index(&self, index: usize) -> Self {
+        let mut itoa_buf = itoa::Buffer::new();
+        RequestFlags {
+            no_reply: true,
+            opaque: Some(itoa_buf.format(index).as_bytes().to_vec()),
+            ..self.clone()
+        }
+    }
+
+    /// Return the first flag the server can't handle, if any.
+    pub(crate) fn unsupported_flag(
+        &self,
+        capabilities: &ServerCapabilities,
+    ) -> Option<&'static str> {
+        if self.vivify_on_miss_ttl.is_some() && !capabilities.vivify_on_miss {
+            return Some("vivify_on_miss_ttl (N)");
+        }
+        if self.explicit_cas.is_some() && !capabilities.explicit_cas {
+            return Some("explicit_cas (E)");
+        }
+        if self.remove_value && !capabilities.remove_value {
+            return Some("remove_value (x)");
+        }
+        None
+    }
+
     pub fn push_bytes(&self, buf: &mut Vec<u8>, allow_no_reply_flag: bool) {
         let mut itoa_buf = itoa::Buffer::new();
         // allow_no_reply_flag controls whether the wire-level `q` flag is emitted
@@ -102,6 +432,10 @@ impl RequestFlags {
             buf.push(b' ');
             buf.push(b'I');
         }
+        if self.remove_value {
+            buf.push(b' ');
+            buf.push(b'x');
+        }
         if let Some(v) = self.cache_ttl {
             buf.push(b' ');
             buf.push(b'T');
@@ -137,6 +471,11 @@ impl RequestFlags {
             buf.push(b'C');
             buf.extend_from_slice(itoa_buf.format(v).as_bytes());
         }
+        if let Some(v) = self.explicit_cas {
+            buf.push(b' ');
+            buf.push(b'E');
+            buf.extend_from_slice(itoa_buf.format(v).as_bytes());
+        }
         if let Some(v) = &self.opaque {
             buf.push(b' ');
             buf.push(b'O');
@@ -173,6 +512,7 @@ impl RequestFlags {
             return_key=false,
             no_update_lru=false,
             mark_stale=false,
+            remove_value=false,
             cache_ttl=None,
             recache_ttl=None,
             vivify_on_miss_ttl=None,
@@ -180,6 +520,7 @@ impl RequestFlags {
             ma_initial_value=None,
             ma_delta_value=None,
             cas_token=None,
+            explicit_cas=None,
             opaque=None,
             mode=None
         ),
@@ -195,6 +536,7 @@ impl RequestFlags {
             return_key: bool = False,
             no_update_lru: bool = False,
             mark_stale: bool = False,
+            remove_value: bool = False,
             cache_ttl: Optional[int] = None,
             recache_ttl: Optional[int] = None,
             vivify_on_miss_ttl: Optional[int] = None,
@@ -202,6 +544,7 @@ impl RequestFlags {
             ma_initial_value: Optional[int] = None,
             ma_delta_value: Optional[int] = None,
             cas_token: Optional[int] = None,
+            explicit_cas: Optional[int] = None,
             opaque: Optional[bytes] = None,
             mode: Optional[int] = None)"
     )]
@@ -217,6 +560,7 @@ impl RequestFlags {
         return_key: bool,
         no_update_lru: bool,
         mark_stale: bool,
+        remove_value: bool,
         cache_ttl: Option<u32>,
         recache_ttl: Option<u32>,
         vivify_on_miss_ttl: Option<u32>,
@@ -224,10 +568,13 @@ impl RequestFlags {
         ma_initial_value: Option<u64>,
         ma_delta_value: Option<u64>,
         cas_token: Option<u32>,
+        explicit_cas: Option<u64>,
         opaque: Option<Vec<u8>>,
         mode: Option<u8>,
-    ) -> Self {
-        RequestFlags {
+    ) -> Result<Self, RequestFlagsError> {
+        validate_opaque(&opaque)?;
+        validate_mode(mode)?;
+        Ok(RequestFlags {
             no_reply,
             return_client_flag,
             return_cas_token,
@@ -239,6 +586,7 @@ impl RequestFlags {
             return_key,
             no_update_lru,
             mark_stale,
+            remove_value,
             cache_ttl,
             recache_ttl,
             vivify_on_miss_ttl,
@@ -246,9 +594,10 @@ impl RequestFlags {
             ma_initial_value,
             ma_delta_value,
             cas_token,
+            explicit_cas,
             opaque,
             mode,
-        }
+        })
     }
 
     /// Return a copy of this object with the specified fields replaced.
@@ -259,6 +608,8 @@ impl RequestFlags {
     /// Note: passing `None` explicitly for an optional field (e.g. `cache_ttl=None`)
     /// keeps the existing value rather than unsetting it. To unset an optional field,
     /// construct a new `RequestFlags` directly.
+    ///
+    /// Raises `ValueError` if the resulting opaque or mode is not valid.
     #[allow(clippy::too_many_arguments)]
     #[pyo3(
         signature = (
@@ -275,6 +626,7 @@ impl RequestFlags {
             return_key=None,
             no_update_lru=None,
             mark_stale=None,
+            remove_value=None,
             cache_ttl=None,
             recache_ttl=None,
             vivify_on_miss_ttl=None,
@@ -282,6 +634,7 @@ impl RequestFlags {
             ma_initial_value=None,
             ma_delta_value=None,
             cas_token=None,
+            explicit_cas=None,
             opaque=None,
             mode=None
         )
@@ -299,6 +652,7 @@ impl RequestFlags {
         return_key: Option<bool>,
         no_update_lru: Option<bool>,
         mark_stale: Option<bool>,
+        remove_value: Option<bool>,
         cache_ttl: Option<u32>,
         recache_ttl: Option<u32>,
         vivify_on_miss_ttl: Option<u32>,
@@ -306,10 +660,13 @@ impl RequestFlags {
         ma_initial_value: Option<u64>,
         ma_delta_value: Option<u64>,
         cas_token: Option<u32>,
+        explicit_cas: Option<u64>,
         opaque: Option<Vec<u8>>,
         mode: Option<u8>,
-    ) -> Self {
-        RequestFlags {
+    ) -> Result<Self, RequestFlagsError> {
+        validate_opaque(&opaque)?;
+        validate_mode(mode)?;
+        Ok(RequestFlags {
             no_reply: no_reply.unwrap_or(self.no_reply),
             return_client_flag: return_client_flag.unwrap_or(self.return_client_flag),
             return_cas_token: return_cas_token.unwrap_or(self.return_cas_token),
@@ -321,6 +678,7 @@ impl RequestFlags {
             return_key: return_key.unwrap_or(self.return_key),
             no_update_lru: no_update_lru.unwrap_or(self.no_update_lru),
             mark_stale: mark_stale.unwrap_or(self.mark_stale),
+            remove_value: remove_value.unwrap_or(self.remove_value),
             cache_ttl: cache_ttl.or(self.cache_ttl),
             recache_ttl: recache_ttl.or(self.recache_ttl),
             vivify_on_miss_ttl: vivify_on_miss_ttl.or(self.vivify_on_miss_ttl),
@@ -328,14 +686,15 @@ impl RequestFlags {
             ma_initial_value: ma_initial_value.or(self.ma_initial_value),
             ma_delta_value: ma_delta_value.or(self.ma_delta_value),
             cas_token: cas_token.or(self.cas_token),
+            explicit_cas: explicit_cas.or(self.explicit_cas),
             opaque: opaque.or_else(|| self.opaque.clone()),
             mode: mode.or(self.mode),
-        }
+        })
     }
 
     pub fn __str__(&self) -> String {
         format!(
-            "RequestFlags(no_reply={:?}, return_client_flag={:?}, return_cas_token={:?}, return_value={:?}, return_ttl={:?}, return_size={:?}, return_last_access={:?}, return_fetched={:?}, return_key={:?}, no_update_lru={:?}, mark_stale={:?}, cache_ttl={:?}, recache_ttl={:?}, vivify_on_miss_ttl={:?}, client_flag={:?}, ma_initial_value={:?}, ma_delta_value={:?}, cas_token={:?}, opaque={:?}, mode={:?})",
+            "RequestFlags(no_reply={:?}, return_client_flag={:?}, return_cas_token={:?}, return_value={:?}, return_ttl={:?}, return_size={:?}, return_last_access={:?}, return_fetched={:?}, return_key={:?}, no_update_lru={:?}, mark_stale={:?}, remove_value={:?}, cache_ttl={:?}, recache_ttl={:?}, vivify_on_miss_ttl={:?}, client_flag={:?}, ma_initial_value={:?}, ma_delta_value={:?}, cas_token={:?}, explicit_cas={:?}, opaque={:?}, mode={:?})",
             self.no_reply,
             self.return_client_flag,
             self.return_cas_token,
@@ -347,6 +706,7 @@ impl RequestFlags {
             self.return_key,
             self.no_update_lru,
             self.mark_stale,
+            self.remove_value,
             self.cache_ttl,
             self.recache_ttl,
             self.vivify_on_miss_ttl,
@@ -354,6 +714,7 @@ impl RequestFlags {
             self.ma_initial_value,
             self.ma_delta_value,
             self.cas_token,
+            self.explicit_cas,
             self.opaque,
             self.mode,
         )
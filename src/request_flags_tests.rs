@@ -1,13 +1,16 @@
 #[cfg(test)]
 mod tests {
-    use crate::request_flags::RequestFlags;
-    use crate::{MA_MODE_DEC, MA_MODE_INC, SET_MODE_ADD, SET_MODE_APPEND, SET_MODE_SET};
+    use crate::request_flags::{RequestFlags, RequestFlagsError};
+    use crate::{
+        MA_MODE_DEC, MA_MODE_INC, SET_MODE_ADD, SET_MODE_APPEND, SET_MODE_PREPEND, SET_MODE_SET,
+    };
 
     fn default_flags() -> RequestFlags {
         RequestFlags::new(
-            false, false, false, false, false, false, false, false, false, false, false, None,
-            None, None, None, None, None, None, None, None,
+            false, false, false, false, false, false, false, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
         )
+        .unwrap()
     }
 
     fn push_to_vec(flags: &RequestFlags) -> Vec<u8> {
@@ -25,99 +28,110 @@ mod tests {
     #[test]
     fn test_no_reply() {
         let flags = RequestFlags::new(
-            true, false, false, false, false, false, false, false, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            true, false, false, false, false, false, false, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" q");
     }
 
     #[test]
     fn test_return_client_flag() {
         let flags = RequestFlags::new(
-            false, true, false, false, false, false, false, false, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, true, false, false, false, false, false, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" f");
     }
 
     #[test]
     fn test_return_cas_token() {
         let flags = RequestFlags::new(
-            false, false, true, false, false, false, false, false, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, true, false, false, false, false, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" c");
     }
 
     #[test]
     fn test_return_value() {
         let flags = RequestFlags::new(
-            false, false, false, true, false, false, false, false, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, true, false, false, false, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" v");
     }
 
     #[test]
     fn test_return_ttl() {
         let flags = RequestFlags::new(
-            false, false, false, false, true, false, false, false, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, false, true, false, false, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" t");
     }
 
     #[test]
     fn test_return_size() {
         let flags = RequestFlags::new(
-            false, false, false, false, false, true, false, false, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, false, false, true, false, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" s");
     }
 
     #[test]
     fn test_return_last_access() {
         let flags = RequestFlags::new(
-            false, false, false, false, false, false, true, false, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, false, false, false, true, false, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" l");
     }
 
     #[test]
     fn test_return_fetched() {
         let flags = RequestFlags::new(
-            false, false, false, false, false, false, false, true, false, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, false, false, false, false, true, false, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" h");
     }
 
     #[test]
     fn test_return_key() {
         let flags = RequestFlags::new(
-            false, false, false, false, false, false, false, false, true, false, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, false, false, false, false, false, true, false, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" k");
     }
 
     #[test]
     fn test_no_update_lru() {
         let flags = RequestFlags::new(
-            false, false, false, false, false, false, false, false, false, true, false, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, false, false, false, false, false, false, true, false, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" u");
     }
 
     #[test]
     fn test_mark_stale() {
         let flags = RequestFlags::new(
-            false, false, false, false, false, false, false, false, false, false, true, None, None,
-            None, None, None, None, None, None, None,
-        );
+            false, false, false, false, false, false, false, false, false, false, true, false,
+            None, None, None, None, None, None, None, None, None, None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" I");
     }
 
@@ -135,6 +149,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             Some(300),
             None,
             None,
@@ -144,7 +159,9 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" T300");
     }
 
@@ -162,6 +179,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             None,
             Some(60),
             None,
@@ -171,7 +189,9 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" R60");
     }
 
@@ -189,6 +209,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             None,
             None,
             Some(120),
@@ -198,7 +219,9 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" N120");
     }
 
@@ -216,6 +239,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             None,
             None,
             None,
@@ -225,7 +249,9 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" F42");
     }
 
@@ -243,6 +269,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             None,
             None,
             None,
@@ -252,7 +279,9 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" J100");
     }
 
@@ -270,6 +299,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             None,
             None,
             None,
@@ -279,7 +309,9 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" D5");
     }
 
@@ -297,6 +329,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             None,
             None,
             None,
@@ -306,7 +339,9 @@ mod tests {
             Some(999),
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" C999");
     }
 
@@ -324,6 +359,8 @@ mod tests {
             false,
             false,
             false,
+            false,
+            None,
             None,
             None,
             None,
@@ -333,10 +370,157 @@ mod tests {
             None,
             Some(b"token123".to_vec()),
             None,
-        );
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" Otoken123");
     }
 
+    fn flags_with_opaque(opaque: &[u8]) -> Result<RequestFlags, RequestFlagsError> {
+        RequestFlags::new(
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(opaque.to_vec()),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_opaque_max_len() {
+        assert!(flags_with_opaque(&[b'x'; 32]).is_ok());
+        assert_eq!(
+            flags_with_opaque(&[b'x'; 33]),
+            Err(RequestFlagsError::OpaqueTooLong)
+        );
+    }
+
+    #[test]
+    fn test_opaque_rejects_injection() {
+        assert!(flags_with_opaque(b"a b").is_err());
+        assert!(flags_with_opaque(b"id\r\nmd key").is_err());
+        assert!(flags_with_opaque(b"\x00").is_err());
+        assert!(flags_with_opaque(b"\xff").is_err());
+    }
+
+    #[test]
+    fn test_replace_rejects_invalid_opaque() {
+        let base = default_flags();
+        let updated = base.replace(
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(b"a b".to_vec()),
+            None,
+        );
+        assert!(updated.is_err());
+    }
+
+    fn flags_with_mode(mode: u8) -> Result<RequestFlags, RequestFlagsError> {
+        RequestFlags::new(
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(mode),
+        )
+    }
+
+    #[test]
+    fn test_mode_rejects_unknown() {
+        for mode in [
+            SET_MODE_ADD,
+            SET_MODE_APPEND,
+            SET_MODE_SET,
+            MA_MODE_INC,
+            MA_MODE_DEC,
+        ] {
+            assert!(flags_with_mode(mode).is_ok());
+        }
+        for mode in [b' ', b'\r', b'\n', b'X', 0, 0xff] {
+            assert_eq!(flags_with_mode(mode), Err(RequestFlagsError::InvalidMode));
+        }
+    }
+
+    #[test]
+    fn test_replace_rejects_invalid_mode() {
+        let updated = default_flags().replace(
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(b'\n'),
+        );
+        assert_eq!(updated, Err(RequestFlagsError::InvalidMode));
+    }
+
     // Mode optimization: SET_MODE_SET and MA_MODE_INC are defaults, not sent
     #[test]
     fn test_mode_set_not_sent() {
@@ -352,6 +536,8 @@ mod tests {
             false,
             false,
             false,
+            false,
+            None,
             None,
             None,
             None,
@@ -361,7 +547,8 @@ mod tests {
             None,
             None,
             Some(SET_MODE_SET),
-        );
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b"");
     }
 
@@ -379,6 +566,8 @@ mod tests {
             false,
             false,
             false,
+            false,
+            None,
             None,
             None,
             None,
@@ -388,7 +577,8 @@ mod tests {
             None,
             None,
             Some(MA_MODE_INC),
-        );
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b"");
     }
 
@@ -406,6 +596,8 @@ mod tests {
             false,
             false,
             false,
+            false,
+            None,
             None,
             None,
             None,
@@ -415,7 +607,8 @@ mod tests {
             None,
             None,
             Some(SET_MODE_ADD),
-        );
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" ME");
     }
 
@@ -433,6 +626,8 @@ mod tests {
             false,
             false,
             false,
+            false,
+            None,
             None,
             None,
             None,
@@ -442,7 +637,8 @@ mod tests {
             None,
             None,
             Some(SET_MODE_APPEND),
-        );
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" MA");
     }
 
@@ -460,6 +656,8 @@ mod tests {
             false,
             false,
             false,
+            false,
+            None,
             None,
             None,
             None,
@@ -469,7 +667,8 @@ mod tests {
             None,
             None,
             Some(MA_MODE_DEC),
-        );
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" M-");
     }
 
@@ -487,6 +686,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             None,
             None,
             None,
@@ -496,7 +696,9 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         let result = push_to_vec(&flags);
         let expected = format!(" J{} D{}", u64::MAX, u64::MAX);
         assert_eq!(result, expected.as_bytes());
@@ -516,6 +718,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             Some(0),
             Some(0),
             Some(0),
@@ -525,7 +728,9 @@ mod tests {
             Some(0),
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(push_to_vec(&flags), b" T0 R0 N0 F0 J0 D0 C0");
     }
 
@@ -544,6 +749,7 @@ mod tests {
             true,                 // k
             true,                 // u
             true,                 // I
+            true,                 // x
             Some(1),              // T
             Some(2),              // R
             Some(3),              // N
@@ -551,21 +757,25 @@ mod tests {
             Some(5),              // J
             Some(6),              // D
             Some(7),              // C
+            Some(8),              // E
             Some(b"op".to_vec()), // O
             Some(SET_MODE_ADD),   // M
-        );
+        )
+        .unwrap();
         assert_eq!(
             push_to_vec(&flags),
-            b" q f c v t s l h k u I T1 R2 N3 F4 J5 D6 C7 Oop ME"
+            b" q f c v t s l h k u I x T1 R2 N3 F4 J5 D6 C7 E8 Oop ME"
         );
     }
 
     // Helper: all-None replace call (no overrides)
     fn replace_none(flags: &RequestFlags) -> RequestFlags {
-        flags.replace(
-            None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-            None, None, None, None, None, None,
-        )
+        flags
+            .replace(
+                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+                None, None, None, None, None, None, None, None,
+            )
+            .unwrap()
     }
 
     #[test]
@@ -582,6 +792,7 @@ mod tests {
             false,
             false,
             false,
+            false,
             Some(300),
             None,
             None,
@@ -591,35 +802,41 @@ mod tests {
             None,
             None,
             None,
-        );
+            None,
+        )
+        .unwrap();
         assert_eq!(replace_none(&base), base);
     }
 
     #[test]
     fn test_replace_bool_flag() {
         let base = default_flags();
-        let updated = base.replace(
-            Some(true), // no_reply
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-        );
+        let updated = base
+            .replace(
+                Some(true), // no_reply
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
         assert_eq!(push_to_vec(&updated), b" q");
         // base is unchanged
         assert_eq!(push_to_vec(&base), b"");
@@ -628,28 +845,32 @@ mod tests {
     #[test]
     fn test_replace_optional_field() {
         let base = default_flags();
-        let updated = base.replace(
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            Some(600), // cache_ttl
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-        );
+        let updated = base
+            .replace(
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(600), // cache_ttl
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
         assert_eq!(push_to_vec(&updated), b" T600");
         assert_eq!(push_to_vec(&base), b"");
     }
@@ -658,10 +879,30 @@ mod tests {
     fn test_replace_none_keeps_existing_optional() {
         // Passing None for an optional field keeps the existing value, not unsets it
         let base = RequestFlags::new(
-            false, false, false, false, false, false, false, false, false, false, false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
             Some(300), // cache_ttl set
-            None, None, None, None, None, None, None, None,
-        );
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
         let updated = replace_none(&base);
         assert_eq!(push_to_vec(&updated), b" T300");
     }
@@ -669,23 +910,21 @@ mod tests {
     #[test]
     fn test_replace_multiple_fields() {
         let base = RequestFlags::new(
-            false, true, false, true, false, false, false, false, false, false, false, Some(60),
-            None, None, None, None, None, None, None, None,
-        );
-        let updated = base.replace(
-            Some(true), // add no_reply
-            None,       // keep return_client_flag=true
-            Some(true), // add return_cas_token
-            None,       // keep return_value=true
-            None,
-            None,
-            None,
-            None,
-            None,
+            false,
+            true,
+            false,
+            true,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            Some(60),
             None,
             None,
-            None,       // keep cache_ttl=60
-            Some(120),  // add recache_ttl
             None,
             None,
             None,
@@ -693,25 +932,127 @@ mod tests {
             None,
             None,
             None,
-        );
+        )
+        .unwrap();
+        let updated = base
+            .replace(
+                Some(true), // add no_reply
+                None,       // keep return_client_flag=true
+                Some(true), // add return_cas_token
+                None,       // keep return_value=true
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,      // keep cache_ttl=60
+                Some(120), // add recache_ttl
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
         assert_eq!(push_to_vec(&updated), b" q f c v T60 R120");
     }
 
     #[test]
     fn test_replace_opaque() {
         let base = default_flags();
-        let updated = base.replace(
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
+        let updated = base
+            .replace(
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(b"abc".to_vec()),
+                None,
+            )
+            .unwrap();
+        assert_eq!(push_to_vec(&updated), b" Oabc");
+        // base is unchanged
+        assert_eq!(push_to_vec(&base), b"");
+    }
+
+    #[test]
+    fn test_for_touch() {
+        let flags = RequestFlags::for_touch(None, 30, false);
+        assert_eq!(push_to_vec(&flags), b" T30");
+        let flags = RequestFlags::for_touch(None, 30, true);
+        assert_eq!(push_to_vec(&flags), b" v T30");
+    }
+
+    #[test]
+    fn test_for_touch_overrides_base() {
+        let base = default_flags()
+            .replace(
+                None,
+                None,
+                Some(true),
+                Some(true),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(600),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let flags = RequestFlags::for_touch(Some(&base), 30, false);
+        assert_eq!(push_to_vec(&flags), b" c T30");
+    }
+
+    #[test]
+    fn test_for_fill() {
+        let base = RequestFlags::new(
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            Some(30),
             None,
             None,
             None,
@@ -719,11 +1060,76 @@ mod tests {
             None,
             None,
             None,
-            Some(b"abc".to_vec()),
             None,
-        );
-        assert_eq!(push_to_vec(&updated), b" Oabc");
-        // base is unchanged
-        assert_eq!(push_to_vec(&base), b"");
+            Some(SET_MODE_APPEND),
+        )
+        .unwrap();
+        let flags = RequestFlags::for_fill(Some(&base), Some(42));
+        assert_eq!(push_to_vec(&flags), b" T30 C42");
+        let flags = RequestFlags::for_fill(Some(&base), None);
+        assert_eq!(push_to_vec(&flags), b" T30 ME");
+    }
+
+    #[test]
+    fn test_drops_chunks() {
+        assert!(RequestFlags::drops_chunks(None));
+        assert!(RequestFlags::drops_chunks(Some(&default_flags())));
+        let flags = RequestFlags::with_no_reply(None);
+        assert!(!RequestFlags::drops_chunks(Some(&flags)));
+        let flags = RequestFlags::for_invalidate(None, None);
+        assert!(!RequestFlags::drops_chunks(Some(&flags)));
+    }
+
+    #[test]
+    fn test_bypasses_near_cache() {
+        assert!(!RequestFlags::bypasses_near_cache(None));
+        assert!(!RequestFlags::bypasses_near_cache(Some(&default_flags())));
+        let flags = RequestFlags::for_counter(None, MA_MODE_INC, 1, Some(0), 30);
+        assert!(RequestFlags::bypasses_near_cache(Some(&flags)));
+    }
+
+    #[test]
+    fn test_appends() {
+        assert!(!RequestFlags::appends(None));
+        assert!(!RequestFlags::appends(Some(&default_flags())));
+        assert!(!RequestFlags::appends(Some(
+            &flags_with_mode(SET_MODE_SET).unwrap()
+        )));
+        assert!(RequestFlags::appends(Some(
+            &flags_with_mode(SET_MODE_APPEND).unwrap()
+        )));
+        assert!(RequestFlags::appends(Some(
+            &flags_with_mode(SET_MODE_PREPEND).unwrap()
+        )));
+    }
+
+    #[test]
+    fn test_for_chunk_set() {
+        let flags = RequestFlags::for_chunk_set(Some(&RequestFlags::for_touch(None, 30, false)));
+        assert_eq!(push_to_vec(&flags), b" T90");
+        let flags = RequestFlags::for_chunk_set(None);
+        assert_eq!(push_to_vec(&flags), b"");
+    }
+
+    #[test]
+    fn test_quiet_with_index() {
+        let flags = RequestFlags::for_touch(None, 30, false).quiet_with_index(12);
+        assert_eq!(push_to_vec(&flags), b" q T30 O12");
+    }
+
+    #[test]
+    fn test_for_read_repair() {
+        let flags = RequestFlags::for_read_repair(Some(42), Some(7), Some(60));
+        assert_eq!(push_to_vec(&flags), b" T60 F7 E42 ME");
+        let flags = RequestFlags::for_read_repair(None, None, None);
+        assert_eq!(push_to_vec(&flags), b" ME");
+    }
+
+    #[test]
+    fn test_for_replica_read() {
+        let flags = RequestFlags::for_replica_read(None);
+        assert_eq!(push_to_vec(&flags), b" f c v t");
+        let flags = RequestFlags::with_no_reply(Some(&flags));
+        assert_eq!(push_to_vec(&flags), b" q f c v t");
     }
 }
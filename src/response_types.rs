@@ -1,3 +1,4 @@
+use atoi::FromRadix10Checked;
 use pyo3::prelude::*;
 use pyo3::pyclass_init::PyClassInitializer;
 
@@ -107,3 +108,36 @@ impl Value {
         )
     }
 }
+
+/// Parse the ASCII digits returned as the value of a meta arithmetic command.
+pub fn parse_counter(value: &[u8]) -> Option<u64> {
+    match u64::from_radix_10_checked(value) {
+        (Some(v), len) if len == value.len() && len > 0 => Some(v),
+        _ => None,
+    }
+}
+
+/// Result of a meta arithmetic command with return_value: the counter value
+/// after the operation, already parsed into an integer.
+#[pyclass(extends=Success, skip_from_py_object)]
+pub struct Counter {
+    #[pyo3(get)]
+    pub value: u64,
+}
+
+#[pymethods]
+impl Counter {
+    #[new]
+    pub fn new(value: u64, flags: ResponseFlags) -> PyClassInitializer<Self> {
+        PyClassInitializer::from(Success::new(flags)).add_subclass(Counter { value })
+    }
+
+    pub fn __repr__(slf: PyRef<'_, Self>) -> String {
+        let super_ = slf.as_super();
+        format!(
+            "Counter(value={}, flags={})",
+            slf.value,
+            super_.flags.__str__()
+        )
+    }
+}
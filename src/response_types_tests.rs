@@ -0,0 +1,19 @@
+#[cfg(test)]
+mod tests {
+    use crate::response_types::parse_counter;
+
+    #[test]
+    fn test_parse_counter() {
+        assert_eq!(parse_counter(b"0"), Some(0));
+        assert_eq!(parse_counter(b"42"), Some(42));
+        assert_eq!(parse_counter(b"18446744073709551615"), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_parse_counter_invalid() {
+        assert_eq!(parse_counter(b""), None);
+        assert_eq!(parse_counter(b"-1"), None);
+        assert_eq!(parse_counter(b"12a"), None);
+        assert_eq!(parse_counter(b"18446744073709551616"), None);
+    }
+}
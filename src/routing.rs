@@ -0,0 +1,14 @@
+/// The part of `key` that selects its server: with Redis-style hash tags,
+/// the substring between the first `{` and the next `}`, if not empty, so
+/// keys sharing a tag (`user:{42}:profile`, `user:{42}:session`) land on
+/// the same server. Otherwise the whole key.
+pub fn routing_key(key: &[u8]) -> &[u8] {
+    let Some(open) = key.iter().position(|&c| c == b'{') else {
+        return key;
+    };
+    let tag = &key[open + 1..];
+    match tag.iter().position(|&c| c == b'}') {
+        Some(close) if close > 0 => &tag[..close],
+        _ => key,
+    }
+}
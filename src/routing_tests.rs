@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod tests {
+    use crate::routing::routing_key;
+
+    #[test]
+    fn test_tagged_key() {
+        assert_eq!(routing_key(b"user:{42}:profile"), b"42");
+        assert_eq!(routing_key(b"{42}"), b"42");
+        assert_eq!(
+            routing_key(b"user:{42}:profile"),
+            routing_key(b"session:{42}")
+        );
+    }
+
+    #[test]
+    fn test_untagged_key() {
+        assert_eq!(routing_key(b"user:42"), b"user:42");
+        assert_eq!(routing_key(b""), b"");
+    }
+
+    #[test]
+    fn test_only_first_tag() {
+        assert_eq!(routing_key(b"{a}{b}"), b"a");
+        assert_eq!(routing_key(b"x{a{b}c}"), b"a{b");
+    }
+
+    #[test]
+    fn test_empty_or_unclosed_tag() {
+        assert_eq!(routing_key(b"user:{}:42"), b"user:{}:42");
+        assert_eq!(routing_key(b"{}{42}"), b"{}{42}");
+        assert_eq!(routing_key(b"user:{42"), b"user:{42");
+        assert_eq!(routing_key(b"user:}42{"), b"user:}42{");
+    }
+}
@@ -0,0 +1,92 @@
+use pyo3::prelude::*;
+
+use crate::constants::*;
+
+/// First release (major, minor, patch) supporting each feature.
+/// Before 1.6.10 `ms` takes the size as an `S<size>` flag.
+const VERSION_TOKEN_SIZE: (u32, u32, u32) = (1, 6, 10);
+const VERSION_BINARY_KEYS: (u32, u32, u32) = (1, 6, 6);
+const VERSION_REMOVE_VALUE: (u32, u32, u32) = (1, 6, 13);
+const VERSION_EXPLICIT_CAS: (u32, u32, u32) = (1, 6, 15);
+const VERSION_META_PROTOCOL: (u32, u32, u32) = (1, 6, 0);
+
+/// Protocol features supported by the connected server.
+#[pyclass(frozen, eq, skip_from_py_object)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerCapabilities {
+    /// `ms` takes the size as an `S<size>` flag (1.6.6 and older).
+    #[pyo3(get)]
+    pub legacy_size_format: bool,
+    /// Base64-encoded binary keys (`b` flag).
+    #[pyo3(get)]
+    pub binary_keys: bool,
+    /// Explicit CAS on write (`E` flag).
+    #[pyo3(get)]
+    pub explicit_cas: bool,
+    /// Remove the value but keep the item on delete (`x` flag).
+    #[pyo3(get)]
+    pub remove_value: bool,
+    /// Vivify on miss (`N` flag).
+    #[pyo3(get)]
+    pub vivify_on_miss: bool,
+}
+
+impl ServerCapabilities {
+    /// Capability profile for a `SERVER_VERSION_*` constant.
+    pub fn from_server_version(version: u8) -> Self {
+        if version == SERVER_VERSION_AWS_1_6_6 {
+            Self::from_version_tuple((1, 6, 6))
+        } else {
+            Self::from_version_tuple(VERSION_EXPLICIT_CAS)
+        }
+    }
+
+    pub fn from_version_tuple(version: (u32, u32, u32)) -> Self {
+        ServerCapabilities {
+            legacy_size_format: version < VERSION_TOKEN_SIZE,
+            binary_keys: version >= VERSION_BINARY_KEYS,
+            explicit_cas: version >= VERSION_EXPLICIT_CAS,
+            remove_value: version >= VERSION_REMOVE_VALUE,
+            vivify_on_miss: version >= VERSION_META_PROTOCOL,
+        }
+    }
+
+    /// The `SERVER_VERSION_*` constant closest to this profile.
+    pub fn server_version(&self) -> u8 {
+        if self.legacy_size_format {
+            SERVER_VERSION_AWS_1_6_6
+        } else {
+            SERVER_VERSION_STABLE
+        }
+    }
+}
+
+#[pymethods]
+impl ServerCapabilities {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "ServerCapabilities(legacy_size_format={}, binary_keys={}, explicit_cas={}, remove_value={}, vivify_on_miss={})",
+            self.legacy_size_format,
+            self.binary_keys,
+            self.explicit_cas,
+            self.remove_value,
+            self.vivify_on_miss,
+        )
+    }
+}
+
+/// Parse the leading `major.minor.patch` of a server version string, as
+/// returned by the `version` command (e.g. "1.6.21"). Missing minor/patch
+/// parts default to 0; trailing suffixes are ignored.
+pub fn parse_server_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.splitn(3, '.').map(|part| {
+        let digits = part
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(part, |end| &part[..end]);
+        digits.parse::<u32>().ok()
+    });
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
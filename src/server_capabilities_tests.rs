@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod tests {
+    use crate::constants::*;
+    use crate::server_capabilities::{ServerCapabilities, parse_server_version};
+
+    #[test]
+    fn test_parse_server_version() {
+        assert_eq!(parse_server_version("1.6.21"), Some((1, 6, 21)));
+        assert_eq!(parse_server_version("1.6.6"), Some((1, 6, 6)));
+        assert_eq!(parse_server_version("1.6"), Some((1, 6, 0)));
+        assert_eq!(parse_server_version("1.6.12-rc1"), Some((1, 6, 12)));
+        assert_eq!(parse_server_version("1.6.x"), Some((1, 6, 0)));
+        assert_eq!(parse_server_version(""), None);
+        assert_eq!(parse_server_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_aws_1_6_6_profile() {
+        let caps = ServerCapabilities::from_server_version(SERVER_VERSION_AWS_1_6_6);
+        assert_eq!(caps, ServerCapabilities::from_version_tuple((1, 6, 6)));
+        assert!(caps.legacy_size_format);
+        assert!(caps.binary_keys);
+        assert!(!caps.explicit_cas);
+        assert!(!caps.remove_value);
+        assert!(caps.vivify_on_miss);
+        assert_eq!(caps.server_version(), SERVER_VERSION_AWS_1_6_6);
+    }
+
+    #[test]
+    fn test_stable_profile() {
+        let caps = ServerCapabilities::from_server_version(SERVER_VERSION_STABLE);
+        assert!(!caps.legacy_size_format);
+        assert!(caps.binary_keys);
+        assert!(caps.explicit_cas);
+        assert!(caps.remove_value);
+        assert!(caps.vivify_on_miss);
+        assert_eq!(caps.server_version(), SERVER_VERSION_STABLE);
+    }
+
+    #[test]
+    fn test_version_thresholds() {
+        let caps = ServerCapabilities::from_version_tuple((1, 6, 10));
+        assert!(!caps.legacy_size_format);
+        assert!(!caps.remove_value);
+        let caps = ServerCapabilities::from_version_tuple((1, 6, 13));
+        assert!(caps.remove_value);
+        assert!(!caps.explicit_cas);
+        let caps = ServerCapabilities::from_version_tuple((1, 5, 22));
+        assert!(caps.legacy_size_format);
+        assert!(!caps.binary_keys);
+        assert!(!caps.vivify_on_miss);
+        let caps = ServerCapabilities::from_version_tuple((2, 0, 0));
+        assert!(!caps.legacy_size_format);
+        assert!(caps.explicit_cas);
+    }
+}
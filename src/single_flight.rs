@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+use pyo3::prelude::*;
+
+use crate::sync::{lock, unpoisoned};
+
+enum CallState<T> {
+    Pending,
+    Done(T),
+    /// The leader went away without a result, followers fetch on their own.
+    Abandoned,
+}
+
+/// A request in flight, shared by its leader and followers.
+pub struct Call<T> {
+    state: Mutex<CallState<T>>,
+    finished: Condvar,
+}
+
+impl<T> Call<T> {
+    fn set(&self, state: CallState<T>) {
+        *lock(&self.state) = state;
+        self.finished.notify_all();
+    }
+
+    /// Block until the leader finished.
+    pub fn wait(&self) {
+        let mut state = lock(&self.state);
+        while matches!(*state, CallState::Pending) {
+            state = unpoisoned(self.finished.wait(state));
+        }
+    }
+
+    /// Apply `f` to the leader's result, None if it was abandoned. Call
+    /// after wait().
+    pub fn with_result<R>(&self, f: impl FnOnce(Option<&T>) -> R) -> R {
+        match &*lock(&self.state) {
+            CallState::Done(result) => f(Some(result)),
+            _ => f(None),
+        }
+    }
+}
+
+/// The caller that performs a request. Dropping it without finish()
+/// releases the followers to fetch on their own.
+pub struct Leader<'a, T> {
+    flights: &'a Flights<T>,
+    key: Vec<u8>,
+    call: Arc<Call<T>>,
+}
+
+impl<T> Leader<'_, T> {
+    /// Hand `result` to the followers. Later callers start a new request.
+    pub fn finish(self, result: T) {
+        lock(&self.flights.calls).remove(&self.key);
+        self.call.set(CallState::Done(result));
+    }
+}
+
+impl<T> Drop for Leader<'_, T> {
+    fn drop(&mut self) {
+        let mut calls = lock(&self.flights.calls);
+        if calls
+            .get(&self.key)
+            .is_some_and(|call| Arc::ptr_eq(call, &self.call))
+        {
+            calls.remove(&self.key);
+            drop(calls);
+            self.call.set(CallState::Abandoned);
+        }
+    }
+}
+
+pub enum Joined<'a, T> {
+    Leader(Leader<'a, T>),
+    Follower(Arc<Call<T>>),
+}
+
+/// Requests in flight by key. The first caller for a key leads and
+/// performs the request; callers arriving before it finishes follow and
+/// get its result.
+pub struct Flights<T> {
+    calls: Mutex<HashMap<Vec<u8>, Arc<Call<T>>>>,
+    pub leaders: AtomicU64,
+    pub followers: AtomicU64,
+}
+
+impl<T> Default for Flights<T> {
+    fn default() -> Self {
+        Flights {
+            calls: Mutex::new(HashMap::new()),
+            leaders: AtomicU64::new(0),
+            followers: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T> Flights<T> {
+    pub fn join(&self, key: &[u8]) -> Joined<'_, T> {
+        let mut calls = lock(&self.calls);
+        if let Some(call) = calls.get(key) {
+            self.followers.fetch_add(1, Ordering::Relaxed);
+            return Joined::Follower(call.clone());
+        }
+        let call = Arc::new(Call {
+            state: Mutex::new(CallState::Pending),
+            finished: Condvar::new(),
+        });
+        calls.insert(key.to_vec(), call.clone());
+        self.leaders.fetch_add(1, Ordering::Relaxed);
+        Joined::Leader(Leader {
+            flights: self,
+            key: key.to_vec(),
+            call,
+        })
+    }
+
+    /// Number of requests in flight.
+    pub fn len(&self) -> usize {
+        lock(&self.calls).len()
+    }
+}
+
+/// Coalesces concurrent identical meta_get() calls (same key and request
+/// flags) of the MemcacheSocket it is attached to with
+/// `MemcacheSocket.set_single_flight()`: one sends the request and the
+/// rest wait for it and get the same response object, or exception.
+/// Share one instance between the sockets of a pool. Thread-safe.
+#[pyclass(frozen)]
+#[derive(Default)]
+pub struct SingleFlight {
+    pub flights: Flights<PyResult<Py<PyAny>>>,
+}
+
+#[pymethods]
+impl SingleFlight {
+    #[new]
+    fn new() -> Self {
+        SingleFlight::default()
+    }
+
+    /// Counters: `requests` sent by leaders and `coalesced` calls that
+    /// waited for one instead.
+    fn get_stats(&self) -> HashMap<&'static str, u64> {
+        HashMap::from([
+            ("requests", self.flights.leaders.load(Ordering::Relaxed)),
+            ("coalesced", self.flights.followers.load(Ordering::Relaxed)),
+        ])
+    }
+
+    /// Number of requests in flight.
+    fn __len__(&self) -> usize {
+        self.flights.len()
+    }
+}
@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use crate::single_flight::{Flights, Joined};
+
+    fn result(call: &crate::single_flight::Call<u32>) -> Option<u32> {
+        call.with_result(|result| result.copied())
+    }
+
+    #[test]
+    fn test_first_caller_leads() {
+        let flights: Flights<u32> = Flights::default();
+        let Joined::Leader(leader) = flights.join(b"key") else {
+            panic!("expected leader");
+        };
+        let Joined::Follower(call) = flights.join(b"key") else {
+            panic!("expected follower");
+        };
+        assert!(matches!(flights.join(b"other"), Joined::Leader(_)));
+        assert_eq!(flights.len(), 1);
+        leader.finish(42);
+        call.wait();
+        assert_eq!(result(&call), Some(42));
+        assert_eq!(flights.len(), 0);
+        assert_eq!(flights.leaders.load(Ordering::Relaxed), 2);
+        assert_eq!(flights.followers.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_new_flight_after_finish() {
+        let flights: Flights<u32> = Flights::default();
+        let Joined::Leader(leader) = flights.join(b"key") else {
+            panic!("expected leader");
+        };
+        leader.finish(1);
+        assert!(matches!(flights.join(b"key"), Joined::Leader(_)));
+    }
+
+    #[test]
+    fn test_dropped_leader_abandons() {
+        let flights: Flights<u32> = Flights::default();
+        let leader = flights.join(b"key");
+        let Joined::Follower(call) = flights.join(b"key") else {
+            panic!("expected follower");
+        };
+        drop(leader);
+        call.wait();
+        assert_eq!(result(&call), None);
+        assert_eq!(flights.len(), 0);
+    }
+
+    #[test]
+    fn test_followers_wait_across_threads() {
+        let flights: Arc<Flights<u32>> = Arc::new(Flights::default());
+        let joined = Arc::new(Barrier::new(5));
+        let Joined::Leader(leader) = flights.join(b"key") else {
+            panic!("expected leader");
+        };
+        let followers: Vec<_> = (0..4)
+            .map(|_| {
+                let flights = flights.clone();
+                let joined = joined.clone();
+                thread::spawn(move || {
+                    let Joined::Follower(call) = flights.join(b"key") else {
+                        panic!("expected follower");
+                    };
+                    joined.wait();
+                    call.wait();
+                    result(&call)
+                })
+            })
+            .collect();
+        joined.wait();
+        leader.finish(7);
+        for follower in followers {
+            assert_eq!(follower.join().unwrap(), Some(7));
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use std::sync::{LockResult, Mutex, MutexGuard};
+
+/// Lock ignoring poisoning: the state guarded by the crate's mutexes stays
+/// consistent even if a holder panicked.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    unpoisoned(mutex.lock())
+}
+
+/// The guard of a lock or condvar wait, ignoring poisoning as lock() does.
+pub fn unpoisoned<G>(result: LockResult<G>) -> G {
+    result.unwrap_or_else(|e| e.into_inner())
+}
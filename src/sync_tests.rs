@@ -0,0 +1,22 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::sync::lock;
+
+    #[test]
+    fn test_lock_poisoned() {
+        let mutex = Mutex::new(1);
+        let _ = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _guard = mutex.lock().unwrap();
+                    panic!("poison");
+                })
+                .join()
+        });
+        assert!(mutex.is_poisoned());
+        *lock(&mutex) += 1;
+        assert_eq!(*lock(&mutex), 2);
+    }
+}
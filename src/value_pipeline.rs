@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+
+use crate::checksum::Checksum;
+use crate::compression::Compression;
+use crate::constants::*;
+use crate::encryption::{Encryption, SEAL_OVERHEAD};
+
+/// Why a stored value could not be unpacked.
+#[derive(Debug, PartialEq)]
+pub enum UnpackError {
+    Checksum(String),
+    Decompression(String),
+    Decryption(String),
+}
+
+/// The transforms applied to values between meta_set and the server, and
+/// undone on read: compression, encryption, then a checksum trailer. Each
+/// stage marks the values it transformed with its bits in client_flag. Only
+/// enabled stages are applied or undone; bits of disabled stages are left
+/// alone.
+#[derive(Default)]
+pub struct ValuePipeline {
+    pub compression: Option<Compression>,
+    pub encryption: Option<Encryption>,
+    pub checksum: Option<Checksum>,
+}
+
+impl ValuePipeline {
+    pub fn is_enabled(&self) -> bool {
+        self.compression.is_some() || self.encryption.is_some() || self.checksum.is_some()
+    }
+
+    /// Most bytes pack() can add to a value: compressed output is only
+    /// kept if smaller, encryption and checksums add a fixed overhead.
+    pub fn max_overhead(&self) -> usize {
+        let encryption = if self.encryption.is_some() {
+            SEAL_OVERHEAD
+        } else {
+            0
+        };
+        encryption + self.checksum.as_ref().map_or(0, Checksum::trailer_len)
+    }
+
+    /// client_flag bits owned by the enabled stages.
+    pub fn mask(&self) -> u32 {
+        let mut mask = 0;
+        if self.compression.is_some() {
+            mask |= COMPRESSION_MASK;
+        }
+        if self.encryption.is_some() {
+            mask |= ENCRYPTION_MASK | ENCRYPTION_KEY_ID_MASK;
+        }
+        if self.checksum.is_some() {
+            mask |= CHECKSUM_MASK;
+        }
+        mask
+    }
+
+    /// Check that stored values can be appended or prepended to: not with
+    /// compression or encryption, whose output only decodes whole, nor
+    /// checksums, as each write brings its own trailer.
+    pub fn check_append(&self) -> Result<(), String> {
+        let stage = if self.compression.is_some() {
+            "compression"
+        } else if self.encryption.is_some() {
+            "encryption"
+        } else if self.checksum.is_some() {
+            "checksums"
+        } else {
+            return Ok(());
+        };
+        Err(format!(
+            "Values can't be appended or prepended to with {stage} enabled"
+        ))
+    }
+
+    /// Transform `value` of `key` for storing. Returns the bytes to send and
+    /// the client_flag to store them with, with the bits of the enabled
+    /// stages replaced by the ones marking the applied transforms.
+    pub fn pack<'a>(
+        &self,
+        key: &[u8],
+        value: &'a [u8],
+        client_flag: Option<u32>,
+    ) -> Result<(Cow<'a, [u8]>, Option<u32>), String> {
+        let mut client_flag = client_flag.map(|flag| flag & !self.mask());
+        let mut value = Cow::Borrowed(value);
+        if let Some(compression) = &self.compression
+            && compression.should_compress(&value)
+            && let Some(compressed) = compression
+                .compress(&value)
+                .map_err(|e| format!("Error compressing value: {e}"))?
+        {
+            client_flag = Some(client_flag.unwrap_or(0) | compression.flag());
+            value = Cow::Owned(compressed);
+        }
+        // The checksum bit is set before encrypting, as the AAD covers the
+        // stored client_flag
+        if let Some(checksum) = &self.checksum {
+            client_flag = Some(client_flag.unwrap_or(0) | checksum.flag());
+        }
+        if let Some(encryption) = &self.encryption {
+            let flag = client_flag.unwrap_or(0) | encryption.flag();
+            value = Cow::Owned(encryption.encrypt(flag, key, &value)?);
+            client_flag = Some(flag);
+        }
+        if let Some(checksum) = &self.checksum {
+            let mut checked = value.into_owned();
+            checksum.append(&mut checked);
+            value = Cow::Owned(checked);
+        }
+        Ok((value, client_flag))
+    }
+
+    /// Whether a value stored with `client_flag` needs unpack(). With
+    /// encryption enabled every value does, as unencrypted ones are
+    /// rejected, counters included.
+    pub fn is_packed(&self, client_flag: u32) -> bool {
+        if self.encryption.is_some() {
+            return true;
+        }
+        let mut packed = 0;
+        if self.compression.is_some() {
+            packed |= COMPRESSION_MASK;
+        }
+        if self.checksum.is_some() {
+            packed |= CHECKSUM_MASK;
+        }
+        client_flag & packed != 0
+    }
+
+    /// Undo pack() on a value of `key` stored with `client_flag`. Returns
+    /// the original value and client_flag. With encryption enabled, values
+    /// that aren't encrypted fail, so whoever can write to the servers can't
+    /// inject plaintext. Without the key encrypted values can't be
+    /// authenticated and fail too.
+    pub fn unpack(
+        &self,
+        key: Option<&[u8]>,
+        data: &[u8],
+        client_flag: u32,
+    ) -> Result<(Vec<u8>, u32), UnpackError> {
+        let mut value = Cow::Borrowed(data);
+        if let Some(checksum) = &self.checksum
+            && client_flag & CHECKSUM_MASK != 0
+        {
+            value = Cow::Borrowed(
+                checksum
+                    .verify(client_flag, data)
+                    .map_err(UnpackError::Checksum)?,
+            );
+        }
+        if let Some(encryption) = &self.encryption {
+            if client_flag & ENCRYPTION_MASK == 0 {
+                return Err(UnpackError::Decryption("Value is not encrypted".into()));
+            }
+            let key = key.ok_or_else(|| {
+                UnpackError::Decryption(
+                    "Encrypted values can only be read with meta_get or send_meta_get".into(),
+                )
+            })?;
+            value = Cow::Owned(
+                encryption
+                    .decrypt(client_flag, key, &value)
+                    .map_err(UnpackError::Decryption)?,
+            );
+        }
+        if let Some(compression) = &self.compression
+            && client_flag & COMPRESSION_MASK != 0
+        {
+            value = Cow::Owned(
+                compression
+                    .decompress(client_flag, &value)
+                    .map_err(|e| UnpackError::Decompression(e.to_string()))?,
+            );
+        }
+        Ok((value.into_owned(), client_flag & !self.mask()))
+    }
+}
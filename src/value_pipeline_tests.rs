@@ -0,0 +1,140 @@
+#[cfg(test)]
+mod tests {
+    use crate::checksum::Checksum;
+    use crate::compression::Compression;
+    use crate::constants::*;
+    use crate::encryption::Encryption;
+    use crate::value_pipeline::{UnpackError, ValuePipeline};
+
+    fn pipeline(compress: bool, encrypt: bool) -> ValuePipeline {
+        ValuePipeline {
+            compression: compress
+                .then(|| Compression::new(COMPRESSION_ZSTD, 16, None, None).unwrap()),
+            encryption: encrypt.then(|| {
+                Encryption::new(vec![(2, vec![2u8; 32])], None, ENCRYPTION_AES_GCM, false).unwrap()
+            }),
+            checksum: None,
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        b"a compressible value. ".repeat(50)
+    }
+
+    #[test]
+    fn test_disabled_passthrough() {
+        let pipeline = pipeline(false, false);
+        assert!(!pipeline.is_enabled());
+        let (value, flag) = pipeline.pack(b"key", b"value", Some(5)).unwrap();
+        assert_eq!((&*value, flag), (&b"value"[..], Some(5)));
+        assert!(!pipeline.is_packed(COMPRESSION_ZSTD | ENCRYPTION_AES_GCM));
+    }
+
+    #[test]
+    fn test_compress_then_encrypt() {
+        let pipeline = pipeline(true, true);
+        let value = sample();
+        let (packed, flag) = pipeline.pack(b"key", &value, Some(CODEC_JSON)).unwrap();
+        let flag = flag.unwrap();
+        assert_eq!(
+            flag,
+            CODEC_JSON | COMPRESSION_ZSTD | ENCRYPTION_AES_GCM | 2 << ENCRYPTION_KEY_ID_SHIFT
+        );
+        assert!(packed.len() < value.len());
+        assert!(pipeline.is_packed(flag));
+        assert_eq!(
+            pipeline.unpack(Some(b"key"), &packed, flag).unwrap(),
+            (value, CODEC_JSON)
+        );
+    }
+
+    #[test]
+    fn test_max_overhead() {
+        assert_eq!(pipeline(true, false).max_overhead(), 0);
+        let mut pipeline = pipeline(true, true);
+        pipeline.checksum = Some(Checksum::new(CHECKSUM_XXH3, false).unwrap());
+        let (packed, _) = pipeline.pack(b"key", b"incompressible", None).unwrap();
+        assert_eq!(
+            packed.len(),
+            b"incompressible".len() + pipeline.max_overhead()
+        );
+    }
+
+    #[test]
+    fn test_check_append() {
+        assert!(pipeline(false, false).check_append().is_ok());
+        assert!(pipeline(true, false).check_append().is_err());
+        assert!(pipeline(false, true).check_append().is_err());
+        let mut pipeline = pipeline(false, false);
+        pipeline.checksum = Some(Checksum::new(CHECKSUM_CRC32C, false).unwrap());
+        assert!(pipeline.check_append().is_err());
+    }
+
+    #[test]
+    fn test_stray_bits_cleared() {
+        let pipeline = pipeline(true, false);
+        let (value, flag) = pipeline
+            .pack(
+                b"key",
+                b"small",
+                Some(COMPRESSION_LZ4 | ENCRYPTION_AES_GCM | 1),
+            )
+            .unwrap();
+        assert_eq!(&*value, b"small");
+        // Encryption is disabled, so its bits are not ours to clear
+        assert_eq!(flag, Some(ENCRYPTION_AES_GCM | 1));
+    }
+
+    #[test]
+    fn test_unencrypted_rejected() {
+        let pipeline = pipeline(true, true);
+        assert!(pipeline.is_packed(0));
+        assert_eq!(
+            pipeline.unpack(Some(b"key"), b"plain", 0),
+            Err(UnpackError::Decryption("Value is not encrypted".into()))
+        );
+    }
+
+    #[test]
+    fn test_encrypted_needs_key() {
+        let pipeline = pipeline(false, true);
+        let (packed, flag) = pipeline.pack(b"key", b"value", None).unwrap();
+        assert!(matches!(
+            pipeline.unpack(None, &packed, flag.unwrap()),
+            Err(UnpackError::Decryption(_))
+        ));
+        assert!(matches!(
+            pipeline.unpack(Some(b"other"), &packed, flag.unwrap()),
+            Err(UnpackError::Decryption(_))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_compressed_value() {
+        let pipeline = pipeline(true, false);
+        assert!(matches!(
+            pipeline.unpack(Some(b"key"), b"garbage", COMPRESSION_ZSTD),
+            Err(UnpackError::Decompression(_))
+        ));
+    }
+
+    #[test]
+    fn test_checksum_covers_encrypted_value() {
+        let mut pipeline = pipeline(true, true);
+        pipeline.checksum = Some(Checksum::new(CHECKSUM_CRC32C, false).unwrap());
+        let value = sample();
+        let (packed, flag) = pipeline.pack(b"key", &value, None).unwrap();
+        let flag = flag.unwrap();
+        assert_eq!(flag & CHECKSUM_MASK, CHECKSUM_CRC32C);
+        assert_eq!(
+            pipeline.unpack(Some(b"key"), &packed, flag).unwrap(),
+            (sample(), 0)
+        );
+        let mut corrupt = packed.to_vec();
+        corrupt[0] ^= 1;
+        assert!(matches!(
+            pipeline.unpack(Some(b"key"), &corrupt, flag),
+            Err(UnpackError::Checksum(_))
+        ));
+    }
+}
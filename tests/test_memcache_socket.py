@@ -5,22 +5,79 @@ but tests the Rust implementation directly.
 """
 
 import base64
+import gc
 import hashlib
+import pickle
 import socket
+import threading
+import time
 
 import pytest
 
 from meta_memcache_socket import (
+    ClusterConfig,
+    ClusterDiscovery,
+    ClusterNode,
     Conflict,
+    Counter,
+    DebugInfo,
+    KeyPolicy,
+    KeyRecorder,
+    Lease,
+    MemcacheLock,
     MemcacheSocket,
+    MigrationClient,
     Miss,
+    NearCache,
     NotStored,
+    ReplicaSet,
+    ReplicationError,
     RequestFlags,
     ResponseFlags,
+    ServerCapabilities,
+    SingleFlight,
     Success,
     Value,
     SERVER_VERSION_AWS_1_6_6,
     SERVER_VERSION_STABLE,
+    SET_MODE_ADD,
+    SET_MODE_APPEND,
+    SET_MODE_PREPEND,
+    LEASE_HIT,
+    LEASE_HIT_STALE_MUST_RECOMPUTE,
+    LEASE_MISS_MUST_COMPUTE,
+    LEASE_MISS_WAIT_FOR_OTHERS,
+    CHECKSUM_CRC32C,
+    CHECKSUM_XXH3,
+    ChecksumError,
+    CHUNKED,
+    DEFAULT_CHUNK_SIZE,
+    DEFAULT_NAMESPACE_REFRESH_INTERVAL,
+    CODEC_BINARY,
+    CODEC_INT,
+    CODEC_JSON,
+    CODEC_PICKLE,
+    CODEC_STR,
+    COMPRESSION_LZ4,
+    COMPRESSION_MASK,
+    COMPRESSION_ZSTD,
+    DecryptionError,
+    ENCRYPTION_AES_GCM,
+    ENCRYPTION_CHACHA20_POLY1305,
+    ENCRYPTION_KEY_ID_SHIFT,
+    DEFAULT_KEY_RECORDER_SIZE,
+    DEFAULT_DISCOVERY_POLL_INTERVAL,
+    KEY_HASH_BLAKE2B,
+    KEY_HASH_MD5,
+    KEY_HASH_SHA1,
+    KEY_HASH_XXH3,
+    build_cmd,
+    decode_wire_key,
+    encode_key,
+    routing_key,
+    build_meta_get,
+    build_meta_debug,
+    build_meta_touch,
 )
 
 
@@ -38,6 +95,15 @@ def socket_pair():
         pass
 
 
+@pytest.fixture
+def replicas():
+    pairs = [socket.socketpair() for _ in range(3)]
+    yield [MemcacheSocket(a) for a, _ in pairs], [b for _, b in pairs]
+    for a, b in pairs:
+        a.close()
+        b.close()
+
+
 # --- Constructor and basic methods ---
 
 
@@ -740,8 +806,9 @@ class TestMetaBlocking:
         flags = RequestFlags(ma_delta_value=5, return_value=True)
         b.sendall(b"VA 2\r\n10\r\n")
         resp = ms.meta_arithmetic(b"counter", flags)
-        assert isinstance(resp, Value)
-        assert resp.value == b"10"
+        assert isinstance(resp, Counter)
+        assert isinstance(resp, Success)
+        assert resp.value == 10
         data = b.recv(1024)
         assert data == b"ma counter v D5\r\n"
 
@@ -1060,3 +1127,2731 @@ class TestRequestFlagsReplace:
         flags = RequestFlags(return_value=True)
         with pytest.raises(AttributeError):
             flags.return_value = False  # type: ignore[misc]
+
+
+class TestProtocolInjection:
+    def test_opaque_max_len(self):
+        assert RequestFlags(opaque=b"x" * 32).opaque == b"x" * 32
+        with pytest.raises(ValueError):
+            RequestFlags(opaque=b"x" * 33)
+
+    def test_opaque_with_whitespace(self):
+        with pytest.raises(ValueError):
+            RequestFlags(opaque=b"req id")
+        with pytest.raises(ValueError):
+            RequestFlags(opaque=b"id\r\nmd victim")
+
+    def test_opaque_non_printable(self):
+        with pytest.raises(ValueError):
+            RequestFlags(opaque=b"\x00")
+        with pytest.raises(ValueError):
+            RequestFlags(opaque=b"\xff")
+
+    def test_replace_validates_opaque(self):
+        with pytest.raises(ValueError):
+            RequestFlags().replace(opaque=b"a b")
+
+    def test_invalid_mode(self):
+        assert RequestFlags(mode=SET_MODE_ADD).mode == SET_MODE_ADD
+        with pytest.raises(ValueError, match="mode"):
+            RequestFlags(mode=ord(" "))
+        with pytest.raises(ValueError, match="mode"):
+            RequestFlags(mode=ord("\n"))
+        with pytest.raises(ValueError, match="mode"):
+            RequestFlags().replace(mode=ord("X"))
+
+    def test_build_cmd_invalid_cmd(self):
+        with pytest.raises(ValueError):
+            build_cmd(b"mg key\r\nmd", b"other")
+        with pytest.raises(ValueError):
+            build_cmd(b"", b"key")
+        assert build_cmd(b"mg", b"key") == b"mg key\r\n"
+
+
+class TestMetaDebug:
+    def test_build_meta_debug(self):
+        assert build_meta_debug(b"foo") == b"me foo\r\n"
+
+    def test_build_meta_debug_binary_key(self):
+        key = b"foo\x00"
+        expected_b64 = base64.b64encode(key)
+        assert build_meta_debug(key) == b"me " + expected_b64 + b" b\r\n"
+
+    def test_meta_debug_hit(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"ME foo exp=-1 la=3 cas=2 fetch=no cls=1 size=63\r\n")
+        info = ms.meta_debug(b"foo")
+        assert b.recv(1024) == b"me foo\r\n"
+        assert isinstance(info, DebugInfo)
+        assert info == DebugInfo(
+            b"foo",
+            ttl=-1,
+            last_access=3,
+            cas_token=2,
+            fetched=False,
+            slab_class=1,
+            size=63,
+        )
+
+    def test_meta_debug_miss(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_debug(b"foo"), Miss)
+
+    def test_meta_debug_hashed_key(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        key = "x" * 300
+        expected = base64.b64encode(
+            hashlib.blake2b(key.encode(), digest_size=18).digest()
+        )
+        b.sendall(b"ME " + expected + b" exp=10 fetch=yes\r\n")
+        info = ms.meta_debug(key)
+        assert b.recv(1024) == b"me " + expected + b" b\r\n"
+        assert info.wire_key == expected
+        assert info.ttl == 10
+        assert info.fetched is True
+
+    def test_meta_debug_empty_key(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError):
+            ms.meta_debug(b"")
+
+
+class TestAdminCommands:
+    def test_version(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VERSION 1.6.21\r\n")
+        assert ms.version() == "1.6.21"
+        assert b.recv(1024) == b"version\r\n"
+
+    def test_version_error(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"ERROR\r\n")
+        with pytest.raises(ConnectionError):
+            ms.version()
+
+    def test_stats(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(
+            b"STAT pid 1234\r\n"
+            b"STAT rusage_user 0.123456\r\n"
+            b"STAT version 1.6.21\r\n"
+            b"END\r\n"
+        )
+        stats = ms.stats()
+        assert b.recv(1024) == b"stats\r\n"
+        assert stats == {"pid": 1234, "rusage_user": 0.123456, "version": "1.6.21"}
+
+    def test_stats_slabs(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(
+            b"STAT 1:chunk_size 96\r\n"
+            b"STAT 1:used_chunks 3\r\n"
+            b"STAT 2:chunk_size 120\r\n"
+            b"STAT active_slabs 2\r\n"
+            b"END\r\n"
+        )
+        stats = ms.stats("slabs")
+        assert b.recv(1024) == b"stats slabs\r\n"
+        assert stats == {
+            1: {"chunk_size": 96, "used_chunks": 3},
+            2: {"chunk_size": 120},
+            "active_slabs": 2,
+        }
+
+    def test_stats_items(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"STAT items:1:number 5\r\nSTAT items:1:age 10\r\nEND\r\n")
+        assert ms.stats("items") == {1: {"number": 5, "age": 10}}
+
+    def test_stats_conns(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"STAT 23:addr tcp:127.0.0.1:11211\r\nEND\r\n")
+        assert ms.stats("conns") == {23: {"addr": "tcp:127.0.0.1:11211"}}
+
+    def test_stats_empty(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"END\r\n")
+        assert ms.stats("settings") == {}
+
+    def test_stats_unknown_group(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError):
+            ms.stats("detail dump")
+
+    def test_stats_larger_than_buffer(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a, buffer_size=64)
+        lines = b"".join(b"STAT items:%d:number %d\r\n" % (i, i) for i in range(100))
+        b.sendall(lines + b"END\r\nEN\r\n")
+        stats = ms.stats("items")
+        assert len(stats) == 100
+        assert stats[99] == {"number": 99}
+        assert isinstance(ms.get_response(), Miss)
+
+    def test_stats_error(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"CLIENT_ERROR bad command\r\n")
+        with pytest.raises(ConnectionError):
+            ms.stats()
+
+    def test_flush_all(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"OK\r\n")
+        assert isinstance(ms.flush_all(), Success)
+        assert b.recv(1024) == b"flush_all\r\n"
+
+    def test_flush_all_delay_no_reply(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        assert isinstance(ms.flush_all(10, no_reply=True), Success)
+        assert b.recv(1024) == b"flush_all 10 noreply\r\nmn\r\n"
+        # The NOOP is drained before the next response
+        b.sendall(b"MN\r\nVERSION 1.6.21\r\n")
+        assert ms.version() == "1.6.21"
+
+    def test_verbosity(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"OK\r\n")
+        assert isinstance(ms.verbosity(1), Success)
+        assert b.recv(1024) == b"verbosity 1\r\n"
+
+    def test_verbosity_no_reply(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        assert isinstance(ms.verbosity(0, no_reply=True), Success)
+        assert b.recv(1024) == b"verbosity 0 noreply\r\nmn\r\n"
+
+    def test_quit(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.quit()
+        assert b.recv(1024) == b"quit\r\n"
+        assert a.fileno() == -1
+
+
+class TestDetectVersion:
+    def test_default_capabilities(self, socket_pair):
+        a, b = socket_pair
+        caps = MemcacheSocket(a).get_capabilities()
+        assert isinstance(caps, ServerCapabilities)
+        assert caps.legacy_size_format is False
+        assert caps.binary_keys is True
+        assert caps.explicit_cas is True
+        assert caps.remove_value is True
+        assert caps.vivify_on_miss is True
+
+    def test_aws_capabilities(self, socket_pair):
+        a, b = socket_pair
+        caps = MemcacheSocket(a, version=SERVER_VERSION_AWS_1_6_6).get_capabilities()
+        assert caps.legacy_size_format is True
+        assert caps.explicit_cas is False
+        assert caps.remove_value is False
+
+    def test_detect_stable(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a, version=SERVER_VERSION_AWS_1_6_6)
+        b.sendall(b"VERSION 1.6.21\r\n")
+        assert ms.detect_version() == SERVER_VERSION_STABLE
+        assert b.recv(1024) == b"version\r\n"
+        assert ms.get_version() == SERVER_VERSION_STABLE
+        assert ms.get_capabilities().legacy_size_format is False
+
+    def test_detect_legacy_uses_legacy_size(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VERSION 1.6.6\r\n")
+        assert ms.detect_version() == SERVER_VERSION_AWS_1_6_6
+        b.recv(1024)
+        ms.send_meta_set(b"key", b"val")
+        assert b.recv(1024) == b"ms key S3\r\nval\r\n"
+
+    def test_detect_unknown_version_assumes_latest(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a, version=SERVER_VERSION_AWS_1_6_6)
+        b.sendall(b"VERSION unknown\r\n")
+        assert ms.detect_version() == SERVER_VERSION_STABLE
+
+    def test_old_server_rejects_unsupported(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VERSION 1.5.22\r\n")
+        ms.detect_version()
+        b.recv(1024)
+        with pytest.raises(ValueError, match="vivify_on_miss_ttl"):
+            ms.send_meta_get(b"key", RequestFlags(vivify_on_miss_ttl=30))
+        with pytest.raises(ValueError, match="Binary"):
+            ms.send_meta_get(b"key\x00")
+        with pytest.raises(ValueError, match="Binary"):
+            ms.send_meta_get(b"k" * 300)
+        # Nothing hit the wire
+        ms.send_meta_get(b"key")
+        assert b.recv(1024) == b"mg key\r\n"
+
+
+class TestExplicitCasAndRemoveValue:
+    def test_flags_to_bytes(self):
+        flags = RequestFlags(mark_stale=True, remove_value=True, cas_token=5, explicit_cas=2**40)
+        assert flags.to_bytes() == b" I x C5 E1099511627776"
+        assert flags.remove_value is True
+        assert flags.explicit_cas == 2**40
+
+    def test_replace(self):
+        flags = RequestFlags(explicit_cas=1).replace(remove_value=True)
+        assert flags.remove_value is True
+        assert flags.explicit_cas == 1
+        assert flags.replace(explicit_cas=2).explicit_cas == 2
+
+    def test_delete_remove_value(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.send_meta_delete(b"key", RequestFlags(remove_value=True))
+        assert b.recv(1024) == b"md key x\r\n"
+
+    def test_set_explicit_cas(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.send_meta_set(b"key", b"val", RequestFlags(explicit_cas=99))
+        assert b.recv(1024) == b"ms key 3 E99\r\nval\r\n"
+
+    def test_aws_rejects_explicit_cas_and_remove_value(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a, version=SERVER_VERSION_AWS_1_6_6)
+        with pytest.raises(ValueError, match="explicit_cas"):
+            ms.send_meta_set(b"key", b"val", RequestFlags(explicit_cas=99))
+        with pytest.raises(ValueError, match="remove_value"):
+            ms.send_meta_delete(b"key", RequestFlags(remove_value=True))
+        # Invalidate-if-CAS-older only needs I and C
+        ms.send_meta_set(b"key", b"val", RequestFlags(mark_stale=True, cas_token=5))
+        assert b.recv(1024) == b"ms key S3 I C5\r\nval\r\n"
+
+
+class TestCounter:
+    def test_counter_type(self):
+        counter = Counter(7, ResponseFlags(cas_token=3))
+        assert isinstance(counter, Success)
+        assert counter.value == 7
+        assert counter.flags.cas_token == 3
+        assert repr(counter).startswith("Counter(value=7, flags=")
+
+    def test_meta_arithmetic_keeps_flags(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        flags = RequestFlags(return_value=True, return_cas_token=True, return_ttl=True)
+        b.sendall(b"VA 20 c9 t-1\r\n18446744073709551615\r\n")
+        resp = ms.meta_arithmetic(b"counter", flags)
+        assert resp.value == 2**64 - 1
+        assert resp.flags.cas_token == 9
+        assert resp.flags.ttl == -1
+
+    def test_meta_arithmetic_invalid_value(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 3\r\nabc\r\n")
+        with pytest.raises(ConnectionError, match="Invalid counter value"):
+            ms.meta_arithmetic(b"counter", RequestFlags(return_value=True))
+
+    def test_meta_arithmetic_without_value(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"HD\r\n")
+        resp = ms.meta_arithmetic(b"counter")
+        assert type(resp) is Success
+
+    def test_meta_incr(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1\r\n1\r\n")
+        resp = ms.meta_incr(b"counter")
+        assert isinstance(resp, Counter)
+        assert resp.value == 1
+        assert b.recv(1024) == b"ma counter v D1\r\n"
+
+    def test_meta_decr(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1\r\n5\r\n")
+        resp = ms.meta_decr(b"counter", 5)
+        assert resp.value == 5
+        assert b.recv(1024) == b"ma counter v D5 M-\r\n"
+
+    def test_meta_incr_autovivify(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 3\r\n100\r\n")
+        resp = ms.meta_incr(b"counter", 2, initial_value=100, initial_ttl=60)
+        assert resp.value == 100
+        assert b.recv(1024) == b"ma counter v N60 J100 D2\r\n"
+
+    def test_meta_incr_miss(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"NF\r\n")
+        assert isinstance(ms.meta_incr(b"counter"), Miss)
+
+    def test_meta_incr_merges_request_flags(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        flags = RequestFlags(return_cas_token=True, cache_ttl=30)
+        b.sendall(b"VA 1 c4\r\n2\r\n")
+        resp = ms.meta_incr(b"counter", request_flags=flags)
+        assert resp.flags.cas_token == 4
+        assert b.recv(1024) == b"ma counter c v T30 D1\r\n"
+
+    def test_meta_incr_no_reply(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        resp = ms.meta_incr(b"counter", request_flags=RequestFlags(no_reply=True))
+        assert type(resp) is Success
+        assert b.recv(1024) == b"ma counter q v D1\r\nmn\r\n"
+
+
+class TestTouch:
+    def test_build_meta_touch(self):
+        assert build_meta_touch(b"key", 30) == b"mg key T30\r\n"
+        flags = RequestFlags(return_value=True, return_ttl=True)
+        assert build_meta_touch(b"key", 30, flags) == b"mg key t T30\r\n"
+
+    def test_meta_touch_hit(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"HD\r\n")
+        assert ms.meta_touch(b"key", 30) is True
+        assert b.recv(1024) == b"mg key T30\r\n"
+
+    def test_meta_touch_miss(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"EN\r\n")
+        assert ms.meta_touch(b"key", 30) is False
+
+    def test_meta_get_and_touch(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 3 f1\r\nabc\r\n")
+        resp = ms.meta_get_and_touch(b"key", 30, RequestFlags(return_client_flag=True))
+        assert isinstance(resp, Value)
+        assert resp.value == b"abc"
+        assert resp.flags.client_flag == 1
+        assert b.recv(1024) == b"mg key f v T30\r\n"
+
+    def test_meta_get_and_touch_miss(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_get_and_touch(b"key", 30), Miss)
+
+    def test_meta_touch_many(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        # Misses are suppressed in quiet mode
+        b.sendall(b"HD O0\r\nHD O2\r\nMN\r\n")
+        assert ms.meta_touch_many([b"a", "b", b"c"], 30) == [True, False, True]
+        assert b.recv(1024) == (
+            b"mg a q T30 O0\r\nmg b q T30 O1\r\nmg c q T30 O2\r\nmn\r\n"
+        )
+
+    def test_meta_touch_many_empty(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        assert ms.meta_touch_many([], 30) == []
+
+    def test_meta_get_and_touch_many(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        # Responses can be matched back to keys regardless of order
+        b.sendall(b"VA 2 O1\r\nbb\r\nVA 1 O0\r\na\r\nMN\r\n")
+        resp = ms.meta_get_and_touch_many([b"a", b"b", b"c"], 60)
+        assert [r.value for r in resp[:2]] == [b"a", b"bb"]
+        assert isinstance(resp[2], Miss)
+        assert b.recv(1024) == (
+            b"mg a q v T60 O0\r\nmg b q v T60 O1\r\nmg c q v T60 O2\r\nmn\r\n"
+        )
+
+    def test_meta_get_and_touch_many_large_value(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a, buffer_size=64)
+        big = b"x" * 200
+        b.sendall(b"VA 200 O0\r\n" + big + b"\r\nVA 1 O1\r\ny\r\nMN\r\n")
+        resp = ms.meta_get_and_touch_many([b"a", b"b"], 60)
+        assert resp[0].value == big
+        assert resp[1].value == b"y"
+
+    def test_meta_touch_many_invalid_opaque(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"HD O7\r\nVA 1 O0\r\nx\r\nMN\r\n")
+        with pytest.raises(ConnectionError, match="opaque"):
+            ms.meta_touch_many([b"a"], 30)
+        # Drained up to the MN, so the next command reads its own response
+        b.sendall(b"MN\r\n")
+        assert ms.meta_touch_many([b"a"], 30) == [False]
+
+    def test_meta_touch_many_after_no_reply(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.send_meta_delete(b"x", RequestFlags(no_reply=True))
+        b.sendall(b"MN\r\nHD O0\r\nMN\r\n")
+        assert ms.meta_touch_many([b"a"], 30) == [True]
+
+
+class TestLease:
+    def test_hit(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 3 c7\r\nabc\r\n")
+        lease = ms.meta_get_lease(b"key", 30)
+        assert isinstance(lease, Lease)
+        assert lease.status == LEASE_HIT
+        assert lease.value == b"abc"
+        assert lease.has_value and not lease.must_compute
+        assert b.recv(1024) == b"mg key c v N30\r\n"
+
+    def test_miss_must_compute(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 0 c7 W\r\n\r\n")
+        lease = ms.meta_get_lease("key", 30, recache_ttl=10)
+        assert lease.status == LEASE_MISS_MUST_COMPUTE
+        assert lease.must_compute and not lease.has_value
+        assert lease.value is None
+        assert lease.cas_token == 7
+        assert lease.key == b"key"
+        assert b.recv(1024) == b"mg key c v R10 N30\r\n"
+
+    def test_miss_wait_for_others(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 0 c7 Z\r\n\r\n")
+        lease = ms.meta_get_lease(b"key", 30)
+        assert lease.status == LEASE_MISS_WAIT_FOR_OTHERS
+        assert not lease.must_compute and not lease.has_value
+
+    def test_stale_must_recompute(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 3 c7 W X\r\nold\r\n")
+        lease = ms.meta_get_lease(b"key", 30)
+        assert lease.status == LEASE_HIT_STALE_MUST_RECOMPUTE
+        assert lease.value == b"old"
+        assert lease.must_compute and lease.has_value
+
+    def test_stale_served_while_others_recompute(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 3 c7 X Z\r\nold\r\n")
+        lease = ms.meta_get_lease(b"key", 30)
+        assert lease.status == LEASE_HIT
+        assert lease.value == b"old"
+
+    def test_fill_lease(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 0 c7 W\r\n\r\nHD\r\n")
+        lease = ms.meta_get_lease(b"key", 30)
+        assert b.recv(1024) == b"mg key c v N30\r\n"
+        resp = ms.fill_lease(lease, b"new", RequestFlags(cache_ttl=300))
+        assert isinstance(resp, Success)
+        assert b.recv(1024) == b"ms key 3 T300 C7\r\nnew\r\n"
+
+    def test_fill_lease_conflict(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lease = Lease(b"key", LEASE_MISS_MUST_COMPUTE, flags=ResponseFlags(cas_token=7))
+        b.sendall(b"EX\r\n")
+        assert isinstance(ms.fill_lease(lease, b"new"), Conflict)
+
+    def test_fill_lease_without_cas_adds(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lease = Lease(b"key", LEASE_MISS_MUST_COMPUTE)
+        b.sendall(b"HD\r\n")
+        ms.fill_lease(lease, b"new")
+        assert b.recv(1024) == b"ms key 3 ME\r\nnew\r\n"
+
+    def test_fill_lease_not_won(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        for status in (LEASE_HIT, LEASE_MISS_WAIT_FOR_OTHERS):
+            with pytest.raises(ValueError, match="not won"):
+                ms.fill_lease(Lease(b"key", status), b"new")
+
+    def test_invalidate(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"HD\r\nNF\r\n")
+        assert isinstance(ms.invalidate(b"key", 30), Success)
+        assert isinstance(ms.invalidate(b"other"), Miss)
+        assert b.recv(1024) == b"md key I T30\r\nmd other I\r\n"
+
+    def test_repr(self):
+        lease = Lease(b"key", LEASE_HIT_STALE_MUST_RECOMPUTE, flags=ResponseFlags(cas_token=1))
+        assert repr(lease) == 'Lease(key="key", status=HIT_STALE_MUST_RECOMPUTE, cas_token=Some(1))'
+
+
+class TestCasUpdate:
+    def test_update_existing(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1 c5\r\n1\r\nHD\r\n")
+        seen = []
+
+        def incr(value):
+            seen.append(value)
+            return str(int(value) + 1).encode()
+
+        resp = ms.cas_update(b"key", incr, request_flags=RequestFlags(cache_ttl=60))
+        assert isinstance(resp, Success)
+        assert seen == [b"1"]
+        assert b.recv(1024) == b"mg key c v\r\nms key 1 T60 C5\r\n2\r\n"
+
+    def test_miss_uses_add(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"EN\r\nHD\r\n")
+        resp = ms.cas_update(b"key", lambda value: b"new" if value is None else value)
+        assert isinstance(resp, Success)
+        assert b.recv(1024) == b"mg key c v\r\nms key 3 ME\r\nnew\r\n"
+
+    def test_retries_on_conflict(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1 c5\r\na\r\nEX\r\nVA 2 c6\r\nab\r\nHD\r\n")
+        seen = []
+
+        def append(value):
+            seen.append(value)
+            return value + b"x"
+
+        assert isinstance(ms.cas_update(b"key", append), Success)
+        assert seen == [b"a", b"ab"]
+
+    def test_retries_when_add_loses_race(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"EN\r\nNS\r\nVA 1 c9\r\nz\r\nHD\r\n")
+        seen = []
+
+        def update(value):
+            seen.append(value)
+            return b"v"
+
+        assert isinstance(ms.cas_update(b"key", update), Success)
+        assert seen == [None, b"z"]
+
+    def test_retries_when_deleted(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1 c5\r\na\r\nNF\r\nEN\r\nHD\r\n")
+        seen = []
+
+        def update(value):
+            seen.append(value)
+            return b"v"
+
+        assert isinstance(ms.cas_update(b"key", update), Success)
+        assert seen == [b"a", None]
+        assert b.recv(1024) == (
+            b"mg key c v\r\nms key 1 C5\r\nv\r\nmg key c v\r\nms key 1 ME\r\nv\r\n"
+        )
+
+    def test_mode_not_sent_with_cas(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1 c5\r\na\r\nHD\r\n")
+        flags = RequestFlags(mode=SET_MODE_APPEND)
+        assert isinstance(ms.cas_update(b"key", lambda v: v + b"b", request_flags=flags), Success)
+        assert b.recv(1024) == b"mg key c v\r\nms key 2 C5\r\nab\r\n"
+
+    def test_gives_up_after_max_retries(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1 c5\r\na\r\nEX\r\n" * 3)
+        calls = []
+        resp = ms.cas_update(b"key", lambda v: calls.append(v) or v, max_retries=2, backoff=0.001)
+        assert isinstance(resp, Conflict)
+        assert len(calls) == 3
+
+    def test_func_error_propagates(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1 c5\r\na\r\n")
+
+        def fail(value):
+            raise KeyError("boom")
+
+        with pytest.raises(KeyError):
+            ms.cas_update(b"key", fail)
+
+    def test_func_must_return_bytes(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"EN\r\n")
+        with pytest.raises(TypeError):
+            ms.cas_update(b"key", lambda value: 1)
+
+    def test_rejects_no_reply_and_bad_backoff(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError, match="no_reply"):
+            ms.cas_update(b"key", lambda v: v, request_flags=RequestFlags(no_reply=True))
+        with pytest.raises(ValueError, match="backoff"):
+            ms.cas_update(b"key", lambda v: v, backoff=-1.0)
+
+
+class TestMemcacheLock:
+    def test_acquire_and_release(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock", ttl=10)
+        b.sendall(b"HD c5\r\n")
+        assert lock.acquire(blocking=False) is True
+        assert lock.locked
+        token = lock.token
+        assert len(token) == 32
+        assert b.recv(1024) == b"ms lock 32 c T10 ME\r\n" + token + b"\r\n"
+
+        b.sendall(b"HD\r\n")
+        assert lock.release() is True
+        assert not lock.locked
+        assert b.recv(1024) == b"md lock C5\r\n"
+
+    def test_failed_release_can_be_retried(self, socket_pair):
+        a, b = socket_pair
+        a.settimeout(0.05)
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock")
+        b.sendall(b"HD c5\r\n")
+        assert lock.acquire(blocking=False) is True
+        b.recv(1024)
+        with pytest.raises(TimeoutError):
+            lock.release()
+        # Still held on the server
+        assert lock.locked
+        assert b.recv(1024) == b"md lock C5\r\n"
+        b.sendall(b"HD\r\n")
+        assert lock.release() is True
+        assert not lock.locked
+        assert b.recv(1024) == b"md lock C5\r\n"
+
+    def test_acquire_busy(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, "lock")
+        b.sendall(b"NS\r\n")
+        assert lock.acquire(blocking=False) is False
+        assert not lock.locked
+        with pytest.raises(RuntimeError, match="not acquired"):
+            lock.release()
+
+    def test_blocking_acquire_polls(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock", poll_interval=0.001)
+        b.sendall(b"NS\r\nNS\r\nHD c1\r\n")
+        assert lock.acquire(timeout=5) is True
+        # The same token is used on every attempt
+        data = b""
+        while data.count(b"\r\n") < 6:
+            data += b.recv(4096)
+        lines = data.split(b"\r\n")
+        assert lines[1] == lines[3] == lines[5] == lock.token
+
+    def test_blocking_acquire_timeout(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock", poll_interval=0.01)
+        b.sendall(b"NS\r\n" * 200)
+        start = time.monotonic()
+        assert lock.acquire(timeout=0.05) is False
+        assert time.monotonic() - start < 1
+
+    def test_release_lost_lock(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock")
+        b.sendall(b"HD c5\r\nEX\r\n")
+        lock.acquire(blocking=False)
+        assert lock.release() is False
+        assert not lock.locked
+
+    def test_extend(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock", ttl=10)
+        b.sendall(b"HD c5\r\n")
+        lock.acquire(blocking=False)
+        b.recv(1024)
+        b.sendall(b"HD c6\r\nHD\r\n")
+        assert lock.extend(60) is True
+        assert b.recv(1024) == b"ms lock 32 c T60 C5\r\n" + lock.token + b"\r\n"
+        # Release uses the CAS returned by extend
+        assert lock.release() is True
+        assert b.recv(1024) == b"md lock C6\r\n"
+
+    def test_extend_lost_lock(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock")
+        b.sendall(b"HD c5\r\nNF\r\n")
+        lock.acquire(blocking=False)
+        assert lock.extend() is False
+        assert not lock.locked
+
+    def test_context_manager(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"HD c5\r\nHD\r\n")
+        with MemcacheLock(ms, b"lock") as lock:
+            assert lock.locked
+        assert not lock.locked
+
+    def test_double_acquire(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        lock = MemcacheLock(ms, b"lock")
+        b.sendall(b"HD c5\r\n")
+        lock.acquire(blocking=False)
+        with pytest.raises(RuntimeError, match="already acquired"):
+            lock.acquire()
+
+    def test_invalid_args(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError):
+            MemcacheLock(ms, b"")
+        with pytest.raises(ValueError, match="ttl"):
+            MemcacheLock(ms, b"lock", ttl=0)
+        with pytest.raises(ValueError, match="poll_interval"):
+            MemcacheLock(ms, b"lock", poll_interval=-1)
+        with pytest.raises(ValueError, match="poll_interval must be a positive number"):
+            MemcacheLock(ms, b"lock", poll_interval=0.0)
+        with pytest.raises(ValueError, match="timeout"):
+            MemcacheLock(ms, b"lock").acquire(timeout=-1)
+
+
+class TestCodecs:
+    def _roundtrip(self, socket_pair, obj, ms=None, **kwargs):
+        a, b = socket_pair
+        ms = ms or MemcacheSocket(a)
+        b.sendall(b"HD\r\n")
+        assert isinstance(ms.meta_set_object(b"key", obj, **kwargs), Success)
+        sent = b.recv(4096)
+        header, data = sent.split(b"\r\n", 1)
+        flags = header.split(b" ")
+        client_flag = int(next(f[1:] for f in flags if f.startswith(b"F")))
+        data = data[:-2]
+        b.sendall(b"VA %d f%d\r\n%s\r\n" % (len(data), client_flag, data))
+        resp = ms.meta_get_object(b"key")
+        assert isinstance(resp, Value)
+        assert b.recv(1024) == b"mg key f v\r\n"
+        return client_flag, data, resp.value
+
+    def test_bytes(self, socket_pair):
+        assert self._roundtrip(socket_pair, b"\x00raw") == (CODEC_BINARY, b"\x00raw", b"\x00raw")
+
+    def test_str(self, socket_pair):
+        assert self._roundtrip(socket_pair, "h\u00e9llo") == (CODEC_STR, "h\u00e9llo".encode(), "h\u00e9llo")
+
+    def test_int(self, socket_pair):
+        assert self._roundtrip(socket_pair, -42) == (CODEC_INT, b"-42", -42)
+
+    def test_big_int(self, socket_pair):
+        big = 2**100
+        assert self._roundtrip(socket_pair, big) == (CODEC_INT, str(big).encode(), big)
+
+    def test_bool_is_pickled(self, socket_pair):
+        client_flag, _, value = self._roundtrip(socket_pair, True)
+        assert client_flag == CODEC_PICKLE
+        assert value is True
+
+    def test_json(self, socket_pair):
+        obj = {"a": [1, 2], "b": None}
+        client_flag, data, value = self._roundtrip(socket_pair, obj, codec=CODEC_JSON)
+        assert client_flag == CODEC_JSON
+        assert data == b'{"a": [1, 2], "b": null}'
+        assert value == obj
+
+    def test_pickle_fallback(self, socket_pair):
+        obj = {"a": (1, 2)}
+        client_flag, data, value = self._roundtrip(socket_pair, obj)
+        assert client_flag == CODEC_PICKLE
+        assert pickle.loads(data) == obj
+        assert value == obj
+
+    def test_keeps_high_client_flag_bits(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"HD\r\n")
+        ms.meta_set_object(b"key", 7, request_flags=RequestFlags(client_flag=0x100 | CODEC_JSON))
+        assert b.recv(1024) == b"ms key 1 F%d\r\n7\r\n" % (0x100 | CODEC_INT)
+
+    def test_custom_codec(self, socket_pair):
+        class Point:
+            def __init__(self, x, y):
+                self.x, self.y = x, y
+
+        ms = MemcacheSocket(socket_pair[0])
+        ms.register_codec(
+            100,
+            lambda p: b"%d,%d" % (p.x, p.y),
+            lambda data: Point(*map(int, data.split(b","))),
+            types=Point,
+        )
+        client_flag, data, value = self._roundtrip(socket_pair, Point(1, 2), ms=ms)
+        assert (client_flag, data) == (100, b"1,2")
+        assert (value.x, value.y) == (1, 2)
+
+    def test_register_rejects_builtin_and_out_of_range(self, socket_pair):
+        a, _ = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError, match="reserved"):
+            ms.register_codec(CODEC_JSON, bytes, bytes)
+        with pytest.raises(ValueError, match="at most"):
+            ms.register_codec(256, bytes, bytes)
+
+    def test_unknown_codec_on_read(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 1 f77\r\nx\r\n")
+        with pytest.raises(ValueError, match="Unknown codec"):
+            ms.meta_get_object(b"key")
+
+    def test_miss(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_get_object(b"key"), Miss)
+
+
+class TestCompression:
+    VALUE = b"compressible " * 100
+
+    def _store(self, ms, b, value, **kwargs):
+        """meta_set value, return the (header, data) sent on the wire."""
+        b.sendall(b"HD\r\n")
+        assert isinstance(ms.meta_set(b"key", value, **kwargs), Success)
+        header, data = b.recv(65536).split(b"\r\n", 1)
+        return header, data[:-2]
+
+    def _client_flag(self, header):
+        return int(next(f[1:] for f in header.split(b" ") if f.startswith(b"F")))
+
+    @pytest.mark.parametrize("algorithm", [COMPRESSION_ZSTD, COMPRESSION_LZ4])
+    def test_roundtrip(self, socket_pair, algorithm):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(algorithm, threshold=100)
+        header, data = self._store(ms, b, self.VALUE)
+        assert self._client_flag(header) == algorithm
+        assert len(data) < len(self.VALUE)
+        b.sendall(b"VA %d f%d\r\n%s\r\n" % (len(data), algorithm, data))
+        resp = ms.meta_get(b"key", RequestFlags(return_value=True))
+        assert b.recv(1024) == b"mg key f v\r\n"
+        assert isinstance(resp, Value)
+        assert resp.value == self.VALUE
+        assert resp.size == len(self.VALUE)
+        assert resp.flags.client_flag == 0
+
+    def test_append_rejected(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=100)
+        for mode in (SET_MODE_APPEND, SET_MODE_PREPEND):
+            # Even below the threshold, the stored value may be compressed
+            with pytest.raises(ValueError, match="compression"):
+                ms.meta_set(b"key", b"x", RequestFlags(mode=mode))
+            with pytest.raises(ValueError, match="compression"):
+                ms.send_meta_set(b"key", b"x", RequestFlags(mode=mode))
+        header, _ = self._store(ms, b, b"x", request_flags=RequestFlags(mode=SET_MODE_ADD))
+        assert header == b"ms key 1 ME"
+
+    def test_zstd_dictionary_and_level(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=0, level=19, dictionary=b"compressible " * 8)
+        header, data = self._store(ms, b, self.VALUE)
+        b.sendall(b"VA %d f%d\r\n%s\r\n" % (len(data), COMPRESSION_ZSTD, data))
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == self.VALUE
+
+    def test_below_threshold_is_not_compressed(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=len(self.VALUE) + 1)
+        header, data = self._store(ms, b, self.VALUE)
+        assert header == b"ms key %d" % len(self.VALUE)
+        assert data == self.VALUE
+
+    def test_keeps_client_flag_and_clears_stray_bits(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_LZ4, threshold=100)
+        flags = RequestFlags(client_flag=COMPRESSION_ZSTD | CODEC_JSON)
+        header, _ = self._store(ms, b, self.VALUE, request_flags=flags)
+        assert self._client_flag(header) == COMPRESSION_LZ4 | CODEC_JSON
+        header, _ = self._store(ms, b, b"small", request_flags=flags)
+        assert self._client_flag(header) == CODEC_JSON
+
+    def test_with_codecs(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=100)
+        obj = ["compressible"] * 100
+        b.sendall(b"HD\r\n")
+        ms.meta_set_object(b"key", obj, codec=CODEC_JSON)
+        header, data = b.recv(65536).split(b"\r\n", 1)
+        data = data[:-2]
+        client_flag = self._client_flag(header)
+        assert client_flag == COMPRESSION_ZSTD | CODEC_JSON
+        b.sendall(b"VA %d f%d\r\n%s\r\n" % (len(data), client_flag, data))
+        assert ms.meta_get_object(b"key").value == obj
+
+    def test_pipelined_get(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_LZ4, threshold=100)
+        _, data = self._store(ms, b, self.VALUE)
+        ms.send_meta_get(b"key", RequestFlags(return_value=True))
+        assert b.recv(1024) == b"mg key f v\r\n"
+        b.sendall(b"VA %d f%d\r\n%s\r\n" % (len(data), COMPRESSION_LZ4, data))
+        assert ms.get_response().value == self.VALUE
+
+    def test_corrupt_value_raises(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD)
+        b.sendall(b"VA 7 f%d\r\ngarbage\r\n" % COMPRESSION_ZSTD)
+        with pytest.raises(ValueError, match="decompressing"):
+            ms.meta_get(b"key", RequestFlags(return_value=True))
+
+    def test_valueless_hit_left_alone(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=1)
+        b.sendall(b"HD f%d t100\r\n" % COMPRESSION_ZSTD)
+        resp = ms.meta_get(b"k", RequestFlags(return_ttl=True))
+        assert isinstance(resp, Success) and not isinstance(resp, Value)
+        assert resp.flags.ttl == 100
+
+    def test_disabled_leaves_values_alone(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=0)
+        ms.set_compression(None)
+        header, data = self._store(ms, b, self.VALUE)
+        assert header == b"ms key %d" % len(self.VALUE)
+        b.sendall(b"VA 3 f%d\r\nabc\r\n" % COMPRESSION_ZSTD)
+        resp = ms.meta_get(b"key", RequestFlags(return_value=True))
+        assert b.recv(1024) == b"mg key v\r\n"
+        assert resp.value == b"abc"
+
+    def test_invalid_settings(self, socket_pair):
+        a, _ = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError, match="Unknown compression"):
+            ms.set_compression(COMPRESSION_MASK)
+        with pytest.raises(ValueError, match="only supported"):
+            ms.set_compression(COMPRESSION_LZ4, level=3)
+        with pytest.raises(ValueError, match="level"):
+            ms.set_compression(COMPRESSION_ZSTD, level=1000)
+
+
+class TestEncryption:
+    KEYS = {1: b"\x01" * 32, 2: b"\x02" * 32}
+
+    def _store(self, ms, b, key, value, **kwargs):
+        """meta_set value, return the (client_flag, data) sent on the wire."""
+        b.sendall(b"HD\r\n")
+        assert isinstance(ms.meta_set(key, value, **kwargs), Success)
+        header, data = b.recv(65536).split(b"\r\n", 1)
+        client_flag = int(next(f[1:] for f in header.split(b" ") if f.startswith(b"F")))
+        return client_flag, data[:-2]
+
+    def _serve(self, b, client_flag, data):
+        b.sendall(b"VA %d f%d\r\n%s\r\n" % (len(data), client_flag, data))
+
+    @pytest.mark.parametrize("algorithm", [ENCRYPTION_AES_GCM, ENCRYPTION_CHACHA20_POLY1305])
+    def test_roundtrip(self, socket_pair, algorithm):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS, algorithm=algorithm)
+        client_flag, data = self._store(ms, b, b"key", b"secret", request_flags=RequestFlags(client_flag=5))
+        assert client_flag == 5 | algorithm | 2 << ENCRYPTION_KEY_ID_SHIFT
+        assert b"secret" not in data
+        assert len(data) == 12 + len(b"secret") + 16
+        self._serve(b, client_flag, data)
+        resp = ms.meta_get(b"key", RequestFlags(return_value=True))
+        assert b.recv(1024) == b"mg key f v\r\n"
+        assert resp.value == b"secret"
+        assert resp.flags.client_flag == 5
+
+    def test_append_rejected(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        for mode in (SET_MODE_APPEND, SET_MODE_PREPEND):
+            with pytest.raises(ValueError, match="encryption"):
+                ms.meta_set(b"key", b"x", RequestFlags(mode=mode))
+        self._store(ms, b, b"key", b"x", request_flags=RequestFlags(mode=SET_MODE_ADD))
+
+    def test_counter_needs_meta_arithmetic(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        # A counter written by ma is plaintext
+        b.sendall(b"VA 1 f0\r\n5\r\n")
+        with pytest.raises(DecryptionError):
+            ms.meta_get(b"key", RequestFlags(return_value=True))
+        b.recv(1024)
+        b.sendall(b"VA 1\r\n5\r\n")
+        assert ms.meta_incr(b"key", delta=0).value == 5
+        assert b.recv(1024) == b"ma key v D0\r\n"
+
+    def test_key_rotation(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption({1: self.KEYS[1]})
+        client_flag, data = self._store(ms, b, b"key", b"old")
+        assert client_flag >> ENCRYPTION_KEY_ID_SHIFT == 1
+        ms.set_encryption(self.KEYS)
+        self._serve(b, client_flag, data)
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == b"old"
+        assert b.recv(1024) == b"mg key f v\r\n"
+        client_flag, _ = self._store(ms, b, b"key", b"new")
+        assert client_flag >> ENCRYPTION_KEY_ID_SHIFT == 2
+
+    def test_value_bound_to_key(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        client_flag, data = self._store(ms, b, b"alice", b"secret")
+        self._serve(b, client_flag, data)
+        with pytest.raises(DecryptionError):
+            ms.meta_get(b"mallory", RequestFlags(return_value=True))
+        assert issubclass(DecryptionError, ValueError)
+
+    def test_miss_on_error(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS, miss_on_error=True)
+        client_flag, data = self._store(ms, b, b"key", b"secret")
+        self._serve(b, client_flag, data[:-1] + bytes([data[-1] ^ 1]))
+        assert isinstance(ms.meta_get(b"key", RequestFlags(return_value=True)), Miss)
+        # The connection is still in sync
+        self._serve(b, client_flag, data)
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == b"secret"
+
+    def test_unknown_key_id(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        client_flag, data = self._store(ms, b, b"key", b"secret")
+        ms.set_encryption({1: self.KEYS[1]})
+        self._serve(b, client_flag, data)
+        with pytest.raises(DecryptionError, match="key id 2"):
+            ms.meta_get(b"key", RequestFlags(return_value=True))
+
+    def test_valueless_hit_left_alone(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        client_flag, _ = self._store(ms, b, b"key", b"secret")
+        b.sendall(b"HD f%d t100\r\n" % client_flag)
+        resp = ms.meta_get(b"key", RequestFlags(return_client_flag=True, return_ttl=True))
+        assert isinstance(resp, Success) and resp.flags.ttl == 100
+
+    def test_pipelined_read(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        client_flag, data = self._store(ms, b, b"key", b"secret")
+        ms.send_meta_set(b"other", b"value")
+        ms.send_meta_get(b"key", RequestFlags(return_value=True))
+        b.sendall(b"HD\r\n")
+        self._serve(b, client_flag, data)
+        assert isinstance(ms.get_response(), Success)
+        assert ms.get_response().value == b"secret"
+
+    def test_raw_pipelined_read_fails(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        client_flag, data = self._store(ms, b, b"key", b"secret")
+        ms.sendall(b"mg key f v\r\n", False)
+        self._serve(b, client_flag, data)
+        with pytest.raises(DecryptionError, match="meta_get"):
+            ms.get_response()
+
+    def test_unencrypted_values_rejected(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        self._serve(b, 0, b"plain")
+        with pytest.raises(DecryptionError, match="not encrypted"):
+            ms.meta_get(b"key", RequestFlags(return_value=True))
+        ms.set_encryption(self.KEYS, miss_on_error=True)
+        self._serve(b, 0, b"plain")
+        assert isinstance(ms.meta_get(b"key", RequestFlags(return_value=True)), Miss)
+
+    def test_lease_placeholder_accepted(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_encryption(self.KEYS)
+        b.sendall(b"VA 0 f0 c7 W\r\n\r\n")
+        assert ms.meta_get_lease(b"key", 30).must_compute
+
+    def test_with_compression_and_codecs(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=100)
+        ms.set_encryption(self.KEYS)
+        obj = ["compressible"] * 100
+        b.sendall(b"HD\r\n")
+        ms.meta_set_object(b"key", obj, codec=CODEC_JSON)
+        header, data = b.recv(65536).split(b"\r\n", 1)
+        client_flag = int(next(f[1:] for f in header.split(b" ") if f.startswith(b"F")))
+        assert client_flag & COMPRESSION_ZSTD
+        assert client_flag & ENCRYPTION_AES_GCM
+        self._serve(b, client_flag, data[:-2])
+        assert ms.meta_get_object(b"key").value == obj
+
+    def test_invalid_settings(self, socket_pair):
+        a, _ = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError, match="32 bytes"):
+            ms.set_encryption({1: b"short"})
+        with pytest.raises(ValueError, match="At least one"):
+            ms.set_encryption({})
+        with pytest.raises(ValueError, match="Unknown encryption key id 3"):
+            ms.set_encryption(self.KEYS, key_id=3)
+        with pytest.raises(ValueError, match="at most 255"):
+            ms.set_encryption({256: self.KEYS[1]})
+        with pytest.raises(ValueError, match="Unknown encryption algorithm"):
+            ms.set_encryption(self.KEYS, algorithm=COMPRESSION_ZSTD)
+        ms.set_encryption(None)
+
+
+class TestChecksum:
+    def _store(self, ms, b, value, **kwargs):
+        """meta_set value, return the (client_flag, data) sent on the wire."""
+        b.sendall(b"HD\r\n")
+        assert isinstance(ms.meta_set(b"key", value, **kwargs), Success)
+        header, data = b.recv(65536).split(b"\r\n", 1)
+        client_flag = int(next(f[1:] for f in header.split(b" ") if f.startswith(b"F")))
+        return client_flag, data[:-2]
+
+    def _serve(self, b, client_flag, data):
+        b.sendall(b"VA %d f%d\r\n%s\r\n" % (len(data), client_flag, data))
+
+    @pytest.mark.parametrize("algorithm,trailer_len", [(CHECKSUM_CRC32C, 4), (CHECKSUM_XXH3, 8)])
+    def test_roundtrip(self, socket_pair, algorithm, trailer_len):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(algorithm)
+        client_flag, data = self._store(ms, b, b"value", request_flags=RequestFlags(client_flag=3))
+        assert client_flag == 3 | algorithm
+        assert data[:-trailer_len] == b"value"
+        assert len(data) == 5 + trailer_len
+        self._serve(b, client_flag, data)
+        resp = ms.meta_get(b"key", RequestFlags(return_value=True))
+        assert b.recv(1024) == b"mg key f v\r\n"
+        assert resp.value == b"value"
+        assert resp.size == 5
+        assert resp.flags.client_flag == 3
+        assert ms.get_checksum_failures() == 0
+
+    def test_append_rejected(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_CRC32C)
+        for mode in (SET_MODE_APPEND, SET_MODE_PREPEND):
+            with pytest.raises(ValueError, match="checksums"):
+                ms.meta_set(b"key", b"x", RequestFlags(mode=mode))
+        ms.set_checksum(None)
+        b.sendall(b"HD\r\n")
+        assert isinstance(ms.meta_set(b"key", b"x", RequestFlags(mode=SET_MODE_APPEND)), Success)
+        assert b.recv(1024) == b"ms key 1 MA\r\nx\r\n"
+
+    def test_valueless_hit_left_alone(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_CRC32C)
+        b.sendall(b"HD f%d t100\r\n" % CHECKSUM_CRC32C)
+        resp = ms.meta_get(b"key", RequestFlags(return_client_flag=True, return_ttl=True))
+        assert isinstance(resp, Success) and resp.flags.ttl == 100
+        assert ms.get_checksum_failures() == 0
+
+    def test_crc32c_check_value(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_CRC32C)
+        _, data = self._store(ms, b, b"123456789")
+        assert data[-4:] == (0xE3069283).to_bytes(4, "little")
+
+    def test_mismatch_raises(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_CRC32C)
+        client_flag, data = self._store(ms, b, b"value")
+        self._serve(b, client_flag, b"valuf" + data[5:])
+        with pytest.raises(ChecksumError, match="mismatch"):
+            ms.meta_get(b"key", RequestFlags(return_value=True))
+        assert ms.get_checksum_failures() == 1
+        assert issubclass(ChecksumError, ValueError)
+
+    def test_mismatch_miss_on_error(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_XXH3, miss_on_error=True)
+        client_flag, data = self._store(ms, b, b"value")
+        self._serve(b, client_flag, data[:-1])
+        assert isinstance(ms.meta_get(b"key", RequestFlags(return_value=True)), Miss)
+        assert ms.get_checksum_failures() == 1
+        # The connection is still in sync
+        self._serve(b, client_flag, data)
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == b"value"
+
+    def test_pipelined_get(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_CRC32C)
+        client_flag, data = self._store(ms, b, b"value")
+        ms.send_meta_get(b"key", RequestFlags(return_value=True))
+        assert b.recv(1024) == b"mg key f v\r\n"
+        self._serve(b, client_flag, data)
+        assert ms.get_response().value == b"value"
+
+    def test_with_compression_and_encryption(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_compression(COMPRESSION_LZ4, threshold=100)
+        ms.set_encryption({1: b"k" * 32})
+        ms.set_checksum(CHECKSUM_XXH3)
+        value = b"compressible " * 100
+        client_flag, data = self._store(ms, b, value)
+        assert client_flag & COMPRESSION_LZ4
+        assert client_flag & ENCRYPTION_AES_GCM
+        assert client_flag & CHECKSUM_XXH3
+        self._serve(b, client_flag, data)
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == value
+
+    def test_unchecked_values_pass_through(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_CRC32C)
+        self._serve(b, 0, b"plain")
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == b"plain"
+
+    def test_invalid_settings(self, socket_pair):
+        a, _ = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError, match="Unknown checksum"):
+            ms.set_checksum(COMPRESSION_ZSTD)
+        ms.set_checksum(None)
+
+
+class TestChunking:
+    CHUNK_SIZE = 1024
+
+    def _recv_all(self, b):
+        b.setblocking(False)
+        data = b""
+        try:
+            while chunk := b.recv(1 << 20):
+                data += chunk
+        except BlockingIOError:
+            pass
+        b.setblocking(True)
+        return data
+
+    def _parse_sets(self, data):
+        """Split the ms commands sent on the wire into (key, flags, value),
+        skipping the read of the manifest being replaced."""
+        sets = []
+        while data:
+            header, data = data.split(b"\r\n", 1)
+            if header.startswith(b"mg "):
+                continue
+            _, key, size, *flags = header.split(b" ")
+            size = int(size)
+            sets.append((key, flags, data[:size]))
+            data = data[size + 2 :]
+        return sets
+
+    def _store(self, ms, b, value, chunks, **kwargs):
+        # Chunked writes first read the manifest they replace
+        b.sendall((b"EN\r\n" if chunks else b"") + b"HD\r\n" * (chunks + 1))
+        assert isinstance(ms.meta_set(b"key", value, **kwargs), Success)
+        return self._parse_sets(self._recv_all(b))
+
+    def _serve(self, b, sets, skip=()):
+        (*chunks, (_, manifest_flags, manifest)) = sets
+        client_flag = next(f[1:] for f in manifest_flags if f.startswith(b"F"))
+        response = b"VA %d f%s\r\n%s\r\n" % (len(manifest), client_flag, manifest)
+        for index, (_, _, chunk) in enumerate(chunks):
+            if index not in skip:
+                response += b"VA %d O%d\r\n%s\r\n" % (len(chunk), index, chunk)
+        b.sendall(response + b"MN\r\n")
+
+    def _value(self):
+        return bytes(range(256)) * 10
+
+    def test_roundtrip(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        value = self._value()
+        flags = RequestFlags(client_flag=3, cache_ttl=60)
+        sets = self._store(ms, b, value, 3, request_flags=flags)
+        assert len(sets) == 4
+        *chunks, (manifest_key, manifest_flags, manifest) = sets
+        assert manifest_key == b"key"
+        assert b"F%d" % (3 | CHUNKED) in manifest_flags
+        assert b"T60" in manifest_flags
+        assert len(manifest) == 32
+        assert b"".join(chunk for _, _, chunk in chunks) == value
+        assert len({key for key, _, _ in chunks}) == 3
+        for key, chunk_flags, _ in chunks:
+            assert key.startswith(b"key:")
+            # Written before the manifest, they outlive it
+            assert chunk_flags == [b"T120"]
+
+        self._serve(b, sets)
+        resp = ms.meta_get(b"key", RequestFlags(return_value=True))
+        assert isinstance(resp, Value)
+        assert resp.value == value
+        assert resp.size == len(value)
+        assert resp.flags.client_flag == 3
+        sent = self._recv_all(b)
+        assert sent.startswith(b"mg key f v\r\n")
+        assert sent.count(b"mg key:") == 3
+        assert sent.endswith(b"mn\r\n")
+
+    def test_small_values_not_chunked(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        sets = self._store(ms, b, b"x" * self.CHUNK_SIZE, 0)
+        assert sets == [(b"key", [], b"x" * self.CHUNK_SIZE)]
+
+    def test_missing_chunk_is_a_miss(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        sets = self._store(ms, b, self._value(), 3)
+        self._serve(b, sets, skip={1})
+        assert isinstance(ms.meta_get(b"key", RequestFlags(return_value=True)), Miss)
+        # The connection is still in sync
+        self._recv_all(b)
+        self._serve(b, sets)
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == self._value()
+
+    def test_corrupted_chunk_is_a_miss(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        sets = self._store(ms, b, self._value(), 3)
+        key, flags, chunk = sets[0]
+        sets[0] = (key, flags, b"\xff" + chunk[1:])
+        self._serve(b, sets)
+        assert isinstance(ms.meta_get(b"key", RequestFlags(return_value=True)), Miss)
+
+    def test_failed_chunk_skips_manifest(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        b.sendall(b"EN\r\nHD\r\nNS\r\nHD\r\nMN\r\n")
+        assert isinstance(ms.meta_set(b"key", self._value()), NotStored)
+        sent = self._recv_all(b)
+        sets = self._parse_sets(sent[: sent.index(b"md ")])
+        assert len(sets) == 3
+        assert all(key.startswith(b"key:") for key, _, _ in sets)
+        # The chunks that were stored are deleted
+        assert sent.endswith(b"\r\n" + self._chunk_deletes(sets + [None]))
+
+    def test_manifest_honors_cas(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        b.sendall(b"EN\r\n" + b"HD\r\n" * 3 + b"EX\r\nMN\r\n")
+        resp = ms.meta_set(b"key", self._value(), RequestFlags(cas_token=7))
+        assert isinstance(resp, Conflict)
+        sent = self._recv_all(b)
+        sets = self._parse_sets(sent[: sent.index(b"md ")])
+        assert b"C7" in sets[-1][1]
+        assert all(b"C7" not in flags for _, flags, _ in sets[:-1])
+        # The manifest wasn't stored, its chunks are deleted
+        assert sent.endswith(b"\r\n" + self._chunk_deletes(sets))
+
+    def test_append_rejected(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        for mode in (SET_MODE_APPEND, SET_MODE_PREPEND):
+            with pytest.raises(ValueError, match="appended or prepended"):
+                ms.meta_set(b"key", self._value(), RequestFlags(mode=mode))
+        assert self._recv_all(b) == b""
+        # Values that fit in one item can still be appended
+        b.sendall(b"HD\r\n")
+        assert isinstance(ms.meta_set(b"key", b"x", RequestFlags(mode=SET_MODE_APPEND)), Success)
+
+    def _manifest_response(self, sets):
+        manifest = sets[-1][2]
+        return b"VA %d f%d\r\n%s\r\n" % (len(manifest), CHUNKED, manifest)
+
+    def _chunk_deletes(self, sets):
+        deletes = b"".join(b"md %s q O%d\r\n" % (key, i) for i, (key, _, _) in enumerate(sets[:-1]))
+        return deletes + b"mn\r\n"
+
+    def test_overwrite_deletes_old_chunks(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        old_sets = self._store(ms, b, self._value(), 3)
+        b.sendall(self._manifest_response(old_sets) + b"HD\r\n" * 4 + b"MN\r\n")
+        assert isinstance(ms.meta_set(b"key", self._value()), Success)
+        sent = self._recv_all(b)
+        assert sent.startswith(b"mg key f v\r\n")
+        assert sent.endswith(b"\r\n" + self._chunk_deletes(old_sets))
+        new_sets = self._parse_sets(sent[: sent.index(b"md ")])
+        assert {key for key, _, _ in new_sets[:-1]}.isdisjoint(key for key, _, _ in old_sets)
+
+    def test_failed_overwrite_keeps_old_chunks(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        old_sets = self._store(ms, b, self._value(), 3)
+        b.sendall(self._manifest_response(old_sets) + b"HD\r\n" * 3 + b"EX\r\nMN\r\n")
+        resp = ms.meta_set(b"key", self._value(), RequestFlags(cas_token=7))
+        assert isinstance(resp, Conflict)
+        sent = self._recv_all(b)
+        new_sets = self._parse_sets(sent[: sent.index(b"md ")])
+        # Only the new chunks are deleted
+        assert sent.endswith(b"\r\n" + self._chunk_deletes(new_sets))
+
+    def test_delete_deletes_chunks(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        sets = self._store(ms, b, self._value(), 3)
+        b.sendall(self._manifest_response(sets) + b"HD\r\nMN\r\n")
+        assert isinstance(ms.meta_delete(b"key"), Success)
+        assert self._recv_all(b) == b"mg key f v\r\nmd key\r\n" + self._chunk_deletes(sets)
+
+    def test_delete_unchunked_value(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        b.sendall(b"VA 1 f0\r\nx\r\nHD\r\n")
+        assert isinstance(ms.meta_delete(b"key"), Success)
+        assert self._recv_all(b) == b"mg key f v\r\nmd key\r\n"
+        # The outcome of a quiet delete is unknown, the chunks are left
+        assert isinstance(ms.meta_delete(b"key", RequestFlags(no_reply=True)), Success)
+        assert self._recv_all(b) == b"md key q\r\nmn\r\n"
+
+    def test_with_compression(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        ms.set_compression(COMPRESSION_ZSTD, threshold=100)
+        # Compresses below the chunk size
+        sets = self._store(ms, b, b"compressible " * 1000, 0)
+        assert len(sets) == 1
+        # Chunked after compression
+        value = b"".join(hashlib.sha256(b"%d" % i).digest() for i in range(200))
+        sets = self._store(ms, b, value, 7)
+        assert len(sets) == 8
+        self._serve(b, sets)
+        resp = ms.meta_get(b"key", RequestFlags(return_value=True))
+        assert resp.value == value
+        assert resp.flags.client_flag == 0
+
+    def test_pipelined_writes_and_reads(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        with pytest.raises(ValueError, match="only be stored with meta_set"):
+            ms.send_meta_set(b"key", self._value())
+        sets = self._store(ms, b, self._value(), 3)
+        ms.send_meta_get(b"key", RequestFlags(return_value=True))
+        self._serve(b, sets)
+        with pytest.raises(ValueError, match="only be read with meta_get"):
+            ms.get_response()
+
+    def test_disabled_returns_manifest(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_chunking(self.CHUNK_SIZE)
+        sets = self._store(ms, b, self._value(), 3)
+        ms.set_chunking(None)
+        manifest = sets[-1][2]
+        b.sendall(b"VA 32 f%d\r\n%s\r\n" % (CHUNKED, manifest))
+        resp = ms.meta_get(b"key", RequestFlags(return_value=True, return_client_flag=True))
+        assert resp.value == manifest
+        assert resp.flags.client_flag == CHUNKED
+
+    def test_invalid_settings(self, socket_pair):
+        a, _ = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError, match="chunk_size"):
+            ms.set_chunking(100)
+        ms.set_chunking()
+        ms.set_chunking(None)
+        assert DEFAULT_CHUNK_SIZE == 512 * 1024
+
+
+class TestNearCache:
+    def _recv_nowait(self, b):
+        b.setblocking(False)
+        try:
+            return b.recv(65536)
+        except BlockingIOError:
+            return b""
+        finally:
+            b.setblocking(True)
+
+    def _cached_get(self, ms, b, response=b"VA 5 t-1\r\nvalue\r\n"):
+        b.sendall(response)
+        return ms.meta_get(b"key", near_cache=True)
+
+    def test_serves_hits_from_cache(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024)
+        ms.set_near_cache(cache)
+        assert self._cached_get(ms, b).value == b"value"
+        assert b.recv(1024) == b"mg key v t\r\n"
+        resp = ms.meta_get(b"key", near_cache=True)
+        assert isinstance(resp, Value)
+        assert resp.value == b"value"
+        assert resp.flags.ttl == -1
+        assert self._recv_nowait(b) == b""
+        assert cache.get_stats() == {
+            "hits": 1,
+            "negative_hits": 0,
+            "misses": 1,
+            "entries": 1,
+            "bytes": 8,
+        }
+        assert len(cache) == 1
+
+    def test_opt_in_per_call(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024)
+        ms.set_near_cache(cache)
+        self._cached_get(ms, b)
+        b.recv(1024)
+        b.sendall(b"VA 5\r\nfresh\r\n")
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == b"fresh"
+        assert b.recv(1024) == b"mg key v\r\n"
+        assert cache.get_stats()["hits"] == 0
+
+    def test_flush_all_clears(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024)
+        ms.set_near_cache(cache)
+        self._cached_get(ms, b)
+        b.recv(1024)
+        b.sendall(b"OK\r\n")
+        ms.flush_all()
+        assert b.recv(1024) == b"flush_all\r\n"
+        assert len(cache) == 0
+
+    @pytest.mark.parametrize(
+        "flags,sent",
+        [
+            ({"vivify_on_miss_ttl": 30}, b"mg key v N30\r\n"),
+            ({"recache_ttl": 30}, b"mg key v R30\r\n"),
+        ],
+    )
+    def test_vivify_and_recache_bypass(self, socket_pair, flags, sent):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024, negative_ttl=60)
+        ms.set_near_cache(cache)
+        self._cached_get(ms, b)
+        b.recv(1024)
+        flags = RequestFlags(return_value=True, **flags)
+        b.sendall(b"VA 5 W\r\nfresh\r\n")
+        assert ms.meta_get(b"key", flags, near_cache=True).value == b"fresh"
+        assert b.recv(1024) == sent
+        # Nor are cached misses served, which would skip the lease
+        cache.clear()
+        assert isinstance(self._cached_get(ms, b, b"EN\r\n"), Miss)
+        b.recv(1024)
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_get(b"key", flags), Miss)
+        assert b.recv(1024) == sent
+        assert cache.get_stats()["hits"] == 0
+        assert cache.get_stats()["negative_hits"] == 0
+
+    def test_misses_and_stale_not_cached(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024)
+        ms.set_near_cache(cache)
+        assert isinstance(self._cached_get(ms, b, b"EN\r\n"), Miss)
+        assert self._cached_get(ms, b, b"VA 5 t10 X W\r\nvalue\r\n").value == b"value"
+        assert len(cache) == 0
+
+    @pytest.mark.parametrize(
+        "write",
+        [
+            lambda ms: ms.meta_set(b"key", b"new"),
+            lambda ms: ms.meta_delete(b"key"),
+            lambda ms: ms.invalidate(b"key"),
+            lambda ms: ms.meta_incr(b"key", 1),
+        ],
+    )
+    def test_writes_invalidate(self, socket_pair, write):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024)
+        ms.set_near_cache(cache)
+        self._cached_get(ms, b)
+        b.sendall(b"HD\r\n")
+        write(ms)
+        assert len(cache) == 0
+
+    def test_pipelined_writes_invalidate(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024)
+        ms.set_near_cache(cache)
+        self._cached_get(ms, b)
+        ms.send_meta_set(b"key", b"new")
+        assert len(cache) == 0
+        self._cached_get(ms, b)
+        ms.send_meta_delete(b"key")
+        assert len(cache) == 0
+
+    def test_shared_between_sockets(self, socket_pair):
+        a, b = socket_pair
+        c, d = socket.socketpair()
+        try:
+            ms1 = MemcacheSocket(a)
+            ms2 = MemcacheSocket(c)
+            cache = NearCache(1024)
+            ms1.set_near_cache(cache)
+            ms2.set_near_cache(cache)
+            self._cached_get(ms1, b)
+            assert ms2.meta_get(b"key", near_cache=True).value == b"value"
+            d.sendall(b"HD\r\n")
+            ms2.meta_set(b"key", b"new")
+            assert len(cache) == 0
+        finally:
+            c.close()
+            d.close()
+
+    def test_without_cache_attached(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"VA 5\r\nvalue\r\n")
+        assert ms.meta_get(b"key", RequestFlags(return_value=True), near_cache=True).value == b"value"
+        assert b.recv(1024) == b"mg key v\r\n"
+
+    def test_caches_unpacked_values(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_checksum(CHECKSUM_CRC32C)
+        cache = NearCache(1024)
+        ms.set_near_cache(cache)
+        b.sendall(b"HD\r\n")
+        ms.meta_set(b"key", b"value", RequestFlags(client_flag=CODEC_STR))
+        header, data = b.recv(1024).split(b"\r\n", 1)
+        b.sendall(b"VA %d f%d t-1\r\n%s" % (len(data) - 2, CHECKSUM_CRC32C, data))
+        assert ms.meta_get_object(b"key", near_cache=True).value == "value"
+        assert ms.meta_get_object(b"key", near_cache=True).value == "value"
+        assert cache.get_stats()["hits"] == 1
+
+    def test_cache_management(self):
+        cache = NearCache(1024, max_ttl=5)
+        cache.invalidate(b"key")
+        cache.clear()
+        assert len(cache) == 0
+        with pytest.raises(ValueError, match="positive"):
+            NearCache(0)
+        with pytest.raises(ValueError, match="positive"):
+            NearCache(1024, max_ttl=0)
+        with pytest.raises(ValueError, match="positive"):
+            NearCache(1024, negative_ttl=0)
+
+    def test_negative_caching(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024, negative_ttl=5)
+        ms.set_near_cache(cache)
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_get(b"key"), Miss)
+        assert b.recv(1024) == b"mg key\r\n"
+        assert isinstance(ms.meta_get(b"key", RequestFlags(return_value=True)), Miss)
+        assert isinstance(ms.meta_get(b"key", near_cache=True), Miss)
+        assert self._recv_nowait(b) == b""
+        stats = cache.get_stats()
+        assert (stats["hits"], stats["negative_hits"], stats["misses"]) == (0, 2, 1)
+
+    def test_negative_prefixes(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024, negative_ttl=5, negative_prefixes=[b"flags:", "override"])
+        ms.set_near_cache(cache)
+        b.sendall(b"EN\r\nEN\r\nEN\r\n")
+        for key in [b"flags:1", "override", b"other"]:
+            assert isinstance(ms.meta_get(key), Miss)
+        assert len(cache) == 2
+        b.recv(1024)
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_get(b"other"), Miss)
+        assert b.recv(1024) == b"mg other\r\n"
+        assert isinstance(ms.meta_get(b"flags:1"), Miss)
+        assert self._recv_nowait(b) == b""
+
+    @pytest.mark.parametrize(
+        "write",
+        [
+            lambda ms: ms.meta_set(b"key", b"new"),
+            lambda ms: ms.meta_delete(b"key"),
+        ],
+    )
+    def test_writes_invalidate_misses(self, socket_pair, write):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024, negative_ttl=5)
+        ms.set_near_cache(cache)
+        b.sendall(b"EN\r\n")
+        ms.meta_get(b"key")
+        assert len(cache) == 1
+        b.sendall(b"HD\r\n")
+        write(ms)
+        assert len(cache) == 0
+
+    def test_values_not_cached_without_opt_in(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        cache = NearCache(1024, negative_ttl=5)
+        ms.set_near_cache(cache)
+        b.sendall(b"VA 5\r\nvalue\r\n")
+        assert ms.meta_get(b"key", RequestFlags(return_value=True)).value == b"value"
+        assert b.recv(1024) == b"mg key v\r\n"
+        assert len(cache) == 0
+
+
+class TestSingleFlight:
+    def _wait_for(self, condition):
+        deadline = time.monotonic() + 5
+        while not condition():
+            assert time.monotonic() < deadline, "timed out"
+            time.sleep(0.001)
+
+    def _sockets(self, socket_pair, count):
+        pairs = [socket_pair] + [socket.socketpair() for _ in range(count - 1)]
+        single_flight = SingleFlight()
+        sockets = []
+        for a, _ in pairs:
+            ms = MemcacheSocket(a)
+            ms.set_single_flight(single_flight)
+            sockets.append(ms)
+        return single_flight, sockets, [b for _, b in pairs]
+
+    def _get_concurrently(self, single_flight, sockets, flags, call=None):
+        call = call or (lambda ms, flags: ms.meta_get(b"key", flags))
+        results = [None] * len(sockets)
+
+        def run(index):
+            try:
+                results[index] = call(sockets[index], flags[index])
+            except Exception as e:
+                results[index] = e
+
+        leader = threading.Thread(target=run, args=(0,))
+        leader.start()
+        self._wait_for(lambda: len(single_flight) == 1)
+        followers = [threading.Thread(target=run, args=(i,)) for i in range(1, len(sockets))]
+        for thread in followers:
+            thread.start()
+        return leader, followers, results
+
+    def test_coalesces_identical_gets(self, socket_pair):
+        single_flight, sockets, servers = self._sockets(socket_pair, 3)
+        flags = [RequestFlags(return_value=True)] * 3
+        leader, followers, results = self._get_concurrently(single_flight, sockets, flags)
+        self._wait_for(lambda: single_flight.get_stats()["coalesced"] == 2)
+        assert servers[0].recv(1024) == b"mg key v\r\n"
+        servers[0].sendall(b"VA 5\r\nvalue\r\n")
+        for thread in [leader, *followers]:
+            thread.join()
+        assert results[0].value == b"value"
+        assert results[1] is results[0]
+        assert results[2] is results[0]
+        assert single_flight.get_stats() == {"requests": 1, "coalesced": 2}
+        assert len(single_flight) == 0
+        for b in servers[1:]:
+            b.setblocking(False)
+            with pytest.raises(BlockingIOError):
+                b.recv(1024)
+
+    def test_different_flags_not_coalesced(self, socket_pair):
+        single_flight, sockets, servers = self._sockets(socket_pair, 2)
+        ms1, ms2 = sockets
+        servers[1].sendall(b"HD\r\n")
+        flags = [RequestFlags(return_value=True), RequestFlags(return_ttl=True)]
+        leader, followers, results = self._get_concurrently(single_flight, sockets, flags)
+        followers[0].join()
+        assert isinstance(results[1], Success)
+        servers[0].sendall(b"VA 5\r\nvalue\r\n")
+        leader.join()
+        assert results[0].value == b"value"
+        assert single_flight.get_stats() == {"requests": 2, "coalesced": 0}
+
+    def test_errors_are_shared(self, socket_pair):
+        single_flight, sockets, servers = self._sockets(socket_pair, 2)
+        flags = [None] * 2
+        leader, followers, results = self._get_concurrently(single_flight, sockets, flags)
+        self._wait_for(lambda: single_flight.get_stats()["coalesced"] == 1)
+        servers[0].sendall(b"SERVER_ERROR out of memory\r\n")
+        for thread in [leader, *followers]:
+            thread.join()
+        assert isinstance(results[0], ConnectionError)
+        assert isinstance(results[1], ConnectionError)
+
+    def test_object_gets_share_the_request(self, socket_pair):
+        single_flight, sockets, servers = self._sockets(socket_pair, 2)
+        leader, followers, results = self._get_concurrently(
+            single_flight,
+            sockets,
+            [None] * 2,
+            call=lambda ms, flags: ms.meta_get_object(b"key"),
+        )
+        self._wait_for(lambda: single_flight.get_stats()["coalesced"] == 1)
+        servers[0].sendall(b"VA 2 f%d\r\n42\r\n" % CODEC_INT)
+        for thread in [leader, *followers]:
+            thread.join()
+        assert results[0].value == 42
+        assert results[1].value == 42
+
+    def test_sequential_gets_not_coalesced(self, socket_pair):
+        single_flight, sockets, servers = self._sockets(socket_pair, 1)
+        (ms,) = sockets
+        (b,) = servers
+        b.sendall(b"EN\r\nEN\r\n")
+        assert isinstance(ms.meta_get(b"key"), Miss)
+        assert isinstance(ms.meta_get(b"key"), Miss)
+        assert single_flight.get_stats() == {"requests": 2, "coalesced": 0}
+        ms.set_single_flight(None)
+
+
+class TestNamespace:
+    def _versioned(self, ms, b, generation=b"17", **kwargs):
+        b.sendall(b"VA %d\r\n%s\r\n" % (len(generation), generation))
+        ms.set_namespace(b"app:", versioned=True, **kwargs)
+        assert b.recv(1024).startswith(b"ma app:generation v N0 J")
+
+    def test_default_refresh_interval(self):
+        assert DEFAULT_NAMESPACE_REFRESH_INTERVAL == 5
+
+    def test_prefix_applied(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_namespace("app:")
+        b.sendall(b"HD\r\n")
+        ms.meta_set(b"key", b"value")
+        assert b.recv(1024) == b"ms app:key 5\r\nvalue\r\n"
+        assert ms.get_namespace_generation() is None
+        ms.set_namespace(None)
+        b.sendall(b"EN\r\n")
+        ms.meta_get(b"key")
+        assert b.recv(1024) == b"mg key\r\n"
+
+    def test_prefix_hashed_with_key(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_namespace(b"app:")
+        key = b"k" * 185
+        b.sendall(b"EN\r\n")
+        ms.meta_get(key)
+        assert b.recv(1024) == build_cmd(b"mg", b"app:" + key)
+        digest = hashlib.blake2b(b"app:" + key, digest_size=18).digest()
+        assert base64.b64encode(digest) in build_cmd(b"mg", b"app:" + key)
+
+    def test_prefix_encoded_with_binary_key(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_namespace(b"app:")
+        b.sendall(b"EN\r\n")
+        ms.meta_get(b"\x00\x01")
+        assert b.recv(1024) == b"mg %s b\r\n" % base64.b64encode(b"app:\x00\x01")
+
+    def test_pipelined_commands_namespaced(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_namespace(b"app:")
+        ms.send_meta_get(b"key")
+        ms.send_meta_delete(b"other")
+        assert b.recv(1024) == b"mg app:key\r\nmd app:other\r\n"
+
+    def test_empty_namespace_rejected(self, socket_pair):
+        a, _ = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError):
+            ms.set_namespace(b"")
+
+    def test_versioned(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        self._versioned(ms, b)
+        assert ms.get_namespace_generation() == 17
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_get(b"key"), Miss)
+        assert b.recv(1024) == b"mg app:17:key\r\n"
+
+    def test_bump(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        self._versioned(ms, b)
+        b.sendall(b"VA 2\r\n18\r\n")
+        assert ms.bump_namespace() == 18
+        assert b.recv(1024).startswith(b"ma app:generation v N0 J")
+        assert ms.get_namespace_generation() == 18
+        b.sendall(b"HD\r\n")
+        ms.meta_delete(b"key")
+        assert b.recv(1024) == b"md app:18:key\r\n"
+
+    def test_bump_needs_versioned_namespace(self, socket_pair):
+        a, _ = socket_pair
+        ms = MemcacheSocket(a)
+        with pytest.raises(ValueError):
+            ms.bump_namespace()
+        ms.set_namespace(b"app:")
+        with pytest.raises(ValueError):
+            ms.bump_namespace()
+
+    def test_reloaded_by_blocking_commands(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        self._versioned(ms, b, refresh_interval=0)
+        b.sendall(b"VA 2\r\n20\r\nEN\r\n")
+        ms.meta_get(b"key")
+        sent = b""
+        while not sent.endswith(b"mg app:20:key\r\n"):
+            sent += b.recv(1024)
+        assert sent.startswith(b"ma app:generation v N0 J")
+        assert ms.get_namespace_generation() == 20
+
+    def test_not_reloaded_by_pipelined_commands(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        self._versioned(ms, b, refresh_interval=0)
+        ms.send_meta_get(b"key")
+        assert b.recv(1024) == b"mg app:17:key\r\n"
+
+    def test_failed_load_clears_namespace(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"NS\r\n")
+        with pytest.raises(Exception):
+            ms.set_namespace(b"app:", versioned=True)
+        b.recv(1024)
+        assert ms.get_namespace_generation() is None
+        ms.send_meta_get(b"key")
+        assert b.recv(1024) == b"mg key\r\n"
+
+    def test_near_cache_keyed_by_generation(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        self._versioned(ms, b)
+        ms.set_near_cache(NearCache(1024))
+        b.sendall(b"VA 3 t-1\r\nold\r\n")
+        assert ms.meta_get(b"key", near_cache=True).value == b"old"
+        b.recv(1024)
+        b.sendall(b"VA 2\r\n18\r\n")
+        ms.bump_namespace()
+        b.recv(1024)
+        b.sendall(b"EN\r\n")
+        assert isinstance(ms.meta_get(b"key", near_cache=True), Miss)
+        assert b.recv(1024) == b"mg app:18:key v t\r\n"
+
+
+class TestKeyPolicy:
+    LONG_KEY = b"user:" + b"x" * 300
+
+    def test_default_unchanged(self):
+        digest = hashlib.blake2b(self.LONG_KEY, digest_size=18).digest()
+        expected = b"mg %s b\r\n" % base64.b64encode(digest)
+        assert build_cmd(b"mg", self.LONG_KEY) == expected
+        assert build_cmd(b"mg", self.LONG_KEY, key_policy=KeyPolicy(algorithm=KEY_HASH_BLAKE2B)) == expected
+
+    def test_md5_hex_digest(self):
+        # e.g. a client keying memcached by md5(key).hexdigest() for long keys
+        policy = KeyPolicy(hash_threshold=251, algorithm=KEY_HASH_MD5, hex_digest=True)
+        wire_key = hashlib.md5(self.LONG_KEY).hexdigest().encode()
+        assert build_cmd(b"mg", self.LONG_KEY, key_policy=policy) == b"mg %s\r\n" % wire_key
+        assert build_cmd(b"mg", b"k" * 250, key_policy=policy) == b"mg %s\r\n" % (b"k" * 250)
+
+    def test_sha1_with_tag(self):
+        policy = KeyPolicy(algorithm=KEY_HASH_SHA1, hex_digest=True, tag=b"sha1:")
+        wire_key = b"sha1:" + hashlib.sha1(self.LONG_KEY).hexdigest().encode()
+        assert build_meta_get(self.LONG_KEY, key_policy=policy) == b"mg %s\r\n" % wire_key
+
+    def test_blake2b_digest_size(self):
+        policy = KeyPolicy(hash_threshold=100, digest_size=32)
+        digest = hashlib.blake2b(b"k" * 100, digest_size=32).digest()
+        assert build_cmd(b"mg", b"k" * 100, key_policy=policy) == b"mg %s b\r\n" % base64.b64encode(digest)
+
+    def test_xxh3(self):
+        policy = KeyPolicy(hash_threshold=1, algorithm=KEY_HASH_XXH3, hex_digest=True)
+        # xxh3_64 of "abc"
+        assert build_cmd(b"mg", b"abc", key_policy=policy) == b"mg 78af5f94892f3950\r\n"
+        policy = KeyPolicy(algorithm=KEY_HASH_XXH3, digest_size=16)
+        assert b" b\r\n" in build_cmd(b"mg", self.LONG_KEY, key_policy=policy)
+
+    def test_strict(self):
+        policy = KeyPolicy(strict=True)
+        assert build_cmd(b"mg", b"short", key_policy=policy) == b"mg short\r\n"
+        with pytest.raises(ValueError, match="too long"):
+            build_cmd(b"mg", self.LONG_KEY, key_policy=policy)
+
+    @pytest.mark.parametrize(
+        "kwargs",
+        [
+            {"hash_threshold": 0},
+            {"hash_threshold": 252},
+            {"algorithm": 99},
+            {"algorithm": KEY_HASH_MD5, "digest_size": 17},
+            {"algorithm": KEY_HASH_XXH3, "digest_size": 4},
+            {"digest_size": 64, "tag": b"t" * 140},
+        ],
+    )
+    def test_invalid(self, kwargs):
+        with pytest.raises(ValueError):
+            KeyPolicy(**kwargs)
+
+    def test_socket(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_key_policy(KeyPolicy(hash_threshold=251, algorithm=KEY_HASH_MD5, hex_digest=True))
+        b.sendall(b"EN\r\n")
+        ms.meta_get(self.LONG_KEY)
+        assert b.recv(1024) == b"mg %s\r\n" % hashlib.md5(self.LONG_KEY).hexdigest().encode()
+        ms.set_key_policy(KeyPolicy(strict=True))
+        with pytest.raises(ValueError):
+            ms.send_meta_get(self.LONG_KEY)
+        ms.set_key_policy(None)
+        ms.send_meta_get(self.LONG_KEY)
+        assert b.recv(1024) == build_cmd(b"mg", self.LONG_KEY)
+
+    def test_namespace_hashed_with_key(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.set_key_policy(KeyPolicy(hash_threshold=10, algorithm=KEY_HASH_MD5, hex_digest=True))
+        ms.set_namespace(b"app:")
+        ms.send_meta_get(b"long key!")
+        assert b.recv(1024) == b"mg %s\r\n" % hashlib.md5(b"app:long key!").hexdigest().encode()
+
+
+class TestEncodeKey:
+    def test_plain_key(self):
+        assert encode_key("user:42") == (b"user:42", False)
+        assert decode_wire_key(b"user:42", False) == b"user:42"
+
+    def test_binary_key_roundtrip(self):
+        wire_key, is_binary = encode_key(b"\x00\x01key")
+        assert (wire_key, is_binary) == (base64.b64encode(b"\x00\x01key"), True)
+        assert decode_wire_key(wire_key, is_binary) == b"\x00\x01key"
+
+    def test_hashed_key(self):
+        key = b"k" * 200
+        digest = hashlib.blake2b(key, digest_size=18).digest()
+        wire_key, is_binary = encode_key(key)
+        assert (wire_key, is_binary) == (base64.b64encode(digest), True)
+        assert decode_wire_key(wire_key, is_binary) == digest
+        assert build_cmd(b"mg", key) == b"mg %s b\r\n" % wire_key
+
+    def test_key_policy(self):
+        policy = KeyPolicy(hash_threshold=10, algorithm=KEY_HASH_MD5, hex_digest=True)
+        wire_key = hashlib.md5(b"a long enough key").hexdigest().encode()
+        assert encode_key(b"a long enough key", key_policy=policy) == (wire_key, False)
+        with pytest.raises(ValueError):
+            encode_key(b"a long enough key", key_policy=KeyPolicy(hash_threshold=10, strict=True))
+
+    def test_invalid(self):
+        with pytest.raises(ValueError):
+            encode_key(b"")
+        with pytest.raises(ValueError):
+            decode_wire_key(b"not base64!", True)
+
+
+class TestKeyRecorder:
+    def test_records_hashed_keys(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        recorder = KeyRecorder()
+        ms.set_key_recorder(recorder)
+        long_key = b"k" * 200
+        ms.send_meta_get(long_key)
+        ms.send_meta_get(b"short")
+        b.recv(1024)
+        assert len(recorder) == 1
+        wire_key, _ = encode_key(long_key)
+        assert recorder.lookup(wire_key) == long_key
+        assert recorder.lookup(wire_key.decode()) == long_key
+        assert recorder.lookup(b"short") is None
+        recorder.clear()
+        assert len(recorder) == 0
+
+    def test_records_namespaced_key(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        recorder = KeyRecorder()
+        ms.set_key_recorder(recorder)
+        ms.set_key_policy(KeyPolicy(hash_threshold=10, algorithm=KEY_HASH_MD5, hex_digest=True))
+        ms.set_namespace(b"app:")
+        ms.send_meta_delete(b"a long key")
+        sent = b.recv(1024)
+        assert recorder.lookup(sent.split()[1]) == b"app:a long key"
+
+    def test_bounded(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        recorder = KeyRecorder(max_entries=2)
+        ms.set_key_recorder(recorder)
+        keys = [bytes([c]) * 200 for c in b"abc"]
+        for key in keys:
+            ms.send_meta_get(key)
+        b.recv(4096)
+        assert len(recorder) == 2
+        assert recorder.lookup(encode_key(keys[0])[0]) is None
+        assert recorder.lookup(encode_key(keys[2])[0]) == keys[2]
+
+    def test_detach(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        recorder = KeyRecorder()
+        ms.set_key_recorder(recorder)
+        ms.set_key_recorder(None)
+        ms.send_meta_get(b"k" * 200)
+        b.recv(1024)
+        assert len(recorder) == 0
+
+    def test_defaults(self):
+        assert DEFAULT_KEY_RECORDER_SIZE == 10000
+        with pytest.raises(ValueError):
+            KeyRecorder(0)
+
+
+class TestRoutingKey:
+    def test_hash_tag(self):
+        assert routing_key(b"user:{42}:profile") == b"42"
+        assert routing_key("user:{42}:settings") == b"42"
+        assert routing_key(b"user:42") == b"user:42"
+        assert routing_key(b"user:{}:42") == b"user:{}:42"
+        assert routing_key(b"user:{42") == b"user:{42"
+
+    def test_related_keys_colocated(self):
+        servers = ["a", "b", "c", "d", "e"]
+
+        def select(key):
+            return servers[int.from_bytes(hashlib.md5(routing_key(key)).digest()[:4], "big") % len(servers)]
+
+        related = [b"user:{42}:profile", b"user:{42}:settings", b"session:{42}"]
+        assert len({select(key) for key in related}) == 1
+        assert len({select(b"user:%d" % i) for i in range(50)}) > 1
+
+    def test_full_key_sent(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        ms.send_meta_get(b"user:{42}:profile")
+        assert b.recv(1024) == b"mg user:{42}:profile\r\n"
+
+    def test_hashed_key_keeps_tag_routing(self):
+        key = b"report:{42}:" + b"x" * 300
+        assert routing_key(key) == b"42"
+        wire_key, is_binary = encode_key(key)
+        assert is_binary and wire_key != key
+
+
+class TestReplicaSet:
+    def test_write_quorum_validation(self, replicas):
+        sockets, _ = replicas
+        with pytest.raises(ValueError):
+            ReplicaSet([])
+        with pytest.raises(ValueError):
+            ReplicaSet(sockets, write_quorum=4)
+        with pytest.raises(ValueError):
+            ReplicaSet(sockets, write_quorum=2, quiet_replicas=True)
+        assert len(ReplicaSet(sockets)) == 3
+
+    def test_set_written_to_all_replicas(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets)
+        for server in servers:
+            server.sendall(b"HD\r\n")
+        assert isinstance(rs.meta_set(b"key", b"value"), Success)
+        for server in servers:
+            assert server.recv(1024) == b"ms key 5\r\nvalue\r\n"
+        assert rs.get_stats()["write_failures"] == 0
+
+    def test_chunked_values(self, replicas):
+        sockets, servers = replicas
+        for ms in sockets:
+            ms.set_chunking(1024)
+        rs = ReplicaSet(sockets)
+        # Each write reads the manifest it replaces, then stores 3 chunks
+        for server in servers:
+            server.sendall(b"EN\r\n" + b"HD\r\n" * 4)
+        assert isinstance(rs.meta_set(b"key", b"x" * 3000), Success)
+        for server in servers:
+            assert server.recv(65536).count(b"ms key") == 4
+        # Deletes read the manifest to delete the chunks too
+        for server in servers:
+            server.sendall(b"EN\r\nHD\r\n")
+        assert isinstance(rs.meta_delete(b"key"), Success)
+        for server in servers:
+            assert server.recv(1024) == b"mg key f v\r\nmd key\r\n"
+        assert rs.get_stats()["write_failures"] == 0
+
+    def test_set_quorum_met_despite_failure(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets)
+        servers[0].sendall(b"HD\r\n")
+        servers[1].sendall(b"HD\r\n")
+        servers[2].close()
+        assert isinstance(rs.meta_set(b"key", b"value"), Success)
+        assert rs.get_stats()["write_failures"] == 1
+
+    def test_set_quorum_not_met(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets, write_quorum=3)
+        servers[0].sendall(b"HD\r\n")
+        servers[1].sendall(b"HD\r\n")
+        servers[2].close()
+        with pytest.raises(ReplicationError, match="2 replicas, 3 needed") as exc_info:
+            rs.meta_set(b"key", b"value")
+        assert isinstance(exc_info.value.__cause__, ConnectionError)
+        assert issubclass(ReplicationError, ConnectionError)
+
+    def test_set_quorum_not_met_by_rejection(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets[:2], write_quorum=2)
+        servers[0].sendall(b"HD\r\n")
+        servers[1].sendall(b"NS\r\n")
+        assert isinstance(rs.meta_set(b"key", b"value"), NotStored)
+        assert rs.get_stats()["write_failures"] == 1
+
+    def test_set_not_stored_returned(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets)
+        for server in servers:
+            server.sendall(b"NS\r\n")
+        flags = RequestFlags(mode=SET_MODE_ADD)
+        assert isinstance(rs.meta_set(b"key", b"value", flags), NotStored)
+
+    def test_no_reply_rejected(self, replicas):
+        sockets, _ = replicas
+        with pytest.raises(ValueError, match="no_reply"):
+            ReplicaSet(sockets).meta_set(b"key", b"value", RequestFlags(no_reply=True))
+
+    def test_quiet_replicas(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets, quiet_replicas=True)
+        servers[0].sendall(b"HD\r\n")
+        assert isinstance(rs.meta_set(b"key", b"value"), Success)
+        assert servers[0].recv(1024) == b"ms key 5\r\nvalue\r\n"
+        for server in servers[1:]:
+            assert server.recv(1024) == b"ms key 5 q\r\nvalue\r\nmn\r\n"
+        # A quiet failure is discarded with the noop on the next command
+        servers[1].sendall(b"NS\r\nMN\r\nEN\r\n")
+        assert isinstance(sockets[1].meta_get(b"key"), Miss)
+
+    def test_delete_miss_acknowledges(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets, write_quorum=3)
+        servers[0].sendall(b"HD\r\n")
+        servers[1].sendall(b"NF\r\n")
+        servers[2].sendall(b"HD\r\n")
+        assert isinstance(rs.meta_delete(b"key"), Success)
+        for server in servers:
+            assert server.recv(1024) == b"md key\r\n"
+
+    def test_get_from_primary(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets)
+        servers[0].sendall(b"VA 5\r\nvalue\r\n")
+        assert rs.meta_get(b"key").value == b"value"
+        assert rs.get_stats()["read_fallbacks"] == 0
+        servers[1].setblocking(False)
+        with pytest.raises(BlockingIOError):
+            servers[1].recv(1024)
+
+    def test_get_falls_back(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets)
+        servers[0].close()
+        servers[1].sendall(b"EN\r\n")
+        servers[2].sendall(b"VA 5\r\nvalue\r\n")
+        assert rs.meta_get(b"key").value == b"value"
+        assert rs.get_stats()["read_fallbacks"] == 1
+
+    def test_get_all_miss(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets)
+        servers[0].close()
+        servers[1].sendall(b"EN\r\n")
+        servers[2].sendall(b"EN\r\n")
+        assert isinstance(rs.meta_get(b"key"), Miss)
+
+    def test_get_all_fail(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets)
+        for server in servers:
+            server.close()
+        with pytest.raises(ConnectionError):
+            rs.meta_get(b"key")
+
+    def test_read_repair(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets, read_repair=True)
+        servers[0].sendall(b"EN\r\n")
+        servers[1].sendall(b"VA 5 f7 c42 t60\r\nvalue\r\n")
+        servers[0].sendall(b"HD\r\n")
+        assert rs.meta_get(b"key").value == b"value"
+        assert servers[0].recv(1024) == b"mg key f c v t\r\nms key 5 T60 F7 E42 ME\r\nvalue\r\n"
+        assert servers[1].recv(1024) == b"mg key f c v t\r\n"
+        assert rs.get_stats() == {"write_failures": 0, "read_fallbacks": 1, "read_repairs": 1}
+
+    def test_read_repair_skips_expiring(self, replicas):
+        sockets, servers = replicas
+        rs = ReplicaSet(sockets, read_repair=True)
+        servers[0].sendall(b"EN\r\n")
+        servers[1].sendall(b"VA 5 t0\r\nvalue\r\n")
+        assert rs.meta_get(b"key").value == b"value"
+        assert servers[0].recv(1024) == b"mg key f c v t\r\n"
+        assert rs.get_stats()["read_repairs"] == 0
+
+
+class TestMigrationClient:
+    def test_writes_to_both(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new)
+        old_server.sendall(b"HD\r\n")
+        new_server.sendall(b"NS\r\n")
+        assert isinstance(client.meta_set(b"key", b"value"), Success)
+        assert old_server.recv(1024) == b"ms key 5\r\nvalue\r\n"
+        assert new_server.recv(1024) == b"ms key 5\r\nvalue\r\n"
+        old_server.sendall(b"NF\r\n")
+        new_server.sendall(b"HD\r\n")
+        assert isinstance(client.meta_delete(b"key"), Miss)
+        assert old_server.recv(1024) == b"md key\r\n"
+        assert new_server.recv(1024) == b"md key\r\n"
+
+    def test_secondary_write_failure_ignored(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new)
+        old_server.sendall(b"HD\r\n")
+        new_server.close()
+        assert isinstance(client.meta_set(b"key", b"value"), Success)
+        assert client.get_stats()["secondary_write_errors"] == 1
+
+    def test_primary_write_failure_raised(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new)
+        old_server.close()
+        with pytest.raises(ConnectionError):
+            client.meta_set(b"key", b"value")
+        new_server.setblocking(False)
+        with pytest.raises(BlockingIOError):
+            new_server.recv(1024)
+
+    def test_no_reply_writes(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new)
+        assert isinstance(client.meta_set(b"key", b"value", RequestFlags(no_reply=True)), Success)
+        assert old_server.recv(1024) == b"ms key 5 q\r\nvalue\r\nmn\r\n"
+        assert new_server.recv(1024) == b"ms key 5 q\r\nvalue\r\nmn\r\n"
+
+    def test_reads_from_primary(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new)
+        old_server.sendall(b"VA 5\r\nvalue\r\n")
+        assert client.meta_get(b"key").value == b"value"
+        new_server.setblocking(False)
+        with pytest.raises(BlockingIOError):
+            new_server.recv(1024)
+        assert client.get_stats()["reads"] == 1
+        assert client.get_stats()["shadow_reads"] == 0
+
+    def test_shadow_reads(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new, shadow_reads=True)
+        old_server.sendall(b"VA 5\r\nvalue\r\n" * 3 + b"EN\r\n")
+        new_server.sendall(b"VA 5\r\nvalue\r\nVA 5\r\nother\r\nEN\r\nVA 5\r\nstale\r\n")
+        for _ in range(3):
+            assert client.meta_get(b"key").value == b"value"
+        assert isinstance(client.meta_get(b"key"), Miss)
+        assert new_server.recv(1024) == b"mg key\r\n" * 4
+        assert client.get_stats()["shadow_reads"] == 3
+        client.flush_shadow_read()
+        stats = client.get_stats()
+        assert stats["shadow_reads"] == 4
+        assert stats["shadow_misses"] == 1
+        assert stats["mismatches"] == 2
+
+    def test_shadow_error_ignored(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new)
+        client.set_shadow_reads(True)
+        new_server.close()
+        old_server.sendall(b"VA 5\r\nvalue\r\n")
+        assert client.meta_get(b"key").value == b"value"
+        assert client.get_stats()["shadow_errors"] == 1
+
+    def test_shadow_read_deferred(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new, shadow_reads=True)
+        old_server.sendall(b"VA 5\r\nvalue\r\nHD\r\n")
+        # Returns without waiting for the secondary
+        assert client.meta_get(b"key").value == b"value"
+        assert new_server.recv(1024) == b"mg key\r\n"
+        new_server.sendall(b"VA 5\r\nvalue\r\nHD\r\n")
+        assert isinstance(client.meta_set(b"key", b"value"), Success)
+        stats = client.get_stats()
+        assert stats["shadow_reads"] == 1
+        assert stats["secondary_write_errors"] == 0
+        new_server.setblocking(False)
+        assert new_server.recv(1024) == b"ms key 5\r\nvalue\r\n"
+
+    def test_shadow_timeout_closes_secondary(self, replicas, socket_pair):
+        (old, _, _), (old_server, _, _) = replicas
+        a, b = socket_pair
+        a.settimeout(0.05)
+        client = MigrationClient(old, MemcacheSocket(a), shadow_reads=True)
+        old_server.sendall(b"VA 5\r\nvalue\r\nHD\r\n")
+        assert client.meta_get(b"key").value == b"value"
+        # Closed, so a late response can't be read as the write's
+        assert isinstance(client.meta_set(b"key", b"value"), Success)
+        stats = client.get_stats()
+        assert stats["shadow_errors"] == 1
+        assert stats["secondary_write_errors"] == 1
+        assert a.fileno() == -1
+
+    def test_shadow_decode_error_keeps_secondary(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        new.set_checksum(CHECKSUM_CRC32C)
+        client = MigrationClient(old, new, shadow_reads=True)
+        old_server.sendall(b"VA 5\r\nvalue\r\nHD\r\n")
+        new_server.sendall(b"VA 5 f%d\r\nvalue\r\nHD\r\n" % CHECKSUM_CRC32C)
+        assert client.meta_get(b"key").value == b"value"
+        # The bad value was read whole, the secondary is still in sync
+        assert isinstance(client.meta_set(b"key", b"value"), Success)
+        stats = client.get_stats()
+        assert stats["shadow_errors"] == 1
+        assert stats["secondary_write_errors"] == 0
+
+    def test_chunked_values(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        for ms in (old, new):
+            ms.set_chunking(1024)
+        client = MigrationClient(old, new)
+        # Each write reads the manifest it replaces, then stores 3 chunks
+        for server in (old_server, new_server):
+            server.sendall(b"EN\r\n" + b"HD\r\n" * 4)
+        assert isinstance(client.meta_set(b"key", b"x" * 3000), Success)
+        for server in (old_server, new_server):
+            assert server.recv(65536).count(b"ms key") == 4
+        # Deletes read the manifest to delete the chunks too
+        for server in (old_server, new_server):
+            server.sendall(b"EN\r\nHD\r\n")
+        assert isinstance(client.meta_delete(b"key"), Success)
+        for server in (old_server, new_server):
+            assert server.recv(1024) == b"mg key f v\r\nmd key\r\n"
+        assert client.get_stats()["secondary_write_errors"] == 0
+
+    def test_flip(self, replicas):
+        (old, new, _), (old_server, new_server, _) = replicas
+        client = MigrationClient(old, new)
+        assert not client.flipped and client.primary is old
+        assert client.flip()
+        assert client.flipped and client.primary is new and client.secondary is old
+        new_server.sendall(b"VA 5\r\nvalue\r\n")
+        assert client.meta_get(b"key").value == b"value"
+        assert new_server.recv(1024) == b"mg key\r\n"
+        assert not client.flip()
+
+
+def cluster_config_response(version, nodes):
+    data = b"%d\n%s\n" % (version, b" ".join(nodes))
+    return b"CONFIG cluster 0 %d\r\n%s\r\nEND\r\n" % (len(data), data)
+
+
+class FakeConfigEndpoint:
+    """A configuration endpoint answering config get cluster with its
+    current version and nodes."""
+
+    def __init__(self):
+        self.version = 1
+        self.nodes = [b"node1.cache.amazonaws.com|10.0.0.1|11211"]
+        self.requests = 0
+        self.server = socket.create_server(("127.0.0.1", 0))
+        self.thread = threading.Thread(target=self._serve, daemon=True)
+        self.thread.start()
+
+    def _serve(self):
+        conn, _ = self.server.accept()
+        with conn:
+            while conn.recv(1024) == b"config get cluster\r\n":
+                self.requests += 1
+                conn.sendall(cluster_config_response(self.version, self.nodes))
+
+    def connect(self):
+        return MemcacheSocket(socket.create_connection(self.server.getsockname()))
+
+    def close(self):
+        self.server.close()
+
+
+class TestConfigGetCluster:
+    def test_config_get_cluster(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(
+            cluster_config_response(
+                12,
+                [
+                    b"node1.cache.amazonaws.com|10.82.235.120|11211",
+                    b"node2.cache.amazonaws.com||11211",
+                ],
+            )
+        )
+        config = ms.config_get_cluster()
+        assert b.recv(1024) == b"config get cluster\r\n"
+        assert config.version == 12
+        assert config.nodes == [
+            ClusterNode("node1.cache.amazonaws.com", "10.82.235.120", 11211),
+            ClusterNode("node2.cache.amazonaws.com", None, 11211),
+        ]
+        assert config.nodes[0].address == "10.82.235.120:11211"
+        assert config.nodes[1].address == "node2.cache.amazonaws.com:11211"
+        assert config == ClusterConfig(12, config.nodes)
+        assert "version=12" in repr(config)
+
+    def test_not_a_config_endpoint(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(b"ERROR\r\n")
+        with pytest.raises(ConnectionError, match="Unexpected response: ERROR"):
+            ms.config_get_cluster()
+
+    def test_invalid_config(self, socket_pair):
+        a, b = socket_pair
+        ms = MemcacheSocket(a)
+        b.sendall(cluster_config_response(1, [b"node1|11211"]))
+        with pytest.raises(ConnectionError, match="Invalid cluster node"):
+            ms.config_get_cluster()
+
+
+class TestClusterDiscovery:
+    def test_default_poll_interval(self):
+        assert DEFAULT_DISCOVERY_POLL_INTERVAL == 60
+
+    def test_poll_on_version_change(self, socket_pair):
+        a, b = socket_pair
+        configs = []
+        discovery = ClusterDiscovery(MemcacheSocket(a), configs.append)
+        assert discovery.config is None
+        b.sendall(cluster_config_response(1, [b"node1||11211"]))
+        assert discovery.poll()
+        b.sendall(cluster_config_response(1, [b"node1||11211"]))
+        assert not discovery.poll()
+        b.sendall(cluster_config_response(2, [b"node1||11211", b"node2||11211"]))
+        assert discovery.poll()
+        assert [config.version for config in configs] == [1, 2]
+        assert [node.host for node in configs[1].nodes] == ["node1", "node2"]
+        assert discovery.config == configs[1]
+
+    def test_callback_failure_retried(self, socket_pair):
+        a, b = socket_pair
+        calls = []
+
+        def on_change(config):
+            calls.append(config)
+            if len(calls) == 1:
+                raise RuntimeError("ring update failed")
+
+        discovery = ClusterDiscovery(MemcacheSocket(a), on_change)
+        b.sendall(cluster_config_response(1, [b"node1||11211"]))
+        with pytest.raises(RuntimeError):
+            discovery.poll()
+        assert discovery.config is None
+        b.sendall(cluster_config_response(1, [b"node1||11211"]))
+        assert discovery.poll()
+        assert len(calls) == 2
+
+    def test_background_polling(self):
+        endpoint = FakeConfigEndpoint()
+        changed = threading.Event()
+        configs = []
+
+        def on_change(config):
+            configs.append(config)
+            changed.set()
+
+        discovery = ClusterDiscovery(endpoint.connect(), on_change, poll_interval=0.01)
+        try:
+            discovery.start()
+            with pytest.raises(RuntimeError, match="already started"):
+                discovery.start()
+            assert changed.wait(5)
+            changed.clear()
+            endpoint.nodes.append(b"node2.cache.amazonaws.com|10.0.0.2|11211")
+            endpoint.version = 2
+            assert changed.wait(5)
+        finally:
+            discovery.stop()
+            endpoint.close()
+        assert [config.version for config in configs] == [1, 2]
+        assert len(configs[1].nodes) == 2
+        requests = endpoint.requests
+        assert requests >= 2
+        time.sleep(0.05)
+        assert endpoint.requests == requests
+
+    def test_collected_while_polling(self):
+        endpoint = FakeConfigEndpoint()
+        polled = threading.Event()
+        discovery = ClusterDiscovery(
+            endpoint.connect(), lambda config: polled.set(), poll_interval=0.01
+        )
+        try:
+            discovery.start()
+            assert polled.wait(5)
+            del discovery
+            gc.collect()
+            time.sleep(0.05)
+            requests = endpoint.requests
+            time.sleep(0.05)
+            assert endpoint.requests == requests
+        finally:
+            endpoint.close()
+
+    def test_invalid_poll_interval(self, socket_pair):
+        a, _ = socket_pair
+        with pytest.raises(ValueError):
+            ClusterDiscovery(MemcacheSocket(a), print, poll_interval=-1)
+        with pytest.raises(ValueError, match="positive"):
+            ClusterDiscovery(MemcacheSocket(a), print, poll_interval=0)